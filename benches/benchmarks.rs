@@ -120,6 +120,47 @@ pub fn bench_shares(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_public);
+/* generating the dealer's shares for every point of the domain,
+with threshold n / 3 */
+pub fn bench_dealer_shares(c: &mut Criterion) {
+    use ark_ff::Field;
+    // use a fixed seed for reproducability
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+    let mut gen_secret = |n: usize| {
+        let s = ark_bls12_381::Fr::rand(&mut rng);
+        poly::random_secret(n as u32 / 3, s, &mut rng)
+    };
+
+    // one evaluation at each domain point
+    let gen_shares = |secret: &poly::Secret, n: usize| {
+        let omega = ferveo::fft::domain(n).group_gen;
+        let _shares: Vec<poly::Share> = (0..n)
+            .map(|k| poly::share(secret, omega.pow([k as u64])))
+            .collect();
+    };
+
+    let gen_shares_fft = |secret: &poly::Secret, n: usize| {
+        let _shares = poly::multi_share(secret, n);
+    };
+
+    let mut group = c.benchmark_group("generate dealer shares");
+    group.sample_size(10);
+
+    for n in [100, 1000].iter() {
+        let secret = gen_secret(*n);
+        group.bench_with_input(BenchmarkId::new("pointwise", n), n, |b, n| {
+            b.iter(|| gen_shares(&secret, *n))
+        });
+        group.bench_with_input(BenchmarkId::new("fft", n), n, |b, n| {
+            b.iter(|| gen_shares_fft(&secret, *n))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_public, bench_dealer_shares);
 //criterion_group!(benches, bench_shares);
 criterion_main!(benches);
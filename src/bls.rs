@@ -51,7 +51,7 @@ pub fn sign_with_mk_g2(secret: Scalar, mk: G2Affine, msg: &[u8]) -> G2Affine {
 }
 
 // verify a signature in G2 against a public key in G1
-fn verify_g2(pk: &G1Affine, sig: &G2Affine, msg: &[u8]) -> bool {
+pub fn verify_g2(pk: &G1Affine, sig: &G2Affine, msg: &[u8]) -> bool {
     let lhs = pairing(pk, &hash_to_g2(msg));
     let rhs = pairing(&G1Affine::generator(), sig);
     lhs == rhs
@@ -0,0 +1,100 @@
+/*
+ECIES over BLS12-381 G1, with ChaCha20-Poly1305 as the AEAD.
+Each message is encrypted under a key derived with HKDF-SHA256
+from a fresh ephemeral key and the recipient's encryption key,
+so a fixed nonce is never reused with the same key.
+Ciphertexts are not bound to a sender,
+so senders should authenticate them with a separate signing key.
+*/
+
+#![allow(non_snake_case)]
+
+use crate::protocol;
+use crate::serialization::{Decode, Encode};
+
+use ark_bls12_381::{Fr, G1Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::UniformRand;
+use ark_serialize::{CanonicalSerialize, Read, SerializationError, Write};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+type Scalar = Fr;
+
+// derive the encryption key for the decryption key `dk`
+pub fn encryption_key(dk: Scalar) -> G1Affine {
+    G1Affine::prime_subgroup_generator().mul(dk).into_affine()
+}
+
+/* A ciphertext, with the ephemeral public key `R`
+and the AEAD ciphertext `c`, including the tag */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ciphertext {
+    pub R: G1Affine,
+    pub c: Vec<u8>,
+}
+
+/* Derive the AEAD key from the ephemeral public key `R`
+and the shared point `S` */
+fn derive_key(R: &G1Affine, S: &G1Affine) -> Key {
+    let mut ikm = Vec::new();
+    R.serialize(&mut ikm).unwrap();
+    S.serialize(&mut ikm).unwrap();
+    let hk = hkdf::Hkdf::<sha2::Sha256>::new(None, &ikm);
+    let mut key = Key::default();
+    hk.expand(b"ferveo-ecies", &mut key).unwrap();
+    key
+}
+
+/* Encrypt `msg` to the encryption key `ek`.
+The associated data `aad` is authenticated, but not encrypted. */
+pub fn encrypt<R: rand::Rng + Sized>(
+    ek: &G1Affine,
+    aad: &[u8],
+    msg: &[u8],
+    rng: &mut R,
+) -> Ciphertext {
+    let r = Scalar::rand(rng);
+    let R = encryption_key(r);
+    let S = ek.mul(r).into_affine();
+    let cipher = ChaCha20Poly1305::new(&derive_key(&R, &S));
+    let c = cipher
+        .encrypt(&Nonce::default(), Payload { msg, aad })
+        .unwrap();
+    Ciphertext { R, c }
+}
+
+/* Decrypt a ciphertext with the decryption key `dk`.
+Returns `None` if the ciphertext or associated data have been tampered with,
+or the ciphertext is for another key. */
+pub fn decrypt(dk: Scalar, aad: &[u8], ct: &Ciphertext) -> Option<Vec<u8>> {
+    let S = ct.R.mul(dk).into_affine();
+    let cipher = ChaCha20Poly1305::new(&derive_key(&ct.R, &S));
+    let msg = &ct.c[..];
+    cipher.decrypt(&Nonce::default(), Payload { msg, aad }).ok()
+}
+
+impl Encode for Ciphertext {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.R.encode(writer)?;
+        self.c.encode(writer)
+    }
+}
+
+impl Decode for Ciphertext {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let R = G1Affine::decode(reader)?;
+        let c = Vec::decode(reader)?;
+        Ok(Ciphertext { R, c })
+    }
+}
+
+impl_canonical!(Ciphertext);
+
+// ciphertexts can be sent and shared between threads
+const _: fn() = || {
+    protocol::assert_send_sync::<Ciphertext>();
+};
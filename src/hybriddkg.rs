@@ -1,79 +1,503 @@
 #![allow(clippy::many_single_char_names)]
+#![allow(clippy::large_enum_variant)]
 #![allow(non_snake_case)]
 
-use crate::poly;
+use crate::hybridvss;
+use crate::hybridvss::sh::{hash_public_poly, Accusation, Reveal};
+use crate::serialization::{decode_version, encode_version, Decode, Encode};
+use crate::transcript::Transcript;
+use crate::{bls, fft, poly, protocol};
 
 use ark_bls12_381::Fr;
+use ark_ec::AffineCurve;
+use ark_ff::Field;
+use ark_serialize::{Read, SerializationError, Write};
+use bls12_381::{G1Affine, G2Affine};
 use num::integer::div_ceil;
 use rand::Rng;
-use std::collections::{BTreeSet, HashMap};
-use std::rc::Rc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
 
 type Scalar = Fr;
 
-/* An "echo" message */
+/* The identifier of a hybriddkg session,
+which is bound into every message.
+Messages from other sessions are rejected. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SessionId {
+    pub epoch: u64, // the epoch of the session
+    pub tau: u32,   // the session counter within the epoch
+}
+
+impl SessionId {
+    // the identifier of the hybridvss session for the dealer `d`
+    pub fn vss(&self, d: u32) -> hybridvss::SessionId {
+        hybridvss::SessionId {
+            epoch: self.epoch,
+            d,
+            tau: self.tau,
+        }
+    }
+}
+
+/* An "echo" message.
+Signed echo messages for the same (L, Q) pair
+form a certificate for Q. */
 #[derive(Clone)]
 pub struct Echo {
+    session: SessionId,
+    L: u32,           // the leader number
     q: BTreeSet<u32>, // a set of node indexes
-                      // FIXME: add r/m
 }
 
-/* A "ready" message */
+/* A "ready" message.
+Signed ready messages for the same (L, Q) pair
+form a certificate for Q. */
 #[derive(Clone)]
 pub struct Ready {
+    session: SessionId,
+    L: u32,           // the leader number
     q: BTreeSet<u32>, // a set of node indexes
-                      // FIXME: add r/m
 }
 
 pub enum ReadyAction {
-    Ready(Ready),
+    Ready(Signed<Ready>),
     Complete,
 }
 
 /* A "send" message */
 #[derive(Clone)]
 pub struct Send {
+    session: SessionId,
+    L: u32,           // the leader number
     q: BTreeSet<u32>, // a set of node indexes
-                      // FIXME: add r/m
+    j: Justification, // the justification for q
+}
+
+/* A "lead-ch" message */
+#[derive(Clone)]
+pub struct LeadCh {
+    session: SessionId,
+    L: u32,           // the proposed leader number
+    q: BTreeSet<u32>, // q_bar if set, and q_hat otherwise
+    j: Justification, // the justification for q
+}
+
+/* A hybridvss "ready" message for the dealer `d`,
+with commitment hash `C_hash`. */
+#[derive(Clone)]
+pub struct VssReady {
+    pub session: SessionId,
+    pub d: u32,
+    pub C_hash: [u8; 32],
+}
+
+/* A complaint against a dealer,
+consisting of a hybridvss accusation by node `j`,
+which carries the dealer's signature on the accused send message.
+Complaints can be verified by any third party,
+and are evidence of the dealer's misbehaviour. */
+#[derive(Clone)]
+pub struct Complaint {
+    pub accusation: Accusation,
+}
+
+impl Complaint {
+    // the index of the accused dealer
+    pub fn dealer(&self) -> u32 {
+        self.accusation.session.d
+    }
+}
+
+/* The proof `R_d` that the dealer `d` completed hybridvss-sh.
+Consists of signed hybridvss ready messages of weight at least `W - t - f`. */
+#[derive(Clone)]
+pub struct Proof {
+    pub d: u32,
+    pub C_hash: [u8; 32],
+    pub R: Vec<Signed<VssReady>>,
+}
+
+/* A certificate `M` for a set of node indexes `Q`,
+consisting of signed echo messages of weight at least `ceil((W + t + 1) / 2)`,
+or signed ready messages of weight at least `t + 1`,
+all for the same (L, Q) pair. */
+#[derive(Clone)]
+pub enum Certificate {
+    Echo(Vec<Signed<Echo>>),
+    Ready(Vec<Signed<Ready>>),
+}
+
+/* The justification for a set of node indexes `Q` */
+#[derive(Clone)]
+pub enum Justification {
+    R(Vec<Proof>),  // a proof for each dealer in `Q`
+    M(Certificate), // a certificate for `Q`
+}
+
+pub enum LeadChAction {
+    LeadCh(Signed<LeadCh>),
+    Send(Signed<Send>),
+    // an echo of the new leader's send message, if it arrived early
+    Echo(Signed<Echo>),
+    Delay,
+}
+
+/* A message, signed by the node with index `signer` */
+#[derive(Clone)]
+pub struct Signed<M> {
+    pub msg: M,
+    pub signer: u32,   // index of the signing node
+    pub sig: G2Affine, // signature on the transcript hash of the message
+}
+
+/* Messages that can be signed */
+pub trait Signable {
+    // the transcript of the message, whose hash is signed
+    fn transcript(&self) -> Transcript;
+
+    // the session that the message belongs to
+    fn session(&self) -> SessionId;
+}
+
+/* the transcript of a message of kind `kind`,
+with a session id, leader number and set of node indexes */
+fn transcript_LQ(
+    kind: &[u8],
+    session: &SessionId,
+    L: u32,
+    Q: &BTreeSet<u32>,
+) -> Transcript {
+    let mut transcript = Transcript::new(kind);
+    transcript.append_encode(b"session", session);
+    transcript.append_u32(b"L", L);
+    transcript.append_encode(b"Q", Q);
+    transcript
+}
+
+/* the transcript of a message of kind `kind`,
+with a session id, leader number, set of node indexes
+and the justification for the set */
+fn transcript_LQj(
+    kind: &[u8],
+    session: &SessionId,
+    L: u32,
+    Q: &BTreeSet<u32>,
+    j: &Justification,
+) -> Transcript {
+    let mut transcript = transcript_LQ(kind, session, L, Q);
+    transcript.append_encode(b"justification", j);
+    transcript
+}
+
+impl Signable for Echo {
+    fn transcript(&self) -> Transcript {
+        transcript_LQ(b"hybriddkg-echo", &self.session, self.L, &self.q)
+    }
+
+    fn session(&self) -> SessionId {
+        self.session
+    }
+}
+
+impl Signable for Ready {
+    fn transcript(&self) -> Transcript {
+        transcript_LQ(b"hybriddkg-ready", &self.session, self.L, &self.q)
+    }
+
+    fn session(&self) -> SessionId {
+        self.session
+    }
+}
+
+impl Signable for VssReady {
+    fn transcript(&self) -> Transcript {
+        let mut transcript = Transcript::new(b"hybridvss-ready");
+        transcript.append_encode(b"session", &self.session.vss(self.d));
+        transcript.append_encode(b"C_hash", &self.C_hash);
+        transcript
+    }
+
+    fn session(&self) -> SessionId {
+        self.session
+    }
+}
+
+impl Signable for Send {
+    fn transcript(&self) -> Transcript {
+        transcript_LQj(
+            b"hybriddkg-send",
+            &self.session,
+            self.L,
+            &self.q,
+            &self.j,
+        )
+    }
+
+    fn session(&self) -> SessionId {
+        self.session
+    }
+}
+
+impl Signable for LeadCh {
+    fn transcript(&self) -> Transcript {
+        transcript_LQj(
+            b"hybriddkg-lead-ch",
+            &self.session,
+            self.L,
+            &self.q,
+            &self.j,
+        )
+    }
+
+    fn session(&self) -> SessionId {
+        self.session
+    }
+}
+
+impl<M: Signable> Signed<M> {
+    // sign a message as the node with index `signer`
+    pub fn sign(msg: M, signer: u32, sk: bls12_381::Scalar) -> Self {
+        let sig = bls::sign_g2(sk, &msg.transcript().finalize());
+        Signed { msg, signer, sig }
+    }
+
+    /* verify the signature against the public key of the signer,
+    and that the message belongs to the session of `params` */
+    pub fn verify(&self, params: &Params) -> bool {
+        if self.msg.session() != params.session_id() {
+            return false;
+        }
+        match params.pubkeys.get(self.signer as usize) {
+            Some(pk) => {
+                let hash = self.msg.transcript().finalize();
+                bls::verify_g2(pk, &self.sig, &hash)
+            }
+            None => false,
+        }
+    }
 }
 
 /* A "shared" message */
 #[derive(Clone)]
 pub struct Shared {
-    pub C: Rc<poly::Public>, // a dealer commitment
-    pub d: u32,              // the dealer index
-    pub s_id: Scalar,        // the share for node i from the dealer
-                             // FIXME: add R_d
+    pub session: SessionId,
+    pub C: Arc<poly::Public>, // a dealer commitment
+    pub d: u32,               // the dealer index
+    /* the shares for node i from the dealer,
+    one for each unit of weight of node i */
+    pub s_id: Vec<Scalar>,
+    // the blinding shares in pedersen mode, one for each share
+    pub s_hat_id: Vec<Scalar>,
+    /* the signed hybridvss ready messages received for the dealer,
+    of weight at least `W - t - f` */
+    pub R: Vec<Signed<VssReady>>,
+}
+
+impl Shared {
+    // the proof `R_d` that the dealer completed hybridvss-sh
+    pub fn proof(&self, params: &Params) -> Proof {
+        let vss_params = params.vss_params(self.d);
+        Proof {
+            d: self.d,
+            C_hash: hash_public_poly(&vss_params, &self.C),
+            R: self.R.clone(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum SharedAction {
     Delay,
-    Send(Send),
+    Send(Signed<Send>),
+}
+
+/* A message of the hybriddkg protocol.
+Shared messages are delivered by the hybridvss instances of this node,
+and all other messages are broadcast. */
+#[derive(Clone)]
+pub enum Message {
+    Shared(Shared),
+    Send(Signed<Send>),
+    Echo(Signed<Echo>),
+    Ready(Signed<Ready>),
+    LeadCh(Signed<LeadCh>),
+    Complaint(Complaint),
+    Reveal(Reveal),
+}
+
+/* An event produced in response to a message.
+All messages are broadcast, and the output is the output of the dkg. */
+pub type Outgoing = protocol::Outgoing<Message, DkgOutput>;
+
+/* The output of the dkg for node `i` */
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkgOutput {
+    /* the aggregate public polynomial.
+    In pedersen mode, only the commitment at `(x, 0)` is known,
+    so each row has a single element. */
+    pub C: poly::Public,
+    pub i: u32,                          // the index of this node
+    pub pubkey: ark_bls12_381::G1Affine, // the distributed public key C[0][0]
+    /* the public key share for each domain point, computed from `C`.
+    node `j` holds the shares for the `w[j]` domain points
+    starting from index `share_indexes()[j]`. */
+    pub pubkey_shares: Vec<ark_bls12_381::G1Affine>,
+    pub share_index: usize, // index of the first domain point of this node
+    /* the private shares of this node, one for each unit of weight.
+    `shares[k]` is the share at domain point `share_index + k`. */
+    pub shares: Vec<Scalar>,
+}
+
+impl DkgOutput {
+    // check that the private shares match the public key shares of this node
+    pub fn verify(&self) -> bool {
+        let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+        self.shares.iter().enumerate().all(|(k, share)| {
+            self.pubkey_shares
+                .get(self.share_index + k)
+                .map_or(false, |pubkey_share| {
+                    g.mul(*share) == pubkey_share.into_projective()
+                })
+        })
+    }
+}
+
+// Errors that can occur when building parameters or finalizing the dkg
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("dealer {0} contributed more than one shared-output message")]
+    DuplicateDealer(u32),
+    #[error("expected an encryption key for each of the {0} participants")]
+    EncryptionKeys(u32),
+    #[error("no shared-output messages to finalize")]
+    NoShares,
+    #[error("expected a signing key for each of the {0} participants")]
+    PublicKeys(u32),
+    #[error("dealer {0} has not revealed a valid blinding polynomial")]
+    Reveal(u32),
+    #[error("dealer {0} sent the wrong number of shares")]
+    ShareCount(u32),
+    #[error(
+        "the shared-output message from dealer {0} is for another session"
+    )]
+    Session(u32),
 }
 
 #[derive(Clone)]
 pub struct Params {
-    pub f: u32,      // failure threshold
-    pub l: u32,      // leader index
-    pub t: u32,      // threshold
-    pub w: Vec<u32>, // weight of each participant
+    /* The encryption key of each participant.
+    If set, point-to-point hybridvss messages are encrypted to the receiver.
+    Should be set with `with_encryption_keys`. */
+    pub ek: Vec<ark_bls12_381::G1Affine>,
+    pub epoch: u64, // the epoch of the session
+    pub f: u32,     // failure threshold
+    pub l: u32,     // index of the initial leader
+    /* If set, dealers commit with pedersen commitments.
+    Each agreed dealer must then publish a reveal message,
+    and finalization waits for it; see `Context::try_finalize`. */
+    pub pedersen: bool,
+    pub pubkeys: Vec<G1Affine>, // signing public key of each participant
+    pub t: u32,                 // threshold
+    pub tau: u32,               // the session counter within the epoch
+    pub w: Vec<u32>,            // weight of each participant
 }
 
 impl Params {
-    pub fn new(f: u32, l: u32, t: u32, w: Vec<u32>) -> Self {
-        Params { f, l, t, w }
+    /* Fails unless there is exactly one signing key
+    for each participant. */
+    pub fn new(
+        session: SessionId,
+        f: u32,
+        l: u32,
+        t: u32,
+        w: Vec<u32>,
+        pubkeys: Vec<G1Affine>,
+    ) -> Result<Self, Error> {
+        if pubkeys.len() != w.len() {
+            return Err(Error::PublicKeys(w.len() as u32));
+        }
+        let SessionId { epoch, tau } = session;
+        Ok(Params {
+            ek: Vec::new(),
+            epoch,
+            f,
+            l,
+            pedersen: false,
+            pubkeys,
+            t,
+            tau,
+            w,
+        })
+    }
+
+    /* set the encryption key of each participant,
+    so that point-to-point hybridvss messages are sealed.
+    Fails unless there is exactly one key for each participant. */
+    pub fn with_encryption_keys(
+        self,
+        ek: Vec<ark_bls12_381::G1Affine>,
+    ) -> Result<Self, Error> {
+        if ek.len() != self.w.len() {
+            return Err(Error::EncryptionKeys(self.n()));
+        }
+        Ok(Params { ek, ..self })
     }
 
     // initialize with random values for `l`
     pub fn random_leader<R: Rng>(
+        session: SessionId,
         f: u32,
         t: u32,
         w: Vec<u32>,
+        pubkeys: Vec<G1Affine>,
         rng: &mut R,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let l = rng.gen_range(0, w.len() as u32);
-        Self::new(f, l, t, w)
+        Self::new(session, f, l, t, w, pubkeys)
+    }
+
+    // the identifier of the session
+    pub fn session_id(&self) -> SessionId {
+        SessionId {
+            epoch: self.epoch,
+            tau: self.tau,
+        }
+    }
+
+    /* start a transcript bound to the protocol, session
+    and threshold parameters */
+    pub fn transcript(&self) -> Transcript {
+        let mut transcript = Transcript::new(b"hybriddkg");
+        transcript.append_encode(b"session", &self.session_id());
+        transcript.append_u32(b"f", self.f);
+        transcript.append_u32(b"t", self.t);
+        transcript.append_encode(b"w", &self.w);
+        transcript.append_encode(b"pedersen", &self.pedersen);
+        transcript
+    }
+
+    // the hybridvss parameters for the dealer `d`
+    pub fn vss_params(&self, d: u32) -> hybridvss::Params {
+        let Params {
+            epoch,
+            f,
+            pedersen,
+            t,
+            tau,
+            ..
+        } = *self;
+        hybridvss::Params {
+            d,
+            ek: self.ek.clone(),
+            epoch,
+            f,
+            pedersen,
+            pubkeys: self.pubkeys.clone(),
+            t,
+            tau,
+            w: self.w.clone(),
+        }
     }
 
     // return the number of participants in the setup
@@ -84,21 +508,146 @@ impl Params {
     pub fn total_weight(&self) -> u32 {
         self.w.iter().sum()
     }
+
+    // the index of the first domain point of each participant
+    pub fn share_indexes(&self) -> Vec<usize> {
+        poly::share_indexes(&self.w)
+    }
+
+    /* the index of the leader with leader number `L`.
+    Leaders rotate through the participants, starting at `l`. */
+    pub fn leader(&self, L: u32) -> u32 {
+        (self.l + L) % self.n()
+    }
+
+    // the total weight of a set of node indexes
+    pub fn weight(&self, nodes: &BTreeSet<u32>) -> u32 {
+        nodes.iter().map(|j| self.w[*j as usize]).sum()
+    }
+
+    /* the total weight of the distinct signers of the given messages.
+    Returns `None` if any of the signatures are invalid,
+    or if `pred` does not hold for each message. */
+    fn signed_weight<M, F>(&self, msgs: &[Signed<M>], pred: F) -> Option<u32>
+    where
+        M: Signable,
+        F: Fn(&M) -> bool,
+    {
+        let mut signers = BTreeSet::new();
+        for msg in msgs.iter() {
+            if !(pred(&msg.msg) && msg.verify(self)) {
+                return None;
+            }
+            signers.insert(msg.signer);
+        }
+        Some(self.weight(&signers))
+    }
+
+    // verify a proof that a dealer completed hybridvss-sh
+    pub fn verify_proof(&self, Proof { d, C_hash, R }: &Proof) -> bool {
+        let Params { t, f, .. } = *self;
+        let W = self.total_weight();
+        let pred = |r: &VssReady| r.d == *d && r.C_hash == *C_hash;
+        self.signed_weight(R, pred)
+            .map_or(false, |weight| weight >= W - t - f)
+    }
+
+    /* verify a complaint against a dealer.
+    The dealer must have signed the send message for the accuser,
+    and the accusation must show that the send message is invalid. */
+    pub fn verify_complaint(
+        &self,
+        Complaint { accusation }: &Complaint,
+    ) -> bool {
+        let d = accusation.session.d;
+        d < self.n() && accusation.verify(&self.vss_params(d))
+    }
+
+    // verify a certificate for `Q`
+    pub fn verify_certificate(
+        &self,
+        Q: &BTreeSet<u32>,
+        M: &Certificate,
+    ) -> bool {
+        let t = self.t;
+        let W = self.total_weight();
+        match M {
+            Certificate::Echo(echos) => {
+                let L = echos.first().map(|echo| echo.msg.L);
+                let pred = |echo: &Echo| Some(echo.L) == L && echo.q == *Q;
+                self.signed_weight(echos, pred)
+                    .map_or(false, |weight| weight >= div_ceil(W + t + 1, 2))
+            }
+            Certificate::Ready(readys) => {
+                let L = readys.first().map(|ready| ready.msg.L);
+                let pred = |ready: &Ready| Some(ready.L) == L && ready.q == *Q;
+                self.signed_weight(readys, pred)
+                    .map_or(false, |weight| weight > t)
+            }
+        }
+    }
+
+    /* verify the justification for `Q`.
+    A set of proofs must all be valid,
+    and contain a proof for each dealer in `Q`. */
+    pub fn verify_justification(
+        &self,
+        Q: &BTreeSet<u32>,
+        j: &Justification,
+    ) -> bool {
+        match j {
+            Justification::R(proofs) => {
+                proofs.iter().all(|proof| self.verify_proof(proof))
+                    && Q.iter()
+                        .all(|d| proofs.iter().any(|proof| proof.d == *d))
+            }
+            Justification::M(M) => self.verify_certificate(Q, M),
+        }
+    }
 }
 
 pub struct Context {
-    /* Counters for `echo` messages.
-    The keys of the map are sha2-256 hashes of q sets. */
+    // valid complaints against dealers, by dealer
+    complaints: BTreeMap<u32, Complaint>,
+    /* Weighted counters for `echo` messages.
+    The keys of the map are sha2-256 hashes of (l, q) pairs. */
     e: HashMap<[u8; 32], u32>,
+    /* The set echoed for each leader number.
+    Only the first valid send message for each leader number is echoed,
+    so that an equivocating leader cannot collect echos for two sets. */
+    echoed: BTreeMap<u32, BTreeSet<u32>>,
     i: u32, // index of this node's public key
-    // FIXME: m_bar
+    L: u32, // the current leader number
+    /* The nodes that have requested a change to each leader number.
+    The keys of the map are leader numbers. */
+    lc: HashMap<u32, BTreeSet<u32>>,
+    // the highest leader number that this node has requested a change to
+    lc_L: u32,
+    /* Signed `echo` and `ready` messages, keyed by sender,
+    from which certificates are formed.
+    The keys of the maps are sha2-256 hashes of (l, q) pairs. */
+    M_echo: HashMap<[u8; 32], BTreeMap<u32, Signed<Echo>>>,
+    M_ready: HashMap<[u8; 32], BTreeMap<u32, Signed<Ready>>>,
+    m_bar: Option<Certificate>, // the certificate for q_bar
+    output: Option<DkgOutput>,  // the output of the dkg, once finalized
     params: Params,
+    /* Send messages from the leaders of future leader numbers,
+    that arrived before this node changed leader.
+    The keys of the map are leader numbers. */
+    pending_sends: BTreeMap<u32, Signed<Send>>,
     q_bar: BTreeSet<u32>, // set of node indexes
+    // the agreed set of dealers, once the dkg has completed
+    q_final: Option<BTreeSet<u32>>,
     q_hat: BTreeSet<u32>, // set of node indexes
-    /* Counters for `ready` messages.
+    /* Weighted counters for `ready` messages.
     The keys of the map are sha2-256 hashes of (l, q) pairs. */
     r: HashMap<[u8; 32], u32>,
-    // FIXME: r_hat
+    r_hat: BTreeMap<u32, Proof>, // proofs for each dealer in q_hat
+    // the reveal messages from dealers in pedersen mode, by dealer
+    reveals: BTreeMap<u32, Reveal>,
+    // the valid shared messages from this node's hybridvss instances
+    shared: BTreeMap<u32, Shared>,
+    sk: bls12_381::Scalar, // the signing key of this node
 }
 
 /* Inserts the provided value if the key is not present in the map. */
@@ -135,38 +684,101 @@ where
     m.get_mut(&k).unwrap()
 }
 
-// Hash an (L, Q) pair
-fn hash_LQ(L: u32, Q: &BTreeSet<u32>) -> [u8; 32] {
-    use digest::Digest;
-    let mut hasher = sha2::Sha256::new();
-    hasher.update(L.to_le_bytes());
-    Q.iter().for_each(|j| hasher.update(j.to_le_bytes()));
-    hasher.finalize().into()
+// Hash an (L, Q) pair, bound to the session and threshold parameters
+fn hash_LQ(params: &Params, L: u32, Q: &BTreeSet<u32>) -> [u8; 32] {
+    let mut transcript = params.transcript();
+    transcript.append_u32(b"L", L);
+    transcript.append_encode(b"Q", Q);
+    transcript.finalize()
 }
 
 impl Context {
     /* Initialize node `i` with `params` */
     pub fn init(
         params: Params,
-        i: u32, // index of this node
+        i: u32,                // index of this node
+        sk: bls12_381::Scalar, // the signing key of this node
     ) -> Self {
+        let complaints = BTreeMap::new();
         let e = HashMap::new();
+        let echoed = BTreeMap::new();
+        let L = 0;
+        let lc = HashMap::new();
+        let lc_L = 0;
+        let M_echo = HashMap::new();
+        let M_ready = HashMap::new();
+        let m_bar = None;
+        let output = None;
+        let pending_sends = BTreeMap::new();
         let q_bar = BTreeSet::new();
+        let q_final = None;
         let q_hat = BTreeSet::new();
         let r = HashMap::new();
+        let r_hat = BTreeMap::new();
+        let reveals = BTreeMap::new();
+        let shared = BTreeMap::new();
 
         Context {
+            complaints,
             e,
+            echoed,
             i,
+            L,
+            lc,
+            lc_L,
+            M_echo,
+            M_ready,
+            m_bar,
+            output,
             params,
+            pending_sends,
             q_bar,
+            q_final,
             q_hat,
             r,
+            r_hat,
+            reveals,
+            shared,
+            sk,
         }
     }
 
+    // sign a message as this node
+    fn sign<M: Signable>(&self, msg: M) -> Signed<M> {
+        Signed::sign(msg, self.i, self.sk)
+    }
+
+    // the index of the current leader
+    pub fn leader(&self) -> u32 {
+        self.params.leader(self.L)
+    }
+
     pub fn is_leader(&self) -> bool {
-        self.i == self.params.l
+        self.i == self.leader()
+    }
+
+    /* the set of node indexes that the leader should propose,
+    and its justification.
+    This is `q_bar` if it is set, and `q_hat` otherwise. */
+    fn proposal(&self) -> (BTreeSet<u32>, Justification) {
+        match &self.m_bar {
+            Some(m_bar) if !self.q_bar.is_empty() => {
+                (self.q_bar.clone(), Justification::M(m_bar.clone()))
+            }
+            _ => {
+                let proofs = self.r_hat.values().cloned().collect();
+                (self.q_hat.clone(), Justification::R(proofs))
+            }
+        }
+    }
+
+    /* Sign a hybridvss ready message for the dealer `d`,
+    with commitment `C`.
+    Should be sent alongside each hybridvss ready message. */
+    pub fn vss_ready(&self, d: u32, C: &poly::Public) -> Signed<VssReady> {
+        let session = self.params.session_id();
+        let C_hash = hash_public_poly(&self.params.vss_params(d), C);
+        self.sign(VssReady { session, d, C_hash })
     }
 
     /* determine if the threshold has been met,
@@ -179,17 +791,28 @@ impl Context {
     }
 
     /* Respond to a "shared" message. */
-    pub fn shared(
-        &mut self,
-        Shared { d, .. }: &Shared,
-    ) -> Option<SharedAction> {
-        self.q_hat.insert(*d);
+    pub fn shared(&mut self, shared: &Shared) -> Option<SharedAction> {
+        let proof = shared.proof(&self.params);
+        if shared.session != self.params.session_id()
+            || !self.params.verify_proof(&proof)
+        {
+            return None;
+        }
+        self.shared
+            .entry(shared.d)
+            .or_insert_with(|| shared.clone());
+        // dealers with a valid complaint are not proposed
+        if self.complaints.contains_key(&shared.d) {
+            return None;
+        }
+        self.q_hat.insert(shared.d);
+        self.r_hat.insert(shared.d, proof);
         if self.shared_send_threshold() {
             if self.is_leader() {
-                Some(SharedAction::Send(Send {
-                    q: self.q_hat.clone(),
-                    // FIXME: add r/m
-                }))
+                let (q, j) = self.proposal();
+                let session = self.params.session_id();
+                let L = self.L;
+                Some(SharedAction::Send(self.sign(Send { session, L, q, j })))
             } else {
                 Some(SharedAction::Delay)
             }
@@ -198,97 +821,1032 @@ impl Context {
         }
     }
 
-    /* Respond to a "send" message */
-    pub fn send(&self, Send { q }: Send) -> Option<Echo> {
-        // FIXME: verify-signatures
-        if self.q_bar.is_empty() || self.q_bar == q {
-            Some(Echo { q })
+    /* Respond to a "reveal" message from a dealer, in pedersen mode.
+    Returns `true` if the reveal is the first from its dealer,
+    and is consistent with the dealer's commitment,
+    if it has been received.
+    Otherwise, the reveal is verified when finalizing. */
+    pub fn reveal(&mut self, reveal: &Reveal) -> bool {
+        let d = reveal.session.d;
+        if !self.params.pedersen
+            || d >= self.params.n()
+            || reveal.session != self.params.session_id().vss(d)
+            || self.reveals.contains_key(&d)
+        {
+            return false;
+        }
+        if let Some(shared) = self.shared.get(&d) {
+            let params = self.params.vss_params(d);
+            if !reveal.verify(&params, &shared.C) {
+                return false;
+            }
+        }
+        self.reveals.insert(d, reveal.clone());
+        true
+    }
+
+    // valid complaints against dealers, by dealer
+    pub fn complaints(&self) -> &BTreeMap<u32, Complaint> {
+        &self.complaints
+    }
+
+    /* Respond to a complaint against a dealer.
+    If the complaint is valid,
+    the dealer is excluded from `q_hat`, and from any future proposals.
+    Returns `true` if the complaint is valid,
+    and is the first against the dealer. */
+    pub fn complaint(&mut self, complaint: &Complaint) -> bool {
+        let d = complaint.dealer();
+        if self.complaints.contains_key(&d)
+            || !self.params.verify_complaint(complaint)
+        {
+            return false;
+        }
+        self.complaints.insert(d, complaint.clone());
+        self.q_hat.remove(&d);
+        self.r_hat.remove(&d);
+        true
+    }
+
+    // determine if `q` contains a dealer with a valid complaint
+    fn contains_accused(&self, q: &BTreeSet<u32>) -> bool {
+        q.iter().any(|d| self.complaints.contains_key(d))
+    }
+
+    // determine if `L` is a future leader number that may be buffered
+    fn is_near_future(&self, L: u32) -> bool {
+        L > self.L && L <= self.L.saturating_add(self.params.n())
+    }
+
+    /* Respond to a "send" message.
+    Should only be accepted from the current leader,
+    and only the first valid send for each leader number is echoed.
+    A send from the leader of a near future leader number is buffered,
+    and echoed once this node changes to that leader number.
+    Unless `q_bar` is set,
+    proposals containing dealers with a valid complaint are rejected. */
+    pub fn send(&mut self, send: Signed<Send>) -> Option<Signed<Echo>> {
+        let L = send.msg.L;
+        let from_leader = send.signer == self.params.leader(L);
+        if !(from_leader && send.verify(&self.params)) {
+            return None;
+        }
+        if self.is_near_future(L) {
+            self.pending_sends.entry(L).or_insert(send);
+            return None;
+        }
+        let Send { session, L, q, j } = send.msg;
+        let t = self.params.t as usize;
+        if L == self.L
+            && !self.echoed.contains_key(&L)
+            && q.len() > t
+            && self.params.verify_justification(&q, &j)
+            && (if self.q_bar.is_empty() {
+                !self.contains_accused(&q)
+            } else {
+                self.q_bar == q
+            })
+        {
+            self.echoed.insert(L, q.clone());
+            Some(self.sign(Echo { session, L, q }))
         } else {
             None
         }
     }
 
-    // increment the echo counter
-    fn incr_echo_counter(&mut self, lq_hash: [u8; 32]) {
+    // increment the echo counter by the weight of node `m`
+    fn incr_echo_counter(&mut self, lq_hash: [u8; 32], m: u32) {
+        let w_m = self.params.w[m as usize];
         let e_LQ: &mut u32 = get_mut_or_insert(lq_hash, 0, &mut self.e);
-        *e_LQ += 1;
+        *e_LQ += w_m;
     }
 
     /* determine if the threshold has been met,
-    in order to broadcast a ready message */
-    fn echo_ready_threshold(&mut self, lq_hash: [u8; 32]) -> bool {
+    in order to broadcast a ready message.
+    `w_m` is the weight of the most recent echo. */
+    fn echo_ready_threshold(&mut self, lq_hash: [u8; 32], w_m: u32) -> bool {
         let t = self.params.t;
         let W = self.params.total_weight();
         let e_LQ = *get_or_insert(lq_hash, 0, &mut self.e);
         let r_LQ = *get_or_insert(lq_hash, 0, &mut self.r);
-        // FIXME: should this be >= on e_LQ?
-        e_LQ == div_ceil(W + t + 1, 2) && r_LQ < t + 1
-    }
-
-    /* Respond to an "echo" message */
-    pub fn echo(&mut self, Echo { q }: Echo) -> Option<Ready> {
-        let lq_hash = hash_LQ(self.params.l, &q);
-        self.incr_echo_counter(lq_hash);
-        if self.echo_ready_threshold(lq_hash) {
-            self.q_bar = self.q_bar.union(&q).cloned().collect();
-            // FIXME: What should happen to M-bar?
-            Some(Ready { q })
+        protocol::reached(e_LQ, w_m, div_ceil(W + t + 1, 2)) && r_LQ < t + 1
+    }
+
+    /* Respond to an "echo" message.
+    Only the first echo from each node is counted. */
+    pub fn echo(&mut self, echo: Signed<Echo>) -> Option<Signed<Ready>> {
+        if !echo.verify(&self.params) {
+            return None;
+        }
+        let m = echo.signer;
+        let w_m = self.params.w[m as usize];
+        let Echo { session, L, q } = echo.msg.clone();
+        let lq_hash = hash_LQ(&self.params, L, &q);
+        let M_LQ =
+            get_mut_or_insert(lq_hash, BTreeMap::new(), &mut self.M_echo);
+        if M_LQ.contains_key(&m) {
+            return None;
+        }
+        M_LQ.insert(m, echo);
+        self.incr_echo_counter(lq_hash, m);
+        if self.echo_ready_threshold(lq_hash, w_m) {
+            let M = self.M_echo[&lq_hash].values().cloned().collect();
+            self.q_bar = q.clone();
+            self.m_bar = Some(Certificate::Echo(M));
+            Some(self.sign(Ready { session, L, q }))
         } else {
             None
         }
     }
 
-    // increment ready echo counter
-    fn incr_ready_counter(&mut self, lq_hash: [u8; 32]) {
+    // increment the ready counter by the weight of node `m`
+    fn incr_ready_counter(&mut self, lq_hash: [u8; 32], m: u32) {
+        let w_m = self.params.w[m as usize];
         let r_LQ: &mut u32 = get_mut_or_insert(lq_hash, 0, &mut self.r);
-        *r_LQ += 1;
+        *r_LQ += w_m;
     }
 
     /* determine if the threshold has been met,
-    in order to broadcast a ready message */
-    fn ready_ready_threshold(&mut self, lq_hash: [u8; 32]) -> bool {
+    in order to broadcast a ready message.
+    `w_m` is the weight of the most recent ready message. */
+    fn ready_ready_threshold(&mut self, lq_hash: [u8; 32], w_m: u32) -> bool {
         let t = self.params.t;
         let W = self.params.total_weight();
         let e_LQ = *get_or_insert(lq_hash, 0, &mut self.e);
         let r_LQ = *get_or_insert(lq_hash, 0, &mut self.r);
-        // FIXME: should this be >= on r_C?
-        r_LQ == t + 1 && e_LQ < div_ceil(W + t + 1, 2)
+        protocol::reached(r_LQ, w_m, t + 1) && e_LQ < div_ceil(W + t + 1, 2)
     }
 
     /* determine if the threshold has been met,
-    in order to complete the dkg */
-    fn ready_complete_threshold(&mut self, lq_hash: [u8; 32]) -> bool {
+    in order to complete the dkg.
+    `w_m` is the weight of the most recent ready message. */
+    fn ready_complete_threshold(
+        &mut self,
+        lq_hash: [u8; 32],
+        w_m: u32,
+    ) -> bool {
         let Params { t, f, .. } = self.params;
         let W = self.params.total_weight();
         let r_LQ = *get_or_insert(lq_hash, 0, &mut self.r);
-        // FIXME: should this be >= on r_C?
-        r_LQ == W - t - f
-    }
-
-    /* Respond to a "ready" message */
-    pub fn ready(&mut self, Ready { q }: Ready) -> Option<ReadyAction> {
-        let lq_hash = hash_LQ(self.params.l, &q);
-        self.incr_ready_counter(lq_hash);
-        if self.ready_ready_threshold(lq_hash) {
-            self.q_bar = self.q_bar.union(&q).cloned().collect();
-            // FIXME: What should happen to M-bar?
-            Some(ReadyAction::Ready(Ready { q }))
-        } else if self.ready_complete_threshold(lq_hash) {
-            Some(ReadyAction::Complete)
+        protocol::reached(r_LQ, w_m, W - t - f)
+    }
+
+    /* Respond to a "ready" message.
+    Only the first ready message from each node is counted.
+    Both thresholds are checked on every message,
+    since a single heavy node may cross both at once. */
+    pub fn ready(&mut self, ready: Signed<Ready>) -> Vec<ReadyAction> {
+        let mut res = Vec::new();
+        if !ready.verify(&self.params) {
+            return res;
+        }
+        let m = ready.signer;
+        let w_m = self.params.w[m as usize];
+        let Ready { session, L, q } = ready.msg.clone();
+        let lq_hash = hash_LQ(&self.params, L, &q);
+        let M_LQ =
+            get_mut_or_insert(lq_hash, BTreeMap::new(), &mut self.M_ready);
+        if M_LQ.contains_key(&m) {
+            return res;
+        }
+        M_LQ.insert(m, ready);
+        self.incr_ready_counter(lq_hash, m);
+        if self.ready_ready_threshold(lq_hash, w_m) {
+            let M = self.M_ready[&lq_hash].values().cloned().collect();
+            self.q_bar = q.clone();
+            self.m_bar = Some(Certificate::Ready(M));
+            res.push(ReadyAction::Ready(self.sign(Ready { session, L, q })));
+        }
+        if self.ready_complete_threshold(lq_hash, w_m) {
+            res.push(ReadyAction::Complete);
+        }
+        res
+    }
+
+    /* Request a change to leader number `L`,
+    if this node has not already done so. */
+    fn request_lead_ch(&mut self, L: u32) -> Option<Signed<LeadCh>> {
+        if L > self.lc_L {
+            self.lc_L = L;
+            let session = self.params.session_id();
+            let (q, j) = self.proposal();
+            Some(self.sign(LeadCh { session, L, q, j }))
+        } else {
+            None
+        }
+    }
+
+    /* Respond to a timeout while waiting for the current leader.
+    Should be called if the leader does not complete the dkg in time. */
+    pub fn timeout(&mut self) -> Option<Signed<LeadCh>> {
+        self.request_lead_ch(self.L + 1)
+    }
+
+    /* Change to leader number `L`.
+    If this node is the new leader,
+    it proposes `q_bar` if it is set, and `q_hat` otherwise.
+    Otherwise, a buffered send from the new leader is echoed.
+    Lead-ch messages and sends for earlier leader numbers are discarded. */
+    fn change_leader(&mut self, L: u32) -> LeadChAction {
+        self.L = L;
+        self.lc_L = u32::max(self.lc_L, L);
+        self.lc.retain(|lc_L, _| *lc_L > L);
+        let pending = self.pending_sends.remove(&L);
+        self.pending_sends.retain(|send_L, _| *send_L > L);
+        let session = self.params.session_id();
+        let (q, j) = self.proposal();
+        if self.is_leader() && q.len() > self.params.t as usize {
+            LeadChAction::Send(self.sign(Send { session, L, q, j }))
+        } else {
+            match pending.and_then(|send| self.send(send)) {
+                Some(echo) => LeadChAction::Echo(echo),
+                None => LeadChAction::Delay,
+            }
+        }
+    }
+
+    /* determine if the threshold has been met,
+    in order to broadcast a lead-ch message */
+    fn lead_ch_lead_ch_threshold(&self, L: u32) -> bool {
+        let Params { t, f, .. } = self.params;
+        let weight = self.params.weight(&self.lc[&L]);
+        weight >= t + f + 1 && self.lc_L < L
+    }
+
+    /* determine if the threshold has been met,
+    in order to change leader */
+    fn lead_ch_change_threshold(&self, L: u32) -> bool {
+        let Params { t, f, .. } = self.params;
+        let W = self.params.total_weight();
+        let weight = self.params.weight(&self.lc[&L]);
+        weight >= W - t - f
+    }
+
+    /* Respond to a "lead-ch" message.
+    Only requests for near future leader numbers are counted,
+    so that a single node cannot grow the lead-ch counters without bound. */
+    pub fn lead_ch(&mut self, lead_ch: Signed<LeadCh>) -> Option<LeadChAction> {
+        if !lead_ch.verify(&self.params) {
+            return None;
+        }
+        let m = lead_ch.signer;
+        if m >= self.params.n() {
+            return None;
+        }
+        let LeadCh { L, q, j, .. } = lead_ch.msg;
+        if !self.is_near_future(L) || !self.params.verify_justification(&q, &j)
+        {
+            return None;
+        }
+        let senders = get_mut_or_insert(L, BTreeSet::new(), &mut self.lc);
+        // each node is only counted once
+        if !senders.insert(m) {
+            return None;
+        }
+        match j {
+            // adopt the proofs for dealers that this node has not seen
+            Justification::R(proofs) => {
+                for proof in proofs.into_iter() {
+                    if q.contains(&proof.d)
+                        && !self.complaints.contains_key(&proof.d)
+                    {
+                        self.q_hat.insert(proof.d);
+                        self.r_hat.entry(proof.d).or_insert(proof);
+                    }
+                }
+            }
+            // adopt the certificate if this node has not set q_bar
+            Justification::M(M) => {
+                if self.q_bar.is_empty() {
+                    self.q_bar = q;
+                    self.m_bar = Some(M);
+                }
+            }
+        }
+        if self.lead_ch_change_threshold(L) {
+            Some(self.change_leader(L))
+        } else if self.lead_ch_lead_ch_threshold(L) {
+            self.request_lead_ch(L).map(LeadChAction::LeadCh)
         } else {
             None
         }
     }
+
+    // the output of the dkg, once finalized
+    pub fn output(&self) -> Option<&DkgOutput> {
+        self.output.as_ref()
+    }
+
+    // the agreed set of dealers, once the dkg has completed
+    pub fn q_final(&self) -> Option<&BTreeSet<u32>> {
+        self.q_final.as_ref()
+    }
+
+    /* Finalize once the dkg has completed,
+    and a shared message has been received for each agreed dealer.
+    In pedersen mode, a reveal message is also needed from each agreed dealer.
+    The output is produced at most once.
+
+    Note that in pedersen mode, an agreed dealer that withholds its reveal
+    stalls finalization indefinitely:
+    there is no recovery of the dealer's contribution from the shares of
+    the other nodes, as in the extraction phase of GJKR,
+    and the dealer cannot be dropped once `Q` is agreed.
+    Deployments relying on pedersen mode must ensure that dealers reveal,
+    eg. by penalizing dealers in `Q` that do not. */
+    fn try_finalize(&mut self) -> Vec<Outgoing> {
+        if self.output.is_some() {
+            return Vec::new();
+        }
+        let q = match &self.q_final {
+            Some(q) => q,
+            None => return Vec::new(),
+        };
+        let shares: Option<Vec<Shared>> =
+            q.iter().map(|d| self.shared.get(d).cloned()).collect();
+        let reveals: Option<Vec<Reveal>> =
+            q.iter().map(|d| self.reveals.get(d).cloned()).collect();
+        let output = match (shares, reveals) {
+            (Some(shares), Some(reveals)) if self.params.pedersen => {
+                Some(self.finalize_pedersen(&shares, &reveals))
+            }
+            (Some(shares), _) if !self.params.pedersen => {
+                Some(self.finalize(&shares))
+            }
+            _ => None,
+        };
+        match output {
+            Some(Ok(output)) => {
+                self.output = Some(output.clone());
+                vec![Outgoing::Output(output)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /* Respond to a message from node `sender`.
+    Shared messages are only accepted from this node,
+    and signed messages only from their signer. */
+    pub fn handle(&mut self, sender: u32, msg: Message) -> Vec<Outgoing> {
+        let broadcast = |msg| vec![Outgoing::Broadcast(msg)];
+        match msg {
+            Message::Shared(shared) if sender == self.i => {
+                let mut res = match self.shared(&shared) {
+                    Some(SharedAction::Send(send)) => {
+                        broadcast(Message::Send(send))
+                    }
+                    Some(SharedAction::Delay) | None => Vec::new(),
+                };
+                res.extend(self.try_finalize());
+                res
+            }
+            Message::Send(send) if send.signer == sender => self
+                .send(send)
+                .map_or_else(Vec::new, |echo| broadcast(Message::Echo(echo))),
+            Message::Echo(echo) if echo.signer == sender => {
+                self.echo(echo).map_or_else(Vec::new, |ready| {
+                    broadcast(Message::Ready(ready))
+                })
+            }
+            Message::Ready(ready) if ready.signer == sender => {
+                let q = ready.msg.q.clone();
+                let mut res = Vec::new();
+                for action in self.ready(ready) {
+                    match action {
+                        ReadyAction::Ready(ready) => {
+                            res.extend(broadcast(Message::Ready(ready)))
+                        }
+                        ReadyAction::Complete => {
+                            self.q_final = Some(q.clone());
+                            res.extend(self.try_finalize())
+                        }
+                    }
+                }
+                res
+            }
+            // complaints are verifiable, so may be forwarded by any node
+            Message::Complaint(complaint) => {
+                self.complaint(&complaint);
+                Vec::new()
+            }
+            Message::Reveal(reveal) if reveal.session.d == sender => {
+                self.reveal(&reveal);
+                self.try_finalize()
+            }
+            Message::LeadCh(lead_ch) if lead_ch.signer == sender => {
+                match self.lead_ch(lead_ch) {
+                    Some(LeadChAction::LeadCh(lead_ch)) => {
+                        broadcast(Message::LeadCh(lead_ch))
+                    }
+                    Some(LeadChAction::Send(send)) => {
+                        broadcast(Message::Send(send))
+                    }
+                    Some(LeadChAction::Echo(echo)) => {
+                        broadcast(Message::Echo(echo))
+                    }
+                    Some(LeadChAction::Delay) | None => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /* Respond to a timeout while waiting for the current leader,
+    broadcasting a lead-ch message */
+    pub fn handle_timeout(&mut self) -> Vec<Outgoing> {
+        self.timeout()
+            .map(|lead_ch| Outgoing::Broadcast(Message::LeadCh(lead_ch)))
+            .into_iter()
+            .collect()
+    }
+
+    /* Finalize after receiving shared-output messages,
+    one for each dealer in the agreed set Q.
+    In pedersen mode, use `finalize_pedersen` instead. */
+    pub fn finalize(&self, shares: &[Shared]) -> Result<DkgOutput, Error> {
+        if self.params.pedersen {
+            return self.finalize_pedersen(shares, &[]);
+        }
+        let commitments = shares.iter().map(|s| s.C.clone()).collect();
+        self.finalize_commitments(shares, commitments)
+    }
+
+    /* Finalize in pedersen mode,
+    with a reveal message from the dealer of each shared-output message.
+    The blinding is removed from each dealer commitment,
+    so the public key is only revealed once the dealers are agreed. */
+    pub fn finalize_pedersen(
+        &self,
+        shares: &[Shared],
+        reveals: &[Reveal],
+    ) -> Result<DkgOutput, Error> {
+        let commitments = shares
+            .iter()
+            .map(|shared| {
+                let params = self.params.vss_params(shared.d);
+                reveals
+                    .iter()
+                    .find(|reveal| reveal.session.d == shared.d)
+                    .filter(|reveal| reveal.verify(&params, &shared.C))
+                    .map(|reveal| Arc::new(reveal.commitment()))
+                    .ok_or(Error::Reveal(shared.d))
+            })
+            .collect::<Result<_, _>>()?;
+        self.finalize_commitments(shares, commitments)
+    }
+
+    /* Finalize with the commitment of each dealer,
+    from which the public key shares are computed */
+    fn finalize_commitments(
+        &self,
+        shares: &[Shared],
+        commitments: Vec<Arc<poly::Public>>,
+    ) -> Result<DkgOutput, Error> {
+        let w_i = self.params.w[self.i as usize] as usize;
+        let mut dealers = BTreeSet::new();
+        for shared in shares.iter() {
+            if shared.session != self.params.session_id() {
+                return Err(Error::Session(shared.d));
+            }
+            if !dealers.insert(shared.d) {
+                return Err(Error::DuplicateDealer(shared.d));
+            }
+            if shared.s_id.len() != w_i {
+                return Err(Error::ShareCount(shared.d));
+            }
+        }
+        let C = commitments
+            .into_iter()
+            .reduce(|x, y| poly::add_public(&x, &y).into())
+            .ok_or(Error::NoShares)?;
+        let C = Arc::try_unwrap(C).unwrap_or_else(|C| (*C).clone());
+        let shares = (0..w_i)
+            .map(|k| shares.iter().map(|s| s.s_id[k]).sum())
+            .collect();
+        let pubkey = C[0][0];
+        let W = self.params.total_weight();
+        let omega = fft::domain(W as usize).group_gen;
+        let pubkey_shares = (0..W)
+            .map(|k| poly::public_share(&C, omega.pow([k as u64])))
+            .collect();
+        let share_index = self.params.share_indexes()[self.i as usize];
+        Ok(DkgOutput {
+            C,
+            i: self.i,
+            pubkey,
+            pubkey_shares,
+            share_index,
+            shares,
+        })
+    }
+}
+
+impl Encode for SessionId {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.epoch.encode(writer)?;
+        self.tau.encode(writer)
+    }
+}
+
+impl Decode for SessionId {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let epoch = u64::decode(reader)?;
+        let tau = u32::decode(reader)?;
+        Ok(SessionId { epoch, tau })
+    }
+}
+
+impl Encode for Complaint {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.accusation.encode(writer)
+    }
+}
+
+impl Decode for Complaint {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let accusation = Accusation::decode(reader)?;
+        Ok(Complaint { accusation })
+    }
+}
+
+impl Encode for Echo {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.L.encode(writer)?;
+        self.q.encode(writer)
+    }
+}
+
+impl Decode for Echo {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let L = u32::decode(reader)?;
+        let q = BTreeSet::decode(reader)?;
+        Ok(Echo { session, L, q })
+    }
+}
+
+impl Encode for Ready {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.L.encode(writer)?;
+        self.q.encode(writer)
+    }
+}
+
+impl Decode for Ready {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let L = u32::decode(reader)?;
+        let q = BTreeSet::decode(reader)?;
+        Ok(Ready { session, L, q })
+    }
+}
+
+impl Encode for Send {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.L.encode(writer)?;
+        self.q.encode(writer)?;
+        self.j.encode(writer)
+    }
+}
+
+impl Decode for Send {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let L = u32::decode(reader)?;
+        let q = BTreeSet::decode(reader)?;
+        let j = Justification::decode(reader)?;
+        Ok(Send { session, L, q, j })
+    }
+}
+
+impl Encode for LeadCh {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.L.encode(writer)?;
+        self.q.encode(writer)?;
+        self.j.encode(writer)
+    }
+}
+
+impl Decode for LeadCh {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let L = u32::decode(reader)?;
+        let q = BTreeSet::decode(reader)?;
+        let j = Justification::decode(reader)?;
+        Ok(LeadCh { session, L, q, j })
+    }
+}
+
+impl Encode for VssReady {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.d.encode(writer)?;
+        self.C_hash.encode(writer)
+    }
+}
+
+impl Decode for VssReady {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let d = u32::decode(reader)?;
+        let C_hash = <[u8; 32]>::decode(reader)?;
+        Ok(VssReady { session, d, C_hash })
+    }
+}
+
+impl Encode for Proof {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.d.encode(writer)?;
+        self.C_hash.encode(writer)?;
+        self.R.encode(writer)
+    }
+}
+
+impl Decode for Proof {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let d = u32::decode(reader)?;
+        let C_hash = <[u8; 32]>::decode(reader)?;
+        let R = Vec::decode(reader)?;
+        Ok(Proof { d, C_hash, R })
+    }
+}
+
+// certificates are encoded with a tag for the variant
+impl Encode for Certificate {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            Certificate::Echo(M) => {
+                0u8.encode(writer)?;
+                M.encode(writer)
+            }
+            Certificate::Ready(M) => {
+                1u8.encode(writer)?;
+                M.encode(writer)
+            }
+        }
+    }
+}
+
+impl Decode for Certificate {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        match u8::decode(reader)? {
+            0 => Ok(Certificate::Echo(Vec::decode(reader)?)),
+            1 => Ok(Certificate::Ready(Vec::decode(reader)?)),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+// justifications are encoded with a tag for the variant
+impl Encode for Justification {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            Justification::R(R) => {
+                0u8.encode(writer)?;
+                R.encode(writer)
+            }
+            Justification::M(M) => {
+                1u8.encode(writer)?;
+                M.encode(writer)
+            }
+        }
+    }
+}
+
+impl Decode for Justification {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        match u8::decode(reader)? {
+            0 => Ok(Justification::R(Vec::decode(reader)?)),
+            1 => Ok(Justification::M(Certificate::decode(reader)?)),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+/* Signatures are not verified when decoding,
+only when the message is handled */
+impl<M: Encode> Encode for Signed<M> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.msg.encode(writer)?;
+        self.signer.encode(writer)?;
+        self.sig.encode(writer)
+    }
+}
+
+impl<M: Decode> Decode for Signed<M> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let msg = M::decode(reader)?;
+        let signer = u32::decode(reader)?;
+        let sig = G2Affine::decode(reader)?;
+        Ok(Signed { msg, signer, sig })
+    }
+}
+
+impl Encode for Shared {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.C.encode(writer)?;
+        self.d.encode(writer)?;
+        self.s_id.encode(writer)?;
+        self.s_hat_id.encode(writer)?;
+        self.R.encode(writer)
+    }
+}
+
+impl Decode for Shared {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let d = u32::decode(reader)?;
+        let s_id = Vec::decode(reader)?;
+        let s_hat_id = Vec::decode(reader)?;
+        let R = Vec::decode(reader)?;
+        Ok(Shared {
+            session,
+            C,
+            d,
+            s_id,
+            s_hat_id,
+            R,
+        })
+    }
+}
+
+// dkg outputs are encoded with a version prefix
+impl Encode for DkgOutput {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_version(writer)?;
+        self.C.encode(writer)?;
+        self.i.encode(writer)?;
+        self.pubkey.encode(writer)?;
+        self.pubkey_shares.encode(writer)?;
+        self.share_index.encode(writer)?;
+        self.shares.encode(writer)
+    }
+}
+
+impl Decode for DkgOutput {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        decode_version(reader)?;
+        let C = Vec::decode(reader)?;
+        let i = u32::decode(reader)?;
+        let pubkey = ark_bls12_381::G1Affine::decode(reader)?;
+        let pubkey_shares = Vec::decode(reader)?;
+        let share_index = usize::decode(reader)?;
+        let shares = Vec::decode(reader)?;
+        Ok(DkgOutput {
+            C,
+            i,
+            pubkey,
+            pubkey_shares,
+            share_index,
+            shares,
+        })
+    }
+}
+
+impl Encode for Params {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.ek.encode(writer)?;
+        self.epoch.encode(writer)?;
+        self.f.encode(writer)?;
+        self.l.encode(writer)?;
+        self.pedersen.encode(writer)?;
+        self.pubkeys.encode(writer)?;
+        self.t.encode(writer)?;
+        self.tau.encode(writer)?;
+        self.w.encode(writer)
+    }
+}
+
+impl Decode for Params {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let ek: Vec<ark_bls12_381::G1Affine> = Vec::decode(reader)?;
+        let epoch = u64::decode(reader)?;
+        let f = u32::decode(reader)?;
+        let l = u32::decode(reader)?;
+        let pedersen = bool::decode(reader)?;
+        let pubkeys: Vec<G1Affine> = Vec::decode(reader)?;
+        let t = u32::decode(reader)?;
+        let tau = u32::decode(reader)?;
+        let w: Vec<u32> = Vec::decode(reader)?;
+        if pubkeys.len() != w.len() || (!ek.is_empty() && ek.len() != w.len()) {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Params {
+            ek,
+            epoch,
+            f,
+            l,
+            pedersen,
+            pubkeys,
+            t,
+            tau,
+            w,
+        })
+    }
 }
 
-/* Finalize after receiving shared-output messages */
-pub fn finalize(shares: &[Shared]) -> (poly::Public, Scalar) {
-    let s_i: Scalar = shares.iter().map(|s| s.s_id).sum();
-    let C = shares
-        .iter()
-        .map(|s| s.C.clone())
-        .reduce(|x, y| poly::add_public(&x, &y).into())
-        .unwrap();
-    let C = Rc::try_unwrap(C).unwrap();
-    (C, s_i)
+/* Contexts are encoded with a version prefix.
+Note that the encoding includes the signing key and the shares of the node,
+so snapshots must be stored as securely as the key itself. */
+impl Encode for Context {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_version(writer)?;
+        self.params.encode(writer)?;
+        self.i.encode(writer)?;
+        self.sk.encode(writer)?;
+        self.L.encode(writer)?;
+        self.lc.encode(writer)?;
+        self.lc_L.encode(writer)?;
+        self.e.encode(writer)?;
+        self.r.encode(writer)?;
+        self.M_echo.encode(writer)?;
+        self.M_ready.encode(writer)?;
+        self.q_bar.encode(writer)?;
+        self.m_bar.encode(writer)?;
+        self.q_hat.encode(writer)?;
+        self.r_hat.encode(writer)?;
+        self.shared.encode(writer)?;
+        self.q_final.encode(writer)?;
+        self.output.encode(writer)?;
+        self.complaints.encode(writer)?;
+        self.reveals.encode(writer)?;
+        self.echoed.encode(writer)?;
+        self.pending_sends.encode(writer)
+    }
+}
+
+impl Decode for Context {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        decode_version(reader)?;
+        let params = Params::decode(reader)?;
+        let i = u32::decode(reader)?;
+        if i >= params.n() {
+            return Err(SerializationError::InvalidData);
+        }
+        let sk = bls12_381::Scalar::decode(reader)?;
+        let mut context = Context::init(params, i, sk);
+        context.L = u32::decode(reader)?;
+        context.lc = HashMap::decode(reader)?;
+        context.lc_L = u32::decode(reader)?;
+        context.e = HashMap::decode(reader)?;
+        context.r = HashMap::decode(reader)?;
+        context.M_echo = HashMap::decode(reader)?;
+        context.M_ready = HashMap::decode(reader)?;
+        context.q_bar = BTreeSet::decode(reader)?;
+        context.m_bar = Option::decode(reader)?;
+        context.q_hat = BTreeSet::decode(reader)?;
+        context.r_hat = BTreeMap::decode(reader)?;
+        context.shared = BTreeMap::decode(reader)?;
+        context.q_final = Option::decode(reader)?;
+        context.output = Option::decode(reader)?;
+        context.complaints = BTreeMap::decode(reader)?;
+        context.reveals = BTreeMap::decode(reader)?;
+        context.echoed = BTreeMap::decode(reader)?;
+        context.pending_sends = BTreeMap::decode(reader)?;
+        Ok(context)
+    }
+}
+
+// messages are encoded with a tag for the variant
+impl Encode for Message {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            Message::Shared(shared) => {
+                0u8.encode(writer)?;
+                shared.encode(writer)
+            }
+            Message::Send(send) => {
+                1u8.encode(writer)?;
+                send.encode(writer)
+            }
+            Message::Echo(echo) => {
+                2u8.encode(writer)?;
+                echo.encode(writer)
+            }
+            Message::Ready(ready) => {
+                3u8.encode(writer)?;
+                ready.encode(writer)
+            }
+            Message::LeadCh(lead_ch) => {
+                4u8.encode(writer)?;
+                lead_ch.encode(writer)
+            }
+            Message::Complaint(complaint) => {
+                5u8.encode(writer)?;
+                complaint.encode(writer)
+            }
+            Message::Reveal(reveal) => {
+                6u8.encode(writer)?;
+                reveal.encode(writer)
+            }
+        }
+    }
 }
+
+impl Decode for Message {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        match u8::decode(reader)? {
+            0 => Ok(Message::Shared(Shared::decode(reader)?)),
+            1 => Ok(Message::Send(Signed::decode(reader)?)),
+            2 => Ok(Message::Echo(Signed::decode(reader)?)),
+            3 => Ok(Message::Ready(Signed::decode(reader)?)),
+            4 => Ok(Message::LeadCh(Signed::decode(reader)?)),
+            5 => Ok(Message::Complaint(Complaint::decode(reader)?)),
+            6 => Ok(Message::Reveal(Reveal::decode(reader)?)),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+impl_canonical!(
+    SessionId,
+    Echo,
+    Ready,
+    Send,
+    LeadCh,
+    VssReady,
+    Complaint,
+    Proof,
+    Certificate,
+    Justification,
+    Signed<Echo>,
+    Signed<Ready>,
+    Signed<Send>,
+    Signed<LeadCh>,
+    Signed<VssReady>,
+    Shared,
+    Message,
+    DkgOutput,
+    Params,
+    Context,
+);
+
+// protocol state and messages can be sent and shared between threads
+const _: fn() = || {
+    protocol::assert_send_sync::<Complaint>();
+    protocol::assert_send_sync::<Context>();
+    protocol::assert_send_sync::<DkgOutput>();
+    protocol::assert_send_sync::<Message>();
+    protocol::assert_send_sync::<Outgoing>();
+    protocol::assert_send_sync::<Params>();
+    protocol::assert_send_sync::<Shared>();
+    protocol::assert_send_sync::<Signed<Echo>>();
+    protocol::assert_send_sync::<Signed<LeadCh>>();
+    protocol::assert_send_sync::<Signed<Ready>>();
+    protocol::assert_send_sync::<Signed<Send>>();
+    protocol::assert_send_sync::<Signed<VssReady>>();
+};
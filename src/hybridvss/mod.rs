@@ -3,4 +3,4 @@ mod params;
 pub mod rec;
 pub mod sh;
 
-pub use params::Params;
+pub use params::{Error, Params, SessionId};
@@ -1,28 +1,124 @@
+use crate::poly;
+use crate::serialization::{Decode, Encode};
+use crate::transcript::Transcript;
+
+use ark_bls12_381::G1Affine;
+use ark_serialize::{Read, SerializationError, Write};
 use rand::Rng;
 
+/* The identifier of a hybridvss session,
+which is bound into every message.
+Messages from other sessions are rejected. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SessionId {
+    pub epoch: u64, // the epoch of the session
+    pub d: u32,     // dealer index
+    pub tau: u32,   // the session counter within the epoch
+}
+
+// Errors that can occur when building parameters
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("expected an encryption key for each of the {0} participants")]
+    EncryptionKeys(u32),
+    #[error("expected a signing key for each of the {0} participants")]
+    PublicKeys(u32),
+}
+
 // HybridVss parameters
 #[derive(Clone)]
 pub struct Params {
-    pub d: u32,      // dealer index
-    pub f: u32,      // failure threshold
+    pub d: u32, // dealer index
+    /* The encryption key of each participant.
+    If set, point-to-point messages are encrypted to the receiver.
+    Should be set with `with_encryption_keys`. */
+    pub ek: Vec<G1Affine>,
+    pub epoch: u64, // the epoch of the session
+    pub f: u32,     // failure threshold
+    /* If set, the dealer commits with pedersen commitments,
+    so that the commitment does not reveal the secret */
+    pub pedersen: bool,
+    // the signing public key of each participant
+    pub pubkeys: Vec<bls12_381::G1Affine>,
     pub t: u32,      // threshold
+    pub tau: u32,    // the session counter within the epoch
     pub w: Vec<u32>, // weight of each participant
 }
 
 impl Params {
-    pub fn new(d: u32, f: u32, t: u32, w: Vec<u32>) -> Self {
-        Params { d, f, t, w }
+    /* Fails unless there is exactly one signing key
+    for each participant. */
+    pub fn new(
+        epoch: u64,
+        tau: u32,
+        d: u32,
+        f: u32,
+        t: u32,
+        w: Vec<u32>,
+        pubkeys: Vec<bls12_381::G1Affine>,
+    ) -> Result<Self, Error> {
+        if pubkeys.len() != w.len() {
+            return Err(Error::PublicKeys(w.len() as u32));
+        }
+        Ok(Params {
+            d,
+            ek: Vec::new(),
+            epoch,
+            f,
+            pedersen: false,
+            pubkeys,
+            t,
+            tau,
+            w,
+        })
+    }
+
+    /* set the encryption key of each participant,
+    so that point-to-point messages are sealed.
+    Fails unless there is exactly one key for each participant. */
+    pub fn with_encryption_keys(
+        self,
+        ek: Vec<G1Affine>,
+    ) -> Result<Self, Error> {
+        if ek.len() != self.w.len() {
+            return Err(Error::EncryptionKeys(self.n()));
+        }
+        Ok(Params { ek, ..self })
     }
 
     // initialize with random values for `d`
     pub fn random_dealer<R: Rng>(
+        epoch: u64,
+        tau: u32,
         f: u32,
         t: u32,
         w: Vec<u32>,
+        pubkeys: Vec<bls12_381::G1Affine>,
         rng: &mut R,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let d = rng.gen_range(0, w.len() as u32);
-        Self::new(d, f, t, w)
+        Self::new(epoch, tau, d, f, t, w, pubkeys)
+    }
+
+    // the identifier of the session
+    pub fn session_id(&self) -> SessionId {
+        SessionId {
+            epoch: self.epoch,
+            d: self.d,
+            tau: self.tau,
+        }
+    }
+
+    /* start a transcript bound to the protocol, session,
+    dealer and threshold parameters */
+    pub fn transcript(&self) -> Transcript {
+        let mut transcript = Transcript::new(b"hybridvss");
+        transcript.append_encode(b"session", &self.session_id());
+        transcript.append_u32(b"f", self.f);
+        transcript.append_u32(b"t", self.t);
+        transcript.append_encode(b"w", &self.w);
+        transcript.append_encode(b"pedersen", &self.pedersen);
+        transcript
     }
 
     // return the number of participants in the setup
@@ -30,7 +126,84 @@ impl Params {
         self.w.len() as u32
     }
 
+    // whether point-to-point messages are encrypted
+    pub fn sealed(&self) -> bool {
+        !self.ek.is_empty()
+    }
+
     pub fn total_weight(&self) -> u32 {
         self.w.iter().sum()
     }
+
+    // the index of the first domain point of each participant
+    pub fn share_indexes(&self) -> Vec<usize> {
+        poly::share_indexes(&self.w)
+    }
+}
+
+impl Encode for Params {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.d.encode(writer)?;
+        self.ek.encode(writer)?;
+        self.epoch.encode(writer)?;
+        self.f.encode(writer)?;
+        self.pedersen.encode(writer)?;
+        self.pubkeys.encode(writer)?;
+        self.t.encode(writer)?;
+        self.tau.encode(writer)?;
+        self.w.encode(writer)
+    }
+}
+
+impl Decode for Params {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let d = u32::decode(reader)?;
+        let ek: Vec<G1Affine> = Vec::decode(reader)?;
+        let epoch = u64::decode(reader)?;
+        let f = u32::decode(reader)?;
+        let pedersen = bool::decode(reader)?;
+        let pubkeys: Vec<bls12_381::G1Affine> = Vec::decode(reader)?;
+        let t = u32::decode(reader)?;
+        let tau = u32::decode(reader)?;
+        let w: Vec<u32> = Vec::decode(reader)?;
+        if pubkeys.len() != w.len() || !ek.is_empty() && ek.len() != w.len() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Params {
+            d,
+            ek,
+            epoch,
+            f,
+            pedersen,
+            pubkeys,
+            t,
+            tau,
+            w,
+        })
+    }
 }
+
+impl Encode for SessionId {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.epoch.encode(writer)?;
+        self.d.encode(writer)?;
+        self.tau.encode(writer)
+    }
+}
+
+impl Decode for SessionId {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let epoch = u64::decode(reader)?;
+        let d = u32::decode(reader)?;
+        let tau = u32::decode(reader)?;
+        Ok(SessionId { epoch, d, tau })
+    }
+}
+
+impl_canonical!(Params, SessionId);
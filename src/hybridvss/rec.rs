@@ -1,28 +1,48 @@
 #![allow(clippy::many_single_char_names)]
 #![allow(non_snake_case)]
 
-use crate::poly;
+use crate::serialization::{decode_version, encode_version, Decode, Encode};
+use crate::{fft, poly, protocol};
 
 use ark_bls12_381::{Fr, G1Projective};
 use ark_ec::ProjectiveCurve;
 use ark_ff::Field;
 use ark_poly::{Polynomial, Radix2EvaluationDomain};
+use ark_serialize::{Read, SerializationError, Write};
 use num::Zero;
-use std::collections::HashSet;
+use std::collections::BTreeMap;
 
-use crate::hybridvss::params::Params;
+use crate::hybridvss::params::{Params, SessionId};
 
 type Scalar = Fr;
 
 pub struct Context {
     C: poly::Public,                    // the public polynomial
-    c: u32, // counter for `reconstruct-share` messages
+    c: u32, // total weight of the accepted `reconstruct-share` messages
     domain: Radix2EvaluationDomain<Fr>, // FFT domain (group_gen, log_size_of_group, size)
+    faulty: Vec<u32>, // the senders of wrong shares, found by robust decoding
     params: Params,
-    S: HashSet<(Scalar, Scalar)>, // set of node-index - share pairs.
-    s: Scalar,                    // the share for this node
+    /* If set, shares are not verified against the public polynomial,
+    and the secret is found by Reed-Solomon decoding instead */
+    robust: bool,
+    /* The accepted shares of each node, by sender.
+    Iterating in sender order makes the interpolated points deterministic. */
+    S: BTreeMap<u32, Vec<Scalar>>,
+    s: Vec<Scalar>, // the shares for this node, one per unit of weight
+    share_indexes: Vec<usize>, // index of the first domain point of each node
+    z: Option<Scalar>, // the reconstructed secret
 }
 
+/* A "reconstruct-share" message,
+containing one share for each unit of weight of the sender */
+#[derive(Clone)]
+pub struct ReconstructShare {
+    pub session: SessionId,
+    pub s: Vec<Scalar>,
+}
+
+pub type Outgoing = protocol::Outgoing<ReconstructShare, Scalar>;
+
 fn mul_g1proj(lhs: G1Projective, rhs: Scalar) -> G1Projective {
     let mut lhs = lhs;
     lhs *= rhs;
@@ -34,63 +54,259 @@ fn scalar_exp_u64(x: Scalar, y: u64) -> Scalar {
     x.pow([y])
 }
 
-// Scalar exponentiation by u32. `exp(x, y) = x^y`
-fn scalar_exp_u32(x: Scalar, y: u32) -> Scalar {
-    scalar_exp_u64(x, y.into())
-}
-
 impl Context {
     pub fn init(
         params: Params,
         C: poly::Public, // the public polynomial
         domain: Radix2EvaluationDomain<Fr>,
-        s: Scalar, // the share for this node
+        s: Vec<Scalar>, // the shares for this node
     ) -> Self {
         let c = 0;
-        let S = HashSet::new();
+        let S = BTreeMap::new();
+        let share_indexes = params.share_indexes();
         Context {
             C,
             c,
             domain,
+            faulty: Vec::new(),
             params,
+            robust: false,
             S,
             s,
+            share_indexes,
+            z: None,
         }
     }
 
-    pub fn reconstruct(&self) -> Scalar {
-        self.s
+    /* Initialize a context for robust reconstruction,
+    which accepts shares without verifying them */
+    pub fn init_robust(
+        params: Params,
+        C: poly::Public, // the public polynomial
+        domain: Radix2EvaluationDomain<Fr>,
+        s: Vec<Scalar>, // the shares for this node
+    ) -> Self {
+        let mut context = Context::init(params, C, domain, s);
+        context.robust = true;
+        context
+    }
+
+    // the reconstructed secret, once enough shares are accepted
+    pub fn reconstruct(&self) -> Option<Scalar> {
+        self.z
+    }
+
+    // the senders of wrong shares, once the secret is decoded
+    pub fn faulty(&self) -> &[u32] {
+        &self.faulty
+    }
+
+    // the total weight of the `f` heaviest nodes
+    fn faulty_weight(&self) -> u32 {
+        let mut w = self.params.w.clone();
+        w.sort_unstable_by(|x, y| y.cmp(x));
+        w.iter().take(self.params.f as usize).sum()
+    }
+
+    // the accepted points, in sender order
+    fn points(&self) -> impl Iterator<Item = (Scalar, Scalar)> + '_ {
+        self.S.iter().flat_map(move |(j, sigma_j)| {
+            self.domain_points(*j).into_iter().zip(sigma_j.clone())
+        })
+    }
+
+    // broadcast the shares of this node, starting reconstruction
+    pub fn broadcast_share(&self) -> Vec<Outgoing> {
+        let msg = ReconstructShare {
+            session: self.params.session_id(),
+            s: self.s.clone(),
+        };
+        vec![Outgoing::Broadcast(msg)]
+    }
+
+    // the domain points of the shares of node `m`
+    fn domain_points(&self, m: u32) -> Vec<Scalar> {
+        let w_m = self.params.w[m as usize];
+        (0..w_m as usize)
+            .map(|k| {
+                let k = self.share_indexes[m as usize] + k;
+                scalar_exp_u64(self.domain.group_gen, k as u64) // omega^k
+            })
+            .collect()
+    }
+
+    /* verify that `sigma` is the share at the domain point `x`,
+    ie. that `g * sigma` is the public polynomial evaluated at `(x, 0)` */
+    fn verify_share(&self, x: Scalar, sigma: Scalar) -> bool {
+        let lhs = mul_g1proj(G1Projective::prime_subgroup_generator(), sigma);
+        lhs.into_affine() == poly::public_share(&self.C, x)
     }
 
+    /* Respond to a "reconstruct-share" message from node `m`,
+    containing one share for each unit of weight of node `m`.
+    Each node is counted at most once, and only if all of its shares verify.
+    Once the verified shares reach weight t + 1,
+    the secret is interpolated from the first t + 1 points in sender order. */
     pub fn reconstruct_share(
         &mut self,
         m: u32,
-        sigma: Scalar,
+        sigma: &[Scalar],
     ) -> Option<Scalar> {
-        let lhs = mul_g1proj(G1Projective::prime_subgroup_generator(), sigma);
-        let rhs = (0..=self.params.t)
-            .map(|j| {
-                mul_g1proj(
-                    self.C[j as usize][0].into(),
-                    scalar_exp_u32(self.domain.group_gen, m * j), // omega^((k + m) * j)
-                )
+        if m >= self.params.n() || self.S.contains_key(&m) {
+            return None;
+        }
+        let xs = self.domain_points(m);
+        if sigma.len() != xs.len()
+            || !xs
+                .iter()
+                .zip(sigma.iter())
+                .all(|(x, sigma_k)| self.verify_share(*x, *sigma_k))
+        {
+            return None;
+        }
+        self.S.insert(m, sigma.to_vec());
+        self.c += self.params.w[m as usize];
+        if self.c <= self.params.t || self.z.is_some() {
+            return None;
+        }
+        // the points to use for lagrange interpolation
+        let points: Vec<(Scalar, Scalar)> =
+            self.points().take(self.params.t as usize + 1).collect();
+        let z_i = poly::interpolate_at(&points, Scalar::zero());
+        self.z = Some(z_i);
+        self.z
+    }
+
+    /* Respond to a "reconstruct-share" message from node `m`
+    without verifying its shares.
+    Up to `(N - t - 1) / 2` wrong points are corrected by Gao decoding,
+    where `N` is the total weight of the accepted messages.
+    A decoded polynomial is only accepted if it agrees with
+    at least t + 1 points more than the weight of the `f` heaviest nodes,
+    so that at least t + 1 of the points that agree are correct.
+    The senders of any points that disagree are recorded as faulty. */
+    pub fn reconstruct_share_unverified(
+        &mut self,
+        m: u32,
+        sigma: &[Scalar],
+    ) -> Option<Scalar> {
+        if m >= self.params.n()
+            || self.S.contains_key(&m)
+            || sigma.len() != self.params.w[m as usize] as usize
+        {
+            return None;
+        }
+        self.S.insert(m, sigma.to_vec());
+        self.c += self.params.w[m as usize];
+        let threshold = self.params.t + 1 + self.faulty_weight();
+        if self.c < threshold || self.z.is_some() {
+            return None;
+        }
+        let points: Vec<(Scalar, Scalar)> = self.points().collect();
+        let z = poly::gao_decode(&points, self.params.t as usize)?;
+        let correct = |(x, sigma): &(Scalar, Scalar)| z.evaluate(x) == *sigma;
+        if (points.iter().filter(|p| correct(p)).count() as u32) < threshold {
+            return None;
+        }
+        self.faulty = self
+            .S
+            .iter()
+            .filter(|(j, sigma_j)| {
+                let xs = self.domain_points(**j);
+                !xs.into_iter()
+                    .zip(sigma_j.iter().cloned())
+                    .all(|p| correct(&p))
             })
-            .sum::<G1Projective>();
-        if lhs == rhs {
-            let wm = scalar_exp_u32(self.domain.group_gen, m); // omega^m
-            self.S.insert((wm, sigma));
-            self.c += 1;
-            if self.c == self.params.t + 1 {
-                // the points to use for lagrange interpolation
-                let points = self.S.clone();
-                let z = poly::lagrange_interpolate(points);
-                let z_i = z.evaluate(&Scalar::zero());
-                Some(z_i)
-            } else {
-                None
-            }
+            .map(|(j, _)| *j)
+            .collect();
+        let z_i = z.evaluate(&Scalar::zero());
+        self.z = Some(z_i);
+        self.z
+    }
+
+    /* Handle a message from node `sender`,
+    outputting the secret once it is reconstructed */
+    pub fn handle(
+        &mut self,
+        sender: u32,
+        msg: ReconstructShare,
+    ) -> Vec<Outgoing> {
+        if msg.session != self.params.session_id() {
+            return Vec::new();
+        }
+        let z = if self.robust {
+            self.reconstruct_share_unverified(sender, &msg.s)
         } else {
-            None
+            self.reconstruct_share(sender, &msg.s)
+        };
+        match z {
+            Some(z) => vec![Outgoing::Output(z)],
+            None => Vec::new(),
         }
     }
 }
+
+/* Contexts are encoded with a version prefix.
+The domain and share indexes are recomputed from the parameters.
+Note that the encoding includes the shares of the node,
+so snapshots must be stored securely. */
+impl Encode for Context {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_version(writer)?;
+        self.params.encode(writer)?;
+        self.C.encode(writer)?;
+        self.s.encode(writer)?;
+        self.robust.encode(writer)?;
+        self.c.encode(writer)?;
+        self.S.encode(writer)?;
+        self.z.encode(writer)?;
+        self.faulty.encode(writer)
+    }
+}
+
+impl Decode for Context {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        decode_version(reader)?;
+        let params = Params::decode(reader)?;
+        let C = Vec::decode(reader)?;
+        let s = Vec::decode(reader)?;
+        let domain = fft::domain(params.total_weight() as usize);
+        let mut context = Context::init(params, C, domain, s);
+        context.robust = bool::decode(reader)?;
+        context.c = u32::decode(reader)?;
+        context.S = BTreeMap::decode(reader)?;
+        context.z = Option::decode(reader)?;
+        context.faulty = Vec::decode(reader)?;
+        Ok(context)
+    }
+}
+
+impl Encode for ReconstructShare {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.s.encode(writer)
+    }
+}
+
+impl Decode for ReconstructShare {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let s = Vec::decode(reader)?;
+        Ok(ReconstructShare { session, s })
+    }
+}
+
+impl_canonical!(Context, ReconstructShare);
+
+// protocol state can be sent and shared between threads
+const _: fn() = || {
+    protocol::assert_send_sync::<Context>();
+    protocol::assert_send_sync::<Params>();
+    protocol::assert_send_sync::<ReconstructShare>();
+};
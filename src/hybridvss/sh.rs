@@ -1,61 +1,137 @@
 #![allow(clippy::many_single_char_names)]
 #![allow(non_snake_case)]
 
-use crate::{fft, poly};
+use crate::serialization::{decode_version, encode_version, Decode, Encode};
+use crate::transcript::Transcript;
+use crate::{bls, ecies, fft, poly, protocol};
 
-use ark_bls12_381::{Fr, G1Affine};
-use ark_ff::Field;
-use ark_poly::{Polynomial, Radix2EvaluationDomain};
-use ark_serialize::CanonicalSerialize;
+use ark_bls12_381::{Fr, G1Affine, G1Projective};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{Field, PrimeField, UniformRand};
+use ark_poly::{Polynomial, Radix2EvaluationDomain, UVPolynomial};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write,
+};
+use bls12_381::G2Affine;
 use either::Either;
 use num::integer::div_ceil;
 use num::Zero;
-use std::collections::{HashMap, HashSet};
-use std::convert::TryInto;
-use std::rc::Rc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::hybridvss::params::Params;
+use crate::hybridvss::params::{Params, SessionId};
 
 type Scalar = Fr;
 
 pub struct Context {
     /* Map keyed by sha2-256 hashes of commitments.
-    The values of the map are pairs of node indexes and scalars */
-    pub A: HashMap<[u8; 32], HashSet<(Scalar, Scalar)>>,
+    The values of the map contain a set of points for each share of this node,
+    where each point is a pair of a domain point and a scalar */
+    pub A: HashMap<[u8; 32], Vec<HashSet<(Scalar, Scalar)>>>,
+    /* The blinding points for each commitment in pedersen mode,
+    laid out as in `A` */
+    pub A_hat: HashMap<[u8; 32], Vec<HashSet<(Scalar, Scalar)>>>,
+    /* The commitments for which this node has sent ready messages.
+    The keys of the map are sha2-256 hashes. */
+    pub B: HashMap<[u8; 32], Arc<poly::Public>>,
+    /* The dealer's reveal message in pedersen mode,
+    once this node has dealt */
+    pub reveal: Option<Reveal>,
+    // the decryption key of this node, if messages are sealed
+    pub dk: Option<Scalar>,
     /* Counters for `echo` messages.
     The keys of the map are sha2-256 hashes. */
     pub e: HashMap<[u8; 32], u32>,
-    pub i: u32, // index of this node in the setup
-    pub share_indexes: Vec<usize>,
+    /* The nodes whose `echo` messages have been counted.
+    The keys of the map are sha2-256 hashes. */
+    pub echo_senders: HashMap<[u8; 32], BTreeSet<u32>>,
+    pub h: Option<G1Affine>, // the pedersen generator, in pedersen mode
+    pub i: u32,              // index of this node in the setup
+    pub share_indexes: Vec<usize>, // index of the first domain point of each node
+    sk: bls12_381::Scalar,         // the signing key of this node
     pub domain: Radix2EvaluationDomain<Fr>, // FFT domain (group_gen, log_size_of_group, size)
+    pub params: Params,
     /* Counters for `ready` messages.
     The keys of the map are sha2-256 hashes. */
-    pub params: Params,
     pub r: HashMap<[u8; 32], u32>,
+    // the number of help requests answered for each node
+    pub help_count: HashMap<u32, u32>,
+    /* Valid accusations against the dealer, by accuser.
+    Each accusation carries the dealer's signature on the accused send message,
+    see `Accusation`. */
+    pub accusations: BTreeMap<u32, Accusation>,
+    /* The nodes whose `ready` messages have been counted.
+    The keys of the map are sha2-256 hashes. */
+    pub ready_senders: HashMap<[u8; 32], BTreeSet<u32>>,
 }
 
 #[derive(Clone)]
-/* An "echo" message */
+/* An "echo" message.
+`alpha[a][b]` is the evaluation of the sender's `a`th share
+at the receiver's `b`th domain point. */
 pub struct Echo {
-    pub C: Rc<poly::Public>,
-    pub alpha: Vec<Scalar>,
+    pub session: SessionId,
+    pub C: Arc<poly::Public>,
+    pub alpha: Vec<Vec<Scalar>>,
+    // the blinding points in pedersen mode, laid out as in `alpha`
+    pub alpha_hat: Vec<Vec<Scalar>>,
 }
 
 #[derive(Clone, Debug)]
-/* A "ready" message */
+/* A "ready" message.
+`alpha[a][b]` is the evaluation of the sender's `a`th share
+at the receiver's `b`th domain point. */
 pub struct Ready {
-    pub C: Rc<poly::Public>,
-    pub alpha: Vec<Scalar>,
+    pub session: SessionId,
+    pub C: Arc<poly::Public>,
+    pub alpha: Vec<Vec<Scalar>>,
+    // the blinding points in pedersen mode, laid out as in `alpha`
+    pub alpha_hat: Vec<Vec<Scalar>>,
+}
+
+/* A single point from an "echo" or "ready" message from node `sender`,
+the evaluation `alpha[a][b]` of the sender's `a`th share
+at the receiver's `b`th domain point.
+`alpha_hat` is the blinding point in pedersen mode, and zero otherwise. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub sender: u32,
+    pub a: usize,
+    pub b: usize,
+    pub alpha: Scalar,
+    pub alpha_hat: Scalar,
+}
+
+// the sender and points of an echo or ready message
+struct MessagePoints<'a> {
+    m: u32,
+    session: &'a SessionId,
+    C: &'a Arc<poly::Public>,
+    alpha: &'a [Vec<Scalar>],
+    alpha_hat: &'a [Vec<Scalar>],
 }
 
 pub type EchoResponse = Option<Vec<Ready>>;
 
-pub type ReadyResponse = Option<Either<Vec<Ready>, Shared>>;
+/* The response to a "ready" message.
+A single ready message may cross both the ready and shared thresholds,
+in which case the ready messages precede the shared message. */
+pub type ReadyResponse = Vec<Either<Vec<Ready>, Shared>>;
 
-/* A "send" message */
+/* A "send" message,
+with one share for each unit of weight of the receiver.
+Signed by the dealer, so that an accusation against the send message
+is attributable to the dealer. */
+#[derive(Clone)]
 pub struct Send {
-    pub C: Rc<poly::Public>,
-    pub a: poly::Share,
+    pub session: SessionId,
+    pub C: Arc<poly::Public>,
+    pub a: Vec<poly::Share>,
+    // the blinding shares in pedersen mode, one for each share
+    pub a_hat: Vec<poly::Share>,
+    // the dealer's signature on the send message, see `send_signed`
+    pub sig: G2Affine,
 }
 
 pub type SendResponse = Option<Vec<Echo>>;
@@ -67,10 +143,270 @@ pub struct Share {
 
 pub type ShareResponse = Vec<Send>;
 
-/* A "shared" message */
+/* A "shared" message,
+with one share for each unit of weight of this node */
+#[derive(Clone)]
 pub struct Shared {
-    pub C: Rc<poly::Public>,
-    pub s: Scalar,
+    pub session: SessionId,
+    pub C: Arc<poly::Public>,
+    pub s: Vec<Scalar>,
+    // the blinding shares in pedersen mode, one for each share
+    pub s_hat: Vec<Scalar>,
+}
+
+/* An accusation against the dealer by node `accuser`,
+showing that the send message for the accuser is invalid.
+The shares of a send message are hashed separately (see `hash_send`),
+so the accusation reveals only the first invalid share `k`,
+and the hashes of the other shares.
+If the send message has the wrong number of shares,
+no share is revealed.
+
+The accusation carries the dealer's signature on the accused send message,
+so that it shows both that the send message is inconsistent
+with the commitment and that the dealer sent it.
+Accusations can therefore be verified by any third party,
+and are evidence against the dealer. */
+#[derive(Clone)]
+pub struct Accusation {
+    pub session: SessionId,
+    pub accuser: u32,
+    pub C: Arc<poly::Public>,
+    pub share_hashes: Vec<[u8; 32]>, // the hash of each share
+    pub k: u32,                      // the index of the invalid share
+    pub a_k: Option<poly::Share>,    // the invalid share, if any
+    // the invalid blinding share in pedersen mode, if any
+    pub a_hat_k: Option<poly::Share>,
+    // the dealer's signature on the accused send message
+    pub sig: G2Affine,
+}
+
+impl Accusation {
+    // the hash of the accused send message
+    pub fn send_hash(&self, params: &Params) -> [u8; 32] {
+        hash_shares(params, &self.C, &self.share_hashes)
+    }
+
+    /* verify the accusation against the parameters of the dealing.
+    The accused send message must be signed by the dealer. */
+    pub fn verify(&self, params: &Params) -> bool {
+        if self.accuser >= params.n()
+            || self.session != params.session_id()
+            || !verify_send_signature(
+                params,
+                self.accuser,
+                &self.send_hash(params),
+                &self.sig,
+            )
+        {
+            return false;
+        }
+        let k = self.k as usize;
+        let (a_k, a_hat_k) = (self.a_k.as_ref(), self.a_hat_k.as_ref());
+        if self.share_hashes.len() != params.w[self.accuser as usize] as usize
+            || !poly::shaped(&self.C, params.t)
+        {
+            return true;
+        }
+        let h = if params.pedersen {
+            Some(poly::pedersen_generator())
+        } else {
+            None
+        };
+        match domain_points(params, self.accuser).get(k) {
+            Some(x) => {
+                self.share_hashes[k] == hash_share(a_k, a_hat_k)
+                    && !verify_share_at(&self.C, *x, a_k, a_hat_k, h)
+            }
+            None => false,
+        }
+    }
+}
+
+/* A "reveal" message from the dealer in pedersen mode,
+with feldman commitments `F` to the coefficients of the secret polynomial
+at `(x, 0)`, and the coefficients `r` of the blinding polynomial at `(x, 0)`.
+It should only be published once the commitment no longer needs to hide
+the secret, eg. once hybriddkg has agreed on the set of dealers.
+
+Any observer can check a reveal against the pedersen commitment,
+so that every node accepts or rejects the same reveal:
+removing the blinding `r` from the commitment must give `F`,
+and the dealer proves knowledge of the discrete logs of `F`.
+Since the discrete log of the pedersen generator is unknown,
+the dealer cannot know the discrete logs of `F` for any other `r`.
+The proof is a schnorr proof of knowledge for a combination of `F`,
+weighted by powers of the challenge. */
+#[derive(Clone)]
+pub struct Reveal {
+    pub session: SessionId,
+    pub F: Vec<G1Affine>,
+    pub r: Vec<Scalar>,
+    pub R: G1Affine, // the commitment of the proof of knowledge
+    pub z: Scalar,   // the response of the proof of knowledge
+}
+
+impl Reveal {
+    /* The challenge for the proof of knowledge,
+    bound to the session, dealer and threshold parameters */
+    fn challenge(params: &Params, F: &[G1Affine], R: &G1Affine) -> Scalar {
+        let mut transcript = params.transcript();
+        transcript.append_encode(b"reveal-F", &F.to_vec());
+        transcript.append_encode(b"reveal-R", R);
+        Scalar::from_le_bytes_mod_order(&transcript.finalize())
+    }
+
+    // the powers `c, c^2, ..., c^n` of the challenge `c`
+    fn weights(c: Scalar, n: usize) -> Vec<Scalar> {
+        std::iter::successors(Some(c), |c_j| Some(*c_j * c))
+            .take(n)
+            .collect()
+    }
+
+    /* Reveal the secret polynomial `phi` and the blinding polynomial
+    `phi_hat` at `(x, 0)`, with `n` coefficients each, as the dealer */
+    fn prove<R: rand::Rng + Sized>(
+        params: &Params,
+        phi: &poly::Univar,
+        phi_hat: &poly::Univar,
+        n: usize,
+        rng: &mut R,
+    ) -> Self {
+        let g = G1Affine::prime_subgroup_generator();
+        let coeffs = |p: &poly::Univar| -> Vec<Scalar> {
+            (0..n)
+                .map(|j| {
+                    p.coeffs().get(j).cloned().unwrap_or_else(Scalar::zero)
+                })
+                .collect()
+        };
+        let c = coeffs(phi);
+        let F: Vec<G1Affine> =
+            c.iter().map(|c_j| g.mul(*c_j).into_affine()).collect();
+        let k = Scalar::rand(rng);
+        let R = g.mul(k).into_affine();
+        let weights = Self::weights(Self::challenge(params, &F, &R), n);
+        let z = k + c
+            .iter()
+            .zip(weights.iter())
+            .map(|(c_j, weight)| *c_j * weight)
+            .sum::<Scalar>();
+        Reveal {
+            session: params.session_id(),
+            F,
+            r: coeffs(phi_hat),
+            R,
+            z,
+        }
+    }
+
+    /* Verify the reveal against the pedersen commitment `C`.
+    Does not depend on the shares of any node. */
+    pub fn verify(&self, params: &Params, C: &poly::Public) -> bool {
+        if !params.pedersen
+            || self.session != params.session_id()
+            || !poly::shaped(C, params.t)
+            || self.F.len() != C.len()
+            || self.r.len() != C.len()
+        {
+            return false;
+        }
+        let g = G1Affine::prime_subgroup_generator();
+        let h = poly::pedersen_generator();
+        let weights =
+            Self::weights(Self::challenge(params, &self.F, &self.R), C.len());
+        let F_c = self
+            .F
+            .iter()
+            .zip(weights.iter())
+            .fold(G1Projective::zero(), |sum, (F_j, weight)| {
+                sum + F_j.mul(*weight)
+            });
+        poly::unblind(C, &self.r, h) == self.commitment()
+            && g.mul(self.z) == self.R.into_projective() + F_c
+    }
+
+    /* The commitment to the secret polynomial at `(x, 0)`,
+    with a single element in each row,
+    so it can be evaluated with `public_share`.
+    Only meaningful once the reveal has been verified. */
+    pub fn commitment(&self) -> poly::Public {
+        self.F.iter().map(|F_j| vec![*F_j]).collect()
+    }
+}
+
+/* A "help" message,
+requesting the ready messages that peers have sent to this node */
+#[derive(Clone)]
+pub struct Help {
+    pub session: SessionId,
+}
+
+/* A point-to-point message, encrypted to the receiver's encryption key.
+The session, sender and receiver are bound as associated data,
+so that a sealed message cannot be redirected.
+The sender signs the associated data and ciphertext with its signing key,
+so that a sealed message cannot be forged by another node.
+The decryption key is only used for decryption. */
+#[derive(Clone)]
+pub struct Sealed {
+    pub session: SessionId,
+    pub c: ecies::Ciphertext,
+    pub sig: G2Affine,
+}
+
+/* The maximum number of help requests from each node
+that are answered.
+Each answer recomputes this node's ready message for the requester,
+for every commitment that this node has sent ready messages for,
+so the limit bounds the work that a single node can cause.
+A few requests suffice to recover from transient message loss. */
+pub const HELP_LIMIT: u32 = 3;
+
+/* A message of the hybridvss-sh protocol */
+#[derive(Clone)]
+pub enum Message {
+    Send(Send),
+    Echo(Echo),
+    Ready(Ready),
+    Accusation(Accusation),
+    Help(Help),
+    Sealed(Sealed),
+}
+
+/* An event produced in response to a message.
+Send, echo and ready messages are addressed to a single node,
+and are sealed if the parameters include encryption keys,
+accusations and help requests are broadcast,
+and the output is a "shared" message. */
+pub type Outgoing = protocol::Outgoing<Message, Shared>;
+
+// address the `j`th message to node `j`
+fn to_each<M, F>(msgs: Vec<M>, f: F) -> Vec<Outgoing>
+where
+    F: Fn(M) -> Message,
+{
+    msgs.into_iter()
+        .enumerate()
+        .map(|(j, msg)| Outgoing::To(j as u32, f(msg)))
+        .collect()
+}
+
+// the associated data for a sealed message from `sender` to `receiver`
+fn sealed_aad(session: &SessionId, sender: u32, receiver: u32) -> [u8; 32] {
+    let mut transcript = Transcript::new(b"hybridvss-sealed");
+    transcript.append_encode(b"session", session);
+    transcript.append_u32(b"sender", sender);
+    transcript.append_u32(b"receiver", receiver);
+    transcript.finalize()
+}
+
+// the hash signed by the sender of a sealed message
+fn sealed_signed(aad: &[u8; 32], c: &ecies::Ciphertext) -> [u8; 32] {
+    let mut transcript = Transcript::new(b"hybridvss-sealed-signature");
+    transcript.append_encode(b"aad", aad);
+    transcript.append_encode(b"c", c);
+    transcript.finalize()
 }
 
 // Scalar exponentiation by u64. `exp(x, y) = x^y`
@@ -78,28 +414,177 @@ fn scalar_exp_u64(x: Scalar, y: u64) -> Scalar {
     x.pow([u64::to_le(y)])
 }
 
-// Scalar exponentiation by u64. `exp(x, y) = x^y`
-fn scalar_exp_u32(x: Scalar, y: u32) -> Scalar {
-    scalar_exp_u64(x, y.into())
-}
-
-// compute the sha2-256 hash of a public polynomial
-fn hash_public_poly(C: &poly::Public) -> [u8; 32] {
-    use digest::Digest;
-    let mut hasher = sha2::Sha256::new();
-    C.iter().for_each(|coeffs| {
-        coeffs.iter().for_each(|coeff| {
-            let coeff_bytes = compress_G1Affine(coeff);
-            hasher.update(coeff_bytes)
+/* compute the sha2-256 hash of a public polynomial,
+bound to the session, dealer and threshold parameters */
+pub fn hash_public_poly(params: &Params, C: &poly::Public) -> [u8; 32] {
+    let mut transcript = params.transcript();
+    transcript.append_encode(b"commitment", C);
+    transcript.finalize()
+}
+
+/* compute the sha2-256 hash of a send message,
+bound to the session, dealer and threshold parameters.
+The shares are hashed separately,
+so that an accusation can reveal a single share. */
+pub fn hash_send(params: &Params, send: &Send) -> [u8; 32] {
+    hash_shares(params, &send.C, &share_hashes(send))
+}
+
+/* the hash of each share in a send message,
+with the blinding share at the same index in pedersen mode */
+pub fn share_hashes(Send { a, a_hat, .. }: &Send) -> Vec<[u8; 32]> {
+    (0..a.len().max(a_hat.len()))
+        .map(|k| hash_share(a.get(k), a_hat.get(k)))
+        .collect()
+}
+
+// compute the sha2-256 hash of a share and its blinding share
+fn hash_share(
+    a_k: Option<&poly::Share>,
+    a_hat_k: Option<&poly::Share>,
+) -> [u8; 32] {
+    let mut transcript = Transcript::new(b"hybridvss-share");
+    transcript.append_encode(b"a", &a_k.cloned());
+    transcript.append_encode(b"a_hat", &a_hat_k.cloned());
+    transcript.finalize()
+}
+
+/* compute the sha2-256 hash of a send message
+from its commitment and share hashes */
+fn hash_shares(
+    params: &Params,
+    C: &poly::Public,
+    share_hashes: &[[u8; 32]],
+) -> [u8; 32] {
+    let mut transcript = params.transcript();
+    transcript.append_encode(b"commitment", C);
+    transcript.append_encode(b"shares", &share_hashes.to_vec());
+    transcript.finalize()
+}
+
+/* the message signed by the dealer for the send message to node `j`
+with hash `send_hash` */
+fn send_signed(params: &Params, j: u32, send_hash: &[u8; 32]) -> [u8; 32] {
+    let mut transcript = params.transcript();
+    transcript.append_u32(b"receiver", j);
+    transcript.append_encode(b"send", send_hash);
+    transcript.finalize()
+}
+
+/* verify the dealer's signature `sig` on the send message to node `j`
+with hash `send_hash` */
+fn verify_send_signature(
+    params: &Params,
+    j: u32,
+    send_hash: &[u8; 32],
+    sig: &G2Affine,
+) -> bool {
+    match params.pubkeys.get(params.d as usize) {
+        Some(pk) => bls::verify_g2(pk, sig, &send_signed(params, j, send_hash)),
+        None => false,
+    }
+}
+
+/* Verify that the send message `send` is signed by the dealer,
+and contains valid shares for node `j`,
+one for each unit of weight of node `j` */
+pub fn verify_send(params: &Params, j: u32, send: &Send) -> bool {
+    if j >= params.n() {
+        return false;
+    }
+    let h = if params.pedersen {
+        Some(poly::pedersen_generator())
+    } else {
+        None
+    };
+    let xs = domain_points(params, j);
+    let send_hash = hash_send(params, send);
+    send.session == params.session_id()
+        && verify_send_signature(params, j, &send_hash, &send.sig)
+        && verify_shares(params, send, &xs, h)
+}
+
+/* Verify the shares in a send message at the domain points `xs`,
+against a pedersen commitment if `h` is set.
+The commitment must be shaped as for a secret polynomial of degree `t`. */
+fn verify_shares(
+    params: &Params,
+    Send { C, a, a_hat, .. }: &Send,
+    xs: &[Scalar],
+    h: Option<G1Affine>,
+) -> bool {
+    poly::shaped(C, params.t)
+        && a.len().max(a_hat.len()) == xs.len()
+        && xs
+            .iter()
+            .enumerate()
+            .all(|(k, x)| verify_share_at(C, *x, a.get(k), a_hat.get(k), h))
+}
+
+/* Verify a share at the domain point `x`,
+with a blinding share if and only if `h` is set */
+fn verify_share_at(
+    C: &poly::Public,
+    x: Scalar,
+    a_k: Option<&poly::Share>,
+    a_hat_k: Option<&poly::Share>,
+    h: Option<G1Affine>,
+) -> bool {
+    match (a_k, a_hat_k, h) {
+        (Some(a_k), Some(a_hat_k), Some(h)) => {
+            poly::verify_share_pedersen(C, a_k, a_hat_k, x, h)
+        }
+        (Some(a_k), None, None) => poly::verify_share(C, a_k, x),
+        _ => false,
+    }
+}
+
+/* The points in an "echo" or "ready" message from node `m`,
+for batch verification */
+pub fn message_points(
+    m: u32,
+    alpha: &[Vec<Scalar>],
+    alpha_hat: &[Vec<Scalar>],
+) -> Vec<Point> {
+    alpha
+        .iter()
+        .enumerate()
+        .flat_map(|(a, alpha_a)| {
+            alpha_a.iter().enumerate().map(move |(b, alpha_ab)| Point {
+                sender: m,
+                a,
+                b,
+                alpha: *alpha_ab,
+                alpha_hat: alpha_hat
+                    .get(a)
+                    .and_then(|alpha_hat_a| alpha_hat_a.get(b))
+                    .cloned()
+                    .unwrap_or_else(Scalar::zero),
+            })
         })
-    });
-    hasher.finalize().into()
+        .collect()
+}
+
+// the domain points of node `j`
+fn domain_points(params: &Params, j: u32) -> Vec<Scalar> {
+    let omega = fft::domain(params.total_weight() as usize).group_gen;
+    let start = params.share_indexes()[j as usize];
+    (start..start + params.w[j as usize] as usize)
+        .map(|k| scalar_exp_u64(omega, k as u64))
+        .collect()
 }
 
-fn compress_G1Affine(p: &G1Affine) -> [u8; 48] {
-    let mut buf = Vec::new();
-    p.serialize(&mut buf).unwrap();
-    buf.try_into().unwrap()
+// insert the points `alpha` from the domain points `xs` into `A_C`
+fn insert_points_into(
+    A_C: &mut [HashSet<(Scalar, Scalar)>],
+    xs: &[Scalar],
+    alpha: &[Vec<Scalar>],
+) {
+    for (x, alpha_k) in xs.iter().zip(alpha.iter()) {
+        for (A_C_b, y) in A_C.iter_mut().zip(alpha_k.iter()) {
+            A_C_b.insert((*x, *y));
+        }
+    }
 }
 
 /* Alters the value at the specified key.
@@ -143,76 +628,260 @@ where
     *hm.get_mut(&k).unwrap() += w;
 }
 
+// determine if the message from node `m` has already been counted
+fn counted(
+    senders: &HashMap<[u8; 32], BTreeSet<u32>>,
+    C_hash: [u8; 32],
+    m: u32,
+) -> bool {
+    senders
+        .get(&C_hash)
+        .map_or(false, |senders_C| senders_C.contains(&m))
+}
+
 impl Context {
     pub fn init(
         params: Params,
         i: u32, // index of this node's public key in the setup
+        sk: bls12_381::Scalar, // the signing key of this node
     ) -> Self {
         let A = HashMap::new();
+        let A_hat = HashMap::new();
+        let accusations = BTreeMap::new();
+        let B = HashMap::new();
         let e = HashMap::new();
+        let echo_senders = HashMap::new();
+        let h = if params.pedersen {
+            Some(poly::pedersen_generator())
+        } else {
+            None
+        };
+        let help_count = HashMap::new();
         let r = HashMap::new();
+        let ready_senders = HashMap::new();
 
         let domain = fft::domain(params.total_weight() as usize);
-
-        let mut share_indexes = Vec::with_capacity(params.n() as usize);
-        let mut total = 0usize;
-        for weight in params.w.iter() {
-            share_indexes.push(total);
-            total += *weight as usize;
-        }
+        let share_indexes = params.share_indexes();
 
         Context {
             A,
+            A_hat,
+            accusations,
+            B,
+            reveal: None,
+            dk: None,
             domain,
             e,
+            echo_senders,
+            h,
+            help_count,
             i,
             params,
             r,
+            ready_senders,
             share_indexes,
+            sk,
         }
     }
 
+    /* Initialize a context for sealed messages,
+    with the decryption key `dk` of this node */
+    pub fn init_sealed(
+        params: Params,
+        i: u32,
+        sk: bls12_381::Scalar,
+        dk: Scalar,
+    ) -> Self {
+        let mut context = Context::init(params, i, sk);
+        context.dk = Some(dk);
+        context
+    }
+
+    // the domain point with index `k`
+    fn domain_point(&self, k: usize) -> Scalar {
+        scalar_exp_u64(self.domain.group_gen, k as u64)
+    }
+
+    // the indexes of the domain points of node `j`
+    fn share_range(&self, j: u32) -> Range<usize> {
+        let start = self.share_indexes[j as usize];
+        start..start + self.params.w[j as usize] as usize
+    }
+
+    /* the shares of a secret polynomial for every domain point,
+    generated in one FFT pass */
+    fn multi_share(&self, phi: &poly::Secret) -> Vec<poly::Share> {
+        poly::multi_share(phi, self.params.total_weight() as usize)
+    }
+
     /* Respond to a "share" message.
-    Should only be processed as the dealer. */
+    Should only be processed as the dealer.
+    In pedersen mode, the blinding polynomial at `(x, 0)` is kept,
+    so that it can be revealed later. */
     pub fn share<R: rand::Rng + Sized>(
-        &self,
+        &mut self,
         rng: &mut R,
         Share { s }: Share,
     ) -> ShareResponse {
-        let phi = poly::random_secret(self.params.t, s, rng);
-        let C = Rc::new(poly::public(&phi));
+        let t = self.params.t;
+        let phi = poly::random_secret(t, s, rng);
+        let (C, phi_hat) = match self.h {
+            Some(h) => {
+                let (C, phi_hat) = poly::public_pedersen(&phi, h, rng);
+                (C, Some(phi_hat))
+            }
+            None => (poly::public(&phi), None),
+        };
+        self.reveal = phi_hat.as_ref().map(|phi_hat| {
+            let zero = Scalar::zero();
+            let (phi_0, phi_hat_0) =
+                (phi.eval_fst(zero), phi_hat.eval_fst(zero));
+            Reveal::prove(&self.params, &phi_0, &phi_hat_0, C.len(), rng)
+        });
+        let C = Arc::new(C);
+        let a = self.multi_share(&phi);
+        let a_hat = phi_hat.as_ref().map(|phi_hat| self.multi_share(phi_hat));
         (0..self.params.n())
             .map(|j| {
-                poly::share(
-                    &phi,
-                    scalar_exp_u64(self.domain.group_gen, j as u64),
-                )
+                let send = Send {
+                    session: self.params.session_id(),
+                    C: C.clone(),
+                    a: a[self.share_range(j)].to_vec(),
+                    a_hat: a_hat.as_ref().map_or_else(Vec::new, |a_hat| {
+                        a_hat[self.share_range(j)].to_vec()
+                    }),
+                    sig: G2Affine::identity(),
+                };
+                self.sign_send(j, send)
             })
-            .map(|a| Send { C: C.clone(), a })
             .collect()
     }
 
-    pub fn verify_share(&self, Send { C, a }: &Send) -> bool {
-        let i = scalar_exp_u32(self.domain.group_gen, self.i);
-        poly::verify_share(&C, &a, i)
+    /* Sign the send message `send` for node `j`, as the dealer.
+    Replaces any existing signature. */
+    pub fn sign_send(&self, j: u32, send: Send) -> Send {
+        let send_hash = hash_send(&self.params, &send);
+        let msg = send_signed(&self.params, j, &send_hash);
+        Send {
+            sig: bls::sign_g2(self.sk, &msg),
+            ..send
+        }
+    }
+
+    // verify the dealer's signature on the send message for this node
+    fn verify_send_signature(&self, send: &Send) -> bool {
+        let send_hash = hash_send(&self.params, send);
+        verify_send_signature(&self.params, self.i, &send_hash, &send.sig)
+    }
+
+    /* Reveal the secret and blinding polynomials at `(x, 0)`
+    as the dealer, in pedersen mode */
+    pub fn reveal(&self) -> Option<Reveal> {
+        self.reveal.clone()
+    }
+
+    pub fn verify_share(&self, send: &Send) -> bool {
+        let xs: Vec<Scalar> = self
+            .share_range(self.i)
+            .map(|k| self.domain_point(k))
+            .collect();
+        send.session == self.params.session_id()
+            && verify_shares(&self.params, send, &xs, self.h)
+    }
+
+    /* Accuse the dealer,
+    if the send message for this node is signed by the dealer
+    but fails `verify_share`.
+    Only the first invalid share is revealed. */
+    pub fn accuse(&self, send: &Send) -> Option<Accusation> {
+        if send.session != self.params.session_id()
+            || !self.verify_send_signature(send)
+            || self.verify_share(send)
+        {
+            return None;
+        }
+        let share_hashes = share_hashes(send);
+        let (a, a_hat) = (&send.a, &send.a_hat);
+        let xs: Vec<Scalar> = self
+            .share_range(self.i)
+            .map(|k| self.domain_point(k))
+            .collect();
+        /* the first invalid share,
+        unless the number of shares or the shape of the commitment is wrong */
+        let k = if share_hashes.len() == xs.len()
+            && poly::shaped(&send.C, self.params.t)
+        {
+            (0..xs.len()).find(|k| {
+                let (a_k, a_hat_k) = (a.get(*k), a_hat.get(*k));
+                !verify_share_at(&send.C, xs[*k], a_k, a_hat_k, self.h)
+            })
+        } else {
+            None
+        };
+        Some(Accusation {
+            session: send.session,
+            accuser: self.i,
+            C: send.C.clone(),
+            share_hashes,
+            k: k.unwrap_or(0) as u32,
+            a_k: k.and_then(|k| a.get(k)).cloned(),
+            a_hat_k: k.and_then(|k| a_hat.get(k)).cloned(),
+            sig: send.sig,
+        })
+    }
+
+    /* Respond to an accusation against the dealer.
+    Returns `true` if the accusation is valid,
+    and is the first from its accuser. */
+    pub fn accusation(&mut self, accusation: &Accusation) -> bool {
+        if self.accusations.contains_key(&accusation.accuser)
+            || !accusation.verify(&self.params)
+        {
+            return false;
+        }
+        self.accusations
+            .insert(accusation.accuser, accusation.clone());
+        true
+    }
+
+    /* Evaluate each share of this node at every domain point,
+    and split the evaluations by receiving node */
+    fn points_for_each(&self, a: &[poly::Univar]) -> Vec<Vec<Vec<Scalar>>> {
+        let evals: Vec<Vec<Scalar>> = a
+            .iter()
+            .map(|a_k| a_k.evaluate_over_domain_by_ref(self.domain).evals)
+            .collect();
+        (0..self.params.n())
+            .map(|j| {
+                evals
+                    .iter()
+                    .map(|evals_k| evals_k[self.share_range(j)].to_vec())
+                    .collect()
+            })
+            .collect()
     }
 
     /* Respond to a "send" message.
-    Should only be accepted from the dealer. */
+    Should only be accepted from the dealer,
+    and is ignored unless signed by the dealer. */
     pub fn send(&self, send: Send) -> SendResponse {
-        if self.verify_share(&send) {
-            let Send { C, a } = send;
-            let shares = a.evaluate_over_domain_by_ref(self.domain).evals;
-
-            let echos = (0usize..self.params.n() as usize)
-                .map(|j| {
-                    shares[self.share_indexes[j]
-                        ..self.share_indexes[j] + self.params.w[j] as usize]
-                        .to_vec()
-                })
-                .map(|alpha| Echo {
+        if self.verify_send_signature(&send) && self.verify_share(&send) {
+            let Send {
+                session,
+                C,
+                a,
+                a_hat,
+                ..
+            } = send;
+            let echos = self
+                .points_for_each(&a)
+                .into_iter()
+                .zip(self.points_for_each(&a_hat))
+                .map(|(alpha, alpha_hat)| Echo {
+                    session,
                     C: C.clone(),
                     alpha,
+                    alpha_hat,
                 })
                 .collect::<Vec<Echo>>();
             Some(echos)
@@ -221,147 +890,1043 @@ impl Context {
         }
     }
 
-    // return a mutable reference to A_C
-    fn get_mut_A_C(
+    /* insert the points from node `m` into A_C,
+    and the blinding points into A_hat_C in pedersen mode */
+    fn insert_points(
         &mut self,
         C_hash: [u8; 32],
-    ) -> &mut HashSet<(Scalar, Scalar)> {
-        insert_if_none(C_hash, HashSet::new(), &mut self.A);
-        self.A.get_mut(&C_hash).unwrap()
+        m: u32,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+    ) {
+        let xs: Vec<Scalar> =
+            self.share_range(m).map(|k| self.domain_point(k)).collect();
+        let w_i = self.params.w[self.i as usize] as usize;
+        let A_C = self
+            .A
+            .entry(C_hash)
+            .or_insert_with(|| vec![HashSet::new(); w_i]);
+        insert_points_into(A_C, &xs, alpha);
+        if self.h.is_some() {
+            let A_hat_C = self
+                .A_hat
+                .entry(C_hash)
+                .or_insert_with(|| vec![HashSet::new(); w_i]);
+            insert_points_into(A_hat_C, &xs, alpha_hat);
+        }
     }
 
     /* determine if the threshold has been met,
-    in order to broadcast ready messages */
-    fn echo_ready_threshold(&mut self, C_hash: [u8; 32]) -> bool {
+    in order to broadcast ready messages.
+    `w_m` is the weight of the most recent echo. */
+    fn echo_ready_threshold(&mut self, C_hash: [u8; 32], w_m: u32) -> bool {
         let t = self.params.t;
         let W = self.params.total_weight();
         let e_C = *get_or_insert(C_hash, 0, &mut self.e);
         let r_C = *get_or_insert(C_hash, 0, &mut self.r);
-        e_C >= div_ceil(W + t + 1, 2) && r_C < t + 1
+        protocol::reached(e_C, w_m, div_ceil(W + t + 1, 2)) && r_C < t + 1
     }
 
-    fn lagrange_interpolate_A_C(&self, C_hash: [u8; 32]) -> poly::Univar {
+    // interpolate each share of this node from the points in A_C
+    fn lagrange_interpolate_A_C(&self, C_hash: [u8; 32]) -> Vec<poly::Univar> {
         let A_C = self.A.get(&C_hash).unwrap();
-        poly::lagrange_interpolate(A_C.clone())
+        A_C.iter()
+            .map(|A_C_b| poly::lagrange_interpolate(A_C_b.clone()))
+            .collect()
+    }
+
+    /* interpolate each blinding share of this node
+    from the points in A_hat_C, in pedersen mode */
+    fn lagrange_interpolate_A_hat_C(
+        &self,
+        C_hash: [u8; 32],
+    ) -> Vec<poly::Univar> {
+        self.A_hat.get(&C_hash).map_or_else(Vec::new, |A_hat_C| {
+            A_hat_C
+                .iter()
+                .map(|A_hat_C_b| poly::lagrange_interpolate(A_hat_C_b.clone()))
+                .collect()
+        })
+    }
+
+    /* Verify the points from node `m`,
+    against a pedersen commitment if `h` is set.
+    The points are checked as a batch, with a single MSM if they are valid. */
+    fn verify_message_points(
+        &self,
+        m: u32,
+        C: &poly::Public,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+        h: Option<G1Affine>,
+    ) -> bool {
+        let points = message_points(m, alpha, alpha_hat);
+        let mut rng = rand::thread_rng();
+        self.shaped(m, alpha, alpha_hat, h.is_some())
+            && self.check_points_batch(C, &points, h, &mut rng).is_empty()
+    }
+
+    /* determine if the points from node `m` have one row for each share
+    of node `m`, with a point at each domain point of this node.
+    The blinding points are laid out likewise in pedersen mode,
+    and must be empty otherwise. */
+    fn shaped(
+        &self,
+        m: u32,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+        pedersen: bool,
+    ) -> bool {
+        let w_i = self.params.w[self.i as usize] as usize;
+        let shaped = |alpha: &[Vec<Scalar>]| {
+            alpha.len() == self.params.w[m as usize] as usize
+                && alpha.iter().all(|alpha_a| alpha_a.len() == w_i)
+        };
+        m < self.params.n()
+            && shaped(alpha)
+            && if pedersen {
+                shaped(alpha_hat)
+            } else {
+                alpha_hat.is_empty()
+            }
     }
 
+    /* Verify the points from node `m`.
+    `alpha[a][b]` should be the evaluation of node `m`'s `a`th share
+    at this node's `b`th domain point. */
     pub fn verify_point(
         &self,
         m: u32,
         C: &poly::Public,
-        alpha: &Vec<Scalar>,
+        alpha: &[Vec<Scalar>],
     ) -> bool {
-        alpha.iter().enumerate().all(|(j, a)| {
-            poly::verify_point(
-                &C,
-                scalar_exp_u32(self.domain.group_gen, self.i),
-                scalar_exp_u64(self.domain.group_gen, (j as u64) + (m as u64)),
-                *a,
-            )
-        })
+        self.verify_message_points(m, C, alpha, &[], None)
+    }
+
+    /* Verify the points and blinding points from node `m`
+    against a pedersen commitment.
+    `alpha_hat` is laid out as in `alpha`. */
+    pub fn verify_point_pedersen(
+        &self,
+        m: u32,
+        C: &poly::Public,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+        h: G1Affine,
+    ) -> bool {
+        self.verify_message_points(m, C, alpha, alpha_hat, Some(h))
     }
 
-    /* Respond to an "echo" message. */
-    pub fn echo(&mut self, m: u32, Echo { C, alpha }: &Echo) -> EchoResponse {
-        if self.verify_point(m, C, alpha) {
-            let C_hash = hash_public_poly(C);
-            incr(C_hash, &mut self.e, self.params.w[m as usize]);
+    /* Batch verify points from many nodes against the commitment `C`,
+    with a single random linear combination if all of them are valid.
+    Points with out of range indexes are invalid,
+    as are points with a nonzero blinding point outside of pedersen mode,
+    and every point if `C` is not shaped as for degree `t`.
+    Returns the indexes of the invalid points, in increasing order. */
+    pub fn verify_points_batch<R: rand::Rng + Sized>(
+        &self,
+        C: &poly::Public,
+        points: &[Point],
+        rng: &mut R,
+    ) -> Vec<usize> {
+        self.check_points_batch(C, points, self.h, rng)
+    }
 
-            let domain_0 = self.domain.group_gen;
-            let A_C = self.get_mut_A_C(C_hash);
-            let mut i = scalar_exp_u32(domain_0, m);
-            for s in alpha.iter() {
-                A_C.insert((i, *s));
-                i *= domain_0;
+    /* Batch verify points against the commitment `C`,
+    with the blinding points against `h` if it is set */
+    fn check_points_batch<R: rand::Rng + Sized>(
+        &self,
+        C: &poly::Public,
+        points: &[Point],
+        h: Option<G1Affine>,
+        rng: &mut R,
+    ) -> Vec<usize> {
+        if !poly::shaped(C, self.params.t) {
+            return (0..points.len()).collect();
+        }
+        let w_i = self.params.w[self.i as usize] as usize;
+        let mut invalid = Vec::new();
+        // the indexes of the points in range, and their coordinates
+        let mut indexes = Vec::new();
+        let mut coords = Vec::new();
+        let mut alpha_hat = Vec::new();
+        for (k, point) in points.iter().enumerate() {
+            let in_range = point.sender < self.params.n()
+                && point.a < self.params.w[point.sender as usize] as usize
+                && point.b < w_i;
+            if !in_range || (h.is_none() && !point.alpha_hat.is_zero()) {
+                invalid.push(k);
+                continue;
             }
+            let k_m = self.share_range(point.sender).start + point.a;
+            let k_i = self.share_range(self.i).start + point.b;
+            indexes.push(k);
+            coords.push((
+                self.domain_point(k_m),
+                self.domain_point(k_i),
+                point.alpha,
+            ));
+            alpha_hat.push(point.alpha_hat);
+        }
+        let blinding = h.map(|h| (&alpha_hat[..], h));
+        invalid.extend(
+            poly::verify_points_batch(C, &coords, blinding, rng)
+                .into_iter()
+                .map(|k| indexes[k]),
+        );
+        invalid.sort_unstable();
+        invalid
+    }
 
-            if self.echo_ready_threshold(C_hash) {
-                let a_bar = self.lagrange_interpolate_A_C(C_hash);
-                let shares =
-                    a_bar.evaluate_over_domain_by_ref(self.domain).evals;
-                let ready_messages = (0usize..self.params.n() as usize)
-                    .map(|j| {
-                        shares[self.share_indexes[j]
-                            ..self.share_indexes[j] + self.params.w[j] as usize]
-                            .to_vec()
-                    })
-                    .map(|alpha| Ready {
-                        C: C.clone(),
-                        alpha,
-                    })
-                    .collect::<Vec<Ready>>();
-                Some(ready_messages)
-            } else {
-                None
+    // verify the points from node `m` in either commitment mode
+    fn verify_points(
+        &self,
+        m: u32,
+        C: &poly::Public,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+    ) -> bool {
+        self.verify_message_points(m, C, alpha, alpha_hat, self.h)
+    }
+
+    /* The ready messages for each node,
+    from the shares of this node interpolated from A_C */
+    fn ready_messages(
+        &self,
+        C_hash: [u8; 32],
+        C: &Arc<poly::Public>,
+    ) -> Vec<Ready> {
+        let a_bar = self.lagrange_interpolate_A_C(C_hash);
+        let a_hat_bar = self.lagrange_interpolate_A_hat_C(C_hash);
+        self.points_for_each(&a_bar)
+            .into_iter()
+            .zip(self.points_for_each(&a_hat_bar))
+            .map(|(alpha, alpha_hat)| Ready {
+                session: self.params.session_id(),
+                C: C.clone(),
+                alpha,
+                alpha_hat,
+            })
+            .collect()
+    }
+
+    /* The ready message for node `m` alone,
+    from the shares of this node interpolated from A_C */
+    fn ready_message(
+        &self,
+        C_hash: [u8; 32],
+        C: &Arc<poly::Public>,
+        m: u32,
+    ) -> Ready {
+        let xs: Vec<Scalar> =
+            self.share_range(m).map(|k| self.domain_point(k)).collect();
+        let points = |a_bar: Vec<poly::Univar>| {
+            a_bar
+                .iter()
+                .map(|a_bar_k| xs.iter().map(|x| a_bar_k.evaluate(x)).collect())
+                .collect()
+        };
+        Ready {
+            session: self.params.session_id(),
+            C: C.clone(),
+            alpha: points(self.lagrange_interpolate_A_C(C_hash)),
+            alpha_hat: points(self.lagrange_interpolate_A_hat_C(C_hash)),
+        }
+    }
+
+    /* Respond to an "echo" message.
+    Only the first valid echo from each node is counted. */
+    pub fn echo(
+        &mut self,
+        m: u32,
+        Echo {
+            session,
+            C,
+            alpha,
+            alpha_hat,
+        }: &Echo,
+    ) -> EchoResponse {
+        if *session != self.params.session_id() {
+            return None;
+        }
+        let C_hash = hash_public_poly(&self.params, C);
+        if counted(&self.echo_senders, C_hash, m)
+            || !self.verify_points(m, C, alpha, alpha_hat)
+        {
+            return None;
+        }
+        self.count_echo(C_hash, m, C, alpha, alpha_hat)
+    }
+
+    /* Respond to many "echo" messages, as to each in turn with `echo`.
+    The points of all of the messages for the same commitment
+    are verified together with `verify_points_batch`.
+    Returns the responses to the messages that produced ready messages,
+    in order. */
+    pub fn echo_batch<R: rand::Rng + Sized>(
+        &mut self,
+        echos: &[(u32, Echo)],
+        rng: &mut R,
+    ) -> Vec<Vec<Ready>> {
+        let msgs: Vec<MessagePoints> = echos
+            .iter()
+            .map(|(m, echo)| MessagePoints {
+                m: *m,
+                session: &echo.session,
+                C: &echo.C,
+                alpha: &echo.alpha,
+                alpha_hat: &echo.alpha_hat,
+            })
+            .collect();
+        let valid = self.verify_messages_batch(&msgs, rng);
+        let mut res = Vec::new();
+        for (msg, C_hash) in msgs.iter().zip(valid) {
+            let C_hash = match C_hash {
+                Some(C_hash) => C_hash,
+                None => continue,
+            };
+            if !counted(&self.echo_senders, C_hash, msg.m) {
+                res.extend(self.count_echo(
+                    C_hash,
+                    msg.m,
+                    msg.C,
+                    msg.alpha,
+                    msg.alpha_hat,
+                ))
+            }
+        }
+        res
+    }
+
+    /* Verify the points of many echo or ready messages,
+    with a single batch for all of the messages with the same commitment.
+    Returns the commitment hash of each valid message,
+    and `None` for each invalid message. */
+    fn verify_messages_batch<R: rand::Rng + Sized>(
+        &self,
+        msgs: &[MessagePoints],
+        rng: &mut R,
+    ) -> Vec<Option<[u8; 32]>> {
+        // the indexes of the well formed messages for each commitment
+        let mut groups: BTreeMap<[u8; 32], Vec<usize>> = BTreeMap::new();
+        for (k, msg) in msgs.iter().enumerate() {
+            if *msg.session == self.params.session_id()
+                && self.shaped(
+                    msg.m,
+                    msg.alpha,
+                    msg.alpha_hat,
+                    self.h.is_some(),
+                )
+            {
+                let C_hash = hash_public_poly(&self.params, msg.C);
+                groups.entry(C_hash).or_default().push(k)
+            }
+        }
+        let mut res = vec![None; msgs.len()];
+        for (C_hash, ks) in groups {
+            // the points of the messages, and the message of each point
+            let mut points = Vec::new();
+            let mut owners = Vec::new();
+            for k in ks.iter() {
+                let msg = &msgs[*k];
+                let msg_points =
+                    message_points(msg.m, msg.alpha, msg.alpha_hat);
+                owners.resize(owners.len() + msg_points.len(), *k);
+                points.extend(msg_points);
+            }
+            let C = msgs[ks[0]].C;
+            let invalid: BTreeSet<usize> = self
+                .verify_points_batch(C, &points, rng)
+                .into_iter()
+                .map(|p| owners[p])
+                .collect();
+            for k in ks {
+                if !invalid.contains(&k) {
+                    res[k] = Some(C_hash)
+                }
             }
+        }
+        res
+    }
+
+    // count a valid echo message from node `m`
+    fn count_echo(
+        &mut self,
+        C_hash: [u8; 32],
+        m: u32,
+        C: &Arc<poly::Public>,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+    ) -> EchoResponse {
+        self.echo_senders.entry(C_hash).or_default().insert(m);
+        let w_m = self.params.w[m as usize];
+        incr(C_hash, &mut self.e, w_m);
+        self.insert_points(C_hash, m, alpha, alpha_hat);
+
+        if self.echo_ready_threshold(C_hash, w_m) {
+            self.B.insert(C_hash, C.clone());
+            Some(self.ready_messages(C_hash, C))
         } else {
             None
         }
     }
 
     /* determine if the threshold has been met,
-    in order to broadcast ready messages */
-    fn ready_ready_threshold(&mut self, C_hash: [u8; 32]) -> bool {
+    in order to broadcast ready messages.
+    `w_m` is the weight of the most recent ready message. */
+    fn ready_ready_threshold(&mut self, C_hash: [u8; 32], w_m: u32) -> bool {
         let t = self.params.t;
         let W = self.params.total_weight();
         let e_C = *get_or_insert(C_hash, 0, &mut self.e);
         let r_C = *get_or_insert(C_hash, 0, &mut self.r);
-        e_C < div_ceil(W + t + 1, 2) && r_C == t + 1
+        e_C < div_ceil(W + t + 1, 2) && protocol::reached(r_C, w_m, t + 1)
     }
 
     /* determine if the threshold has been met,
-    in order to broadcast shared messages */
-    fn ready_shared_threshold(&mut self, C_hash: [u8; 32]) -> bool {
+    in order to broadcast shared messages.
+    `w_m` is the weight of the most recent ready message. */
+    fn ready_shared_threshold(&mut self, C_hash: [u8; 32], w_m: u32) -> bool {
         let Params { t, f, .. } = self.params;
         let W = self.params.total_weight();
         let r_C = *get_or_insert(C_hash, 0, &mut self.r);
-        r_C >= W - t - f
+        protocol::reached(r_C, w_m, W - t - f)
     }
 
+    /* Respond to a "ready" message.
+    Only the first valid ready message from each node is counted.
+    Both thresholds are checked on every message. */
     pub fn ready(
         &mut self,
         m: u32,
-        Ready { C, alpha }: &Ready,
+        Ready {
+            session,
+            C,
+            alpha,
+            alpha_hat,
+        }: &Ready,
+    ) -> ReadyResponse {
+        if *session != self.params.session_id() {
+            return Vec::new();
+        }
+        let C_hash = hash_public_poly(&self.params, C);
+        if counted(&self.ready_senders, C_hash, m)
+            || !self.verify_points(m, C, alpha, alpha_hat)
+        {
+            return Vec::new();
+        }
+        self.count_ready(*session, C_hash, m, C, alpha, alpha_hat)
+    }
+
+    /* Respond to many "ready" messages, as to each in turn with `ready`.
+    The points of all of the messages for the same commitment
+    are verified together with `verify_points_batch`.
+    Returns the responses to each message in order. */
+    pub fn ready_batch<R: rand::Rng + Sized>(
+        &mut self,
+        readies: &[(u32, Ready)],
+        rng: &mut R,
     ) -> ReadyResponse {
-        if self.verify_point(m, C, alpha) {
-            let C_hash = hash_public_poly(&C);
-            incr(C_hash, &mut self.r, self.params.w[m as usize]);
-
-            let domain_0 = self.domain.group_gen;
-            let A_C = self.get_mut_A_C(C_hash);
-            let mut i = scalar_exp_u32(domain_0, m);
-            for s in alpha.iter() {
-                A_C.insert((i, *s));
-                i *= domain_0;
+        let msgs: Vec<MessagePoints> = readies
+            .iter()
+            .map(|(m, ready)| MessagePoints {
+                m: *m,
+                session: &ready.session,
+                C: &ready.C,
+                alpha: &ready.alpha,
+                alpha_hat: &ready.alpha_hat,
+            })
+            .collect();
+        let valid = self.verify_messages_batch(&msgs, rng);
+        let mut res = Vec::new();
+        for (msg, C_hash) in msgs.iter().zip(valid) {
+            let C_hash = match C_hash {
+                Some(C_hash) => C_hash,
+                None => continue,
+            };
+            if !counted(&self.ready_senders, C_hash, msg.m) {
+                res.extend(self.count_ready(
+                    *msg.session,
+                    C_hash,
+                    msg.m,
+                    msg.C,
+                    msg.alpha,
+                    msg.alpha_hat,
+                ))
             }
+        }
+        res
+    }
 
-            let a_bar = self.lagrange_interpolate_A_C(C_hash);
-
-            if self.ready_ready_threshold(C_hash) {
-                let shares =
-                    a_bar.evaluate_over_domain_by_ref(self.domain).evals;
-
-                let ready_messages = (0usize..self.params.n() as usize)
-                    .map(|j| {
-                        shares[self.share_indexes[j]
-                            ..self.share_indexes[j]
-                                + (self.params.w[j] as usize)]
-                            .to_vec()
-                    })
-                    .map(|alpha| Ready {
-                        C: C.clone(),
-                        alpha,
-                    })
-                    .collect();
-                Some(Either::Left(ready_messages))
-            } else if self.ready_shared_threshold(C_hash) {
-                let s = a_bar.evaluate(&Scalar::zero());
-                Some(Either::Right(Shared { C: C.clone(), s }))
-            } else {
-                None
+    // count a valid ready message from node `m`
+    fn count_ready(
+        &mut self,
+        session: SessionId,
+        C_hash: [u8; 32],
+        m: u32,
+        C: &Arc<poly::Public>,
+        alpha: &[Vec<Scalar>],
+        alpha_hat: &[Vec<Scalar>],
+    ) -> ReadyResponse {
+        let mut res = Vec::new();
+        self.ready_senders.entry(C_hash).or_default().insert(m);
+        let w_m = self.params.w[m as usize];
+        incr(C_hash, &mut self.r, w_m);
+        self.insert_points(C_hash, m, alpha, alpha_hat);
+
+        if self.ready_ready_threshold(C_hash, w_m) {
+            self.B.insert(C_hash, C.clone());
+            res.push(Either::Left(self.ready_messages(C_hash, C)));
+        }
+        if self.ready_shared_threshold(C_hash, w_m) {
+            let eval_zero = |a_bar: Vec<poly::Univar>| {
+                a_bar
+                    .iter()
+                    .map(|a_bar_b| a_bar_b.evaluate(&Scalar::zero()))
+                    .collect()
+            };
+            res.push(Either::Right(Shared {
+                session,
+                C: C.clone(),
+                s: eval_zero(self.lagrange_interpolate_A_C(C_hash)),
+                s_hat: eval_zero(self.lagrange_interpolate_A_hat_C(C_hash)),
+            }));
+        }
+        res
+    }
+
+    /* Request help from the other nodes,
+    if this node missed the messages needed to finish.
+    Each node answers with the ready messages it has sent to this node,
+    which are handled as usual. */
+    pub fn request_help(&self) -> Vec<Outgoing> {
+        let session = self.params.session_id();
+        vec![Outgoing::Broadcast(Message::Help(Help { session }))]
+    }
+
+    /* Respond to a "help" message from node `m`,
+    by sending the ready messages for node `m` again.
+    At most `HELP_LIMIT` requests from each node are answered. */
+    pub fn help(&mut self, m: u32, Help { session }: &Help) -> Vec<Ready> {
+        if *session != self.params.session_id() || m >= self.params.n() {
+            return Vec::new();
+        }
+        let count = self.help_count.entry(m).or_insert(0);
+        if *count >= HELP_LIMIT {
+            return Vec::new();
+        }
+        *count += 1;
+        self.B
+            .iter()
+            .map(|(C_hash, C)| self.ready_message(*C_hash, C, m))
+            .collect()
+    }
+
+    /* Respond to a "share" message as the dealer,
+    addressing a send message to each node */
+    pub fn deal<R: rand::Rng + Sized>(
+        &mut self,
+        rng: &mut R,
+        share: Share,
+    ) -> Vec<Outgoing> {
+        to_each(self.share(rng, share), Message::Send)
+    }
+
+    /* Seal the point-to-point messages in `outgoing`,
+    encrypting each to the encryption key of its receiver,
+    and signing it with this node's signing key.
+    Point-to-point messages are dropped if the receiver has no encryption key.
+    Other events are returned unchanged,
+    as are all events if the parameters have no encryption keys. */
+    pub fn seal<R: rand::Rng + Sized>(
+        &self,
+        outgoing: Vec<Outgoing>,
+        rng: &mut R,
+    ) -> Vec<Outgoing> {
+        if !self.params.sealed() {
+            return outgoing;
+        }
+        let session = self.params.session_id();
+        outgoing
+            .into_iter()
+            .filter_map(|out| match out {
+                Outgoing::To(j, msg) => {
+                    let ek = self.params.ek.get(j as usize)?;
+                    let mut bytes = Vec::new();
+                    msg.serialize(&mut bytes).unwrap();
+                    let aad = sealed_aad(&session, self.i, j);
+                    let c = ecies::encrypt(ek, &aad, &bytes, rng);
+                    let sig = bls::sign_g2(self.sk, &sealed_signed(&aad, &c));
+                    let sealed = Sealed { session, c, sig };
+                    Some(Outgoing::To(j, Message::Sealed(sealed)))
+                }
+                out => Some(out),
+            })
+            .collect()
+    }
+
+    /* Open a sealed message from node `sender`.
+    Returns `None` if this node has no decryption key,
+    the message is not signed by `sender`,
+    or the message fails to decrypt or is not a send, echo or ready message. */
+    pub fn open(&self, sender: u32, sealed: &Sealed) -> Option<Message> {
+        if sealed.session != self.params.session_id() {
+            return None;
+        }
+        let pk = self.params.pubkeys.get(sender as usize)?;
+        let aad = sealed_aad(&sealed.session, sender, self.i);
+        if !bls::verify_g2(pk, &sealed.sig, &sealed_signed(&aad, &sealed.c)) {
+            return None;
+        }
+        let bytes = ecies::decrypt(self.dk?, &aad, &sealed.c)?;
+        match Message::deserialize(&bytes[..]).ok()? {
+            msg @ Message::Send(_)
+            | msg @ Message::Echo(_)
+            | msg @ Message::Ready(_) => Some(msg),
+            _ => None,
+        }
+    }
+
+    /* Respond to a "share" message as the dealer,
+    sealing the send message for each node */
+    pub fn deal_sealed<R: rand::Rng + Sized>(
+        &mut self,
+        rng: &mut R,
+        share: Share,
+    ) -> Vec<Outgoing> {
+        let outgoing = self.deal(rng, share);
+        self.seal(outgoing, rng)
+    }
+
+    /* Respond to a message from node `sender`, sealing the responses.
+    See `handle`. */
+    pub fn handle_sealed<R: rand::Rng + Sized>(
+        &mut self,
+        sender: u32,
+        msg: Message,
+        rng: &mut R,
+    ) -> Vec<Outgoing> {
+        let outgoing = self.handle(sender, msg);
+        self.seal(outgoing, rng)
+    }
+
+    /* Respond to a message from node `sender`.
+    Send messages are only accepted from the dealer,
+    and invalid send messages are answered with an accusation.
+    If the parameters include encryption keys,
+    send, echo and ready messages are only accepted if sealed.
+    Sealed messages are opened and handled as the message they contain,
+    but the responses are not sealed; see `handle_sealed`. */
+    pub fn handle(&mut self, sender: u32, msg: Message) -> Vec<Outgoing> {
+        let plaintext = matches!(
+            msg,
+            Message::Send(_) | Message::Echo(_) | Message::Ready(_)
+        );
+        if sender >= self.params.n() || plaintext && self.params.sealed() {
+            return Vec::new();
+        }
+        self.handle_opened(sender, msg)
+    }
+
+    /* Respond to a message from node `sender`,
+    which has been opened if it was sealed */
+    fn handle_opened(&mut self, sender: u32, msg: Message) -> Vec<Outgoing> {
+        match msg {
+            Message::Send(send) if sender == self.params.d => {
+                match self.send(send.clone()) {
+                    Some(echos) => to_each(echos, Message::Echo),
+                    None => match self.accuse(&send) {
+                        Some(accusation) => {
+                            self.accusation(&accusation);
+                            let msg = Message::Accusation(accusation);
+                            vec![Outgoing::Broadcast(msg)]
+                        }
+                        None => Vec::new(),
+                    },
+                }
+            }
+            Message::Send(_) => Vec::new(),
+            // accusations are only accepted from the accuser
+            Message::Accusation(accusation) if accusation.accuser == sender => {
+                self.accusation(&accusation);
+                Vec::new()
+            }
+            Message::Accusation(_) => Vec::new(),
+            Message::Help(help) => self
+                .help(sender, &help)
+                .into_iter()
+                .map(|ready| Outgoing::To(sender, Message::Ready(ready)))
+                .collect(),
+            Message::Echo(echo) => to_each(
+                self.echo(sender, &echo).unwrap_or_default(),
+                Message::Ready,
+            ),
+            Message::Ready(ready) => self
+                .ready(sender, &ready)
+                .into_iter()
+                .flat_map(|response| match response {
+                    Either::Left(readies) => to_each(readies, Message::Ready),
+                    Either::Right(shared) => vec![Outgoing::Output(shared)],
+                })
+                .collect(),
+            Message::Sealed(sealed) => match self.open(sender, &sealed) {
+                Some(msg) => self.handle_opened(sender, msg),
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+/* Contexts are encoded with a version prefix.
+The domain and share indexes are recomputed from the parameters.
+Note that the encoding includes the received shares,
+the signing key of the node and its decryption key, if any,
+so snapshots must be stored as securely as the keys themselves. */
+impl Encode for Context {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_version(writer)?;
+        self.params.encode(writer)?;
+        self.i.encode(writer)?;
+        self.sk.encode(writer)?;
+        self.A.encode(writer)?;
+        self.e.encode(writer)?;
+        self.echo_senders.encode(writer)?;
+        self.r.encode(writer)?;
+        self.ready_senders.encode(writer)?;
+        self.accusations.encode(writer)?;
+        self.B.encode(writer)?;
+        self.help_count.encode(writer)?;
+        self.A_hat.encode(writer)?;
+        self.reveal.encode(writer)?;
+        self.dk.encode(writer)
+    }
+}
+
+impl Decode for Context {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        decode_version(reader)?;
+        let params = Params::decode(reader)?;
+        let i = u32::decode(reader)?;
+        if i >= params.n() {
+            return Err(SerializationError::InvalidData);
+        }
+        let sk = bls12_381::Scalar::decode(reader)?;
+        let mut context = Context::init(params, i, sk);
+        context.A = HashMap::decode(reader)?;
+        context.e = HashMap::decode(reader)?;
+        context.echo_senders = HashMap::decode(reader)?;
+        context.r = HashMap::decode(reader)?;
+        context.ready_senders = HashMap::decode(reader)?;
+        context.accusations = BTreeMap::decode(reader)?;
+        context.B = HashMap::decode(reader)?;
+        context.help_count = HashMap::decode(reader)?;
+        context.A_hat = HashMap::decode(reader)?;
+        context.reveal = Option::decode(reader)?;
+        context.dk = Option::decode(reader)?;
+        Ok(context)
+    }
+}
+
+impl Encode for Echo {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.C.encode(writer)?;
+        self.alpha.encode(writer)?;
+        self.alpha_hat.encode(writer)
+    }
+}
+
+impl Decode for Echo {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let alpha = Vec::decode(reader)?;
+        let alpha_hat = Vec::decode(reader)?;
+        Ok(Echo {
+            session,
+            C,
+            alpha,
+            alpha_hat,
+        })
+    }
+}
+
+impl Encode for Ready {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.C.encode(writer)?;
+        self.alpha.encode(writer)?;
+        self.alpha_hat.encode(writer)
+    }
+}
+
+impl Decode for Ready {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let alpha = Vec::decode(reader)?;
+        let alpha_hat = Vec::decode(reader)?;
+        Ok(Ready {
+            session,
+            C,
+            alpha,
+            alpha_hat,
+        })
+    }
+}
+
+impl Encode for Send {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.C.encode(writer)?;
+        self.a.encode(writer)?;
+        self.a_hat.encode(writer)?;
+        self.sig.encode(writer)
+    }
+}
+
+impl Decode for Send {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let a = Vec::decode(reader)?;
+        let a_hat = Vec::decode(reader)?;
+        let sig = G2Affine::decode(reader)?;
+        Ok(Send {
+            session,
+            C,
+            a,
+            a_hat,
+            sig,
+        })
+    }
+}
+
+impl Encode for Accusation {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.accuser.encode(writer)?;
+        self.C.encode(writer)?;
+        self.share_hashes.encode(writer)?;
+        self.k.encode(writer)?;
+        self.a_k.encode(writer)?;
+        self.a_hat_k.encode(writer)?;
+        self.sig.encode(writer)
+    }
+}
+
+impl Decode for Accusation {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let accuser = u32::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let share_hashes = Vec::decode(reader)?;
+        let k = u32::decode(reader)?;
+        let a_k = Option::decode(reader)?;
+        let a_hat_k = Option::decode(reader)?;
+        let sig = G2Affine::decode(reader)?;
+        Ok(Accusation {
+            session,
+            accuser,
+            C,
+            share_hashes,
+            k,
+            a_k,
+            a_hat_k,
+            sig,
+        })
+    }
+}
+
+impl Encode for Help {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)
+    }
+}
+
+impl Decode for Help {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        Ok(Help { session })
+    }
+}
+
+impl Encode for Reveal {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.F.encode(writer)?;
+        self.r.encode(writer)?;
+        self.R.encode(writer)?;
+        self.z.encode(writer)
+    }
+}
+
+impl Decode for Reveal {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let F = Vec::decode(reader)?;
+        let r = Vec::decode(reader)?;
+        let R = G1Affine::decode(reader)?;
+        let z = Scalar::decode(reader)?;
+        Ok(Reveal {
+            session,
+            F,
+            r,
+            R,
+            z,
+        })
+    }
+}
+
+impl Encode for Share {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.s.encode(writer)
+    }
+}
+
+impl Decode for Share {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let s = Scalar::decode(reader)?;
+        Ok(Share { s })
+    }
+}
+
+impl Encode for Shared {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.C.encode(writer)?;
+        self.s.encode(writer)?;
+        self.s_hat.encode(writer)
+    }
+}
+
+impl Decode for Shared {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let C = Arc::decode(reader)?;
+        let s = Vec::decode(reader)?;
+        let s_hat = Vec::decode(reader)?;
+        Ok(Shared {
+            session,
+            C,
+            s,
+            s_hat,
+        })
+    }
+}
+
+impl Encode for Sealed {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.c.encode(writer)?;
+        self.sig.encode(writer)
+    }
+}
+
+impl Decode for Sealed {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let c = ecies::Ciphertext::decode(reader)?;
+        let sig = G2Affine::decode(reader)?;
+        Ok(Sealed { session, c, sig })
+    }
+}
+
+// messages are encoded with a tag for the variant
+impl Encode for Message {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            Message::Send(send) => {
+                0u8.encode(writer)?;
+                send.encode(writer)
+            }
+            Message::Echo(echo) => {
+                1u8.encode(writer)?;
+                echo.encode(writer)
+            }
+            Message::Ready(ready) => {
+                2u8.encode(writer)?;
+                ready.encode(writer)
+            }
+            Message::Accusation(accusation) => {
+                3u8.encode(writer)?;
+                accusation.encode(writer)
+            }
+            Message::Help(help) => {
+                4u8.encode(writer)?;
+                help.encode(writer)
+            }
+            Message::Sealed(sealed) => {
+                5u8.encode(writer)?;
+                sealed.encode(writer)
             }
-        } else {
-            None
         }
     }
 }
+
+impl Decode for Message {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        match u8::decode(reader)? {
+            0 => Ok(Message::Send(Send::decode(reader)?)),
+            1 => Ok(Message::Echo(Echo::decode(reader)?)),
+            2 => Ok(Message::Ready(Ready::decode(reader)?)),
+            3 => Ok(Message::Accusation(Accusation::decode(reader)?)),
+            4 => Ok(Message::Help(Help::decode(reader)?)),
+            5 => Ok(Message::Sealed(Sealed::decode(reader)?)),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+impl_canonical!(
+    Accusation, Context, Echo, Help, Message, Ready, Reveal, Sealed, Send,
+    Share, Shared
+);
+
+// protocol state and messages can be sent and shared between threads
+const _: fn() = || {
+    protocol::assert_send_sync::<Accusation>();
+    protocol::assert_send_sync::<Context>();
+    protocol::assert_send_sync::<Echo>();
+    protocol::assert_send_sync::<Help>();
+    protocol::assert_send_sync::<Message>();
+    protocol::assert_send_sync::<Outgoing>();
+    protocol::assert_send_sync::<Ready>();
+    protocol::assert_send_sync::<Reveal>();
+    protocol::assert_send_sync::<Sealed>();
+    protocol::assert_send_sync::<Send>();
+    protocol::assert_send_sync::<Share>();
+    protocol::assert_send_sync::<Shared>();
+};
@@ -1,9 +1,16 @@
 #![allow(dead_code)]
 
+#[macro_use]
+mod serialization;
+
 pub mod bls;
+pub mod ecies;
 pub mod fft;
 pub mod hash_to_curve;
 pub mod hash_to_field;
 pub mod hybriddkg;
 pub mod hybridvss;
 pub mod poly;
+pub mod protocol;
+pub mod pvss;
+pub mod transcript;
@@ -2,14 +2,17 @@
 Operations involving polynomials.
 */
 
-use ark_bls12_381::{Fr, G1Affine, G1Projective};
+use ark_bls12_381::{Fq, Fr, G1Affine, G1Projective};
+use ark_ec::msm::VariableBaseMSM;
 use ark_ec::wnaf::WnafContext;
-use ark_ec::ProjectiveCurve;
-use ark_ff::{Field, UniformRand};
-use ark_poly::polynomial::multivariate::{SparsePolynomial, SparseTerm, Term};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{batch_inversion, Field, PrimeField, UniformRand};
 use ark_poly::polynomial::univariate::DensePolynomial;
-use ark_poly::polynomial::{MVPolynomial, Polynomial, UVPolynomial};
+use ark_poly::polynomial::{Polynomial, UVPolynomial};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
 use num::{One, Zero};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::{Add, AddAssign, Mul, MulAssign, Range};
 
 use crate::fft;
 
@@ -39,83 +42,107 @@ fn scalar_exp_usize(x: Scalar, y: usize) -> Scalar {
 // Univariate polynomial
 pub type Univar = DensePolynomial<Scalar>;
 
-// the exponent of the nth parameter in a term
-fn param_pow(term: &SparseTerm, i: usize) -> usize {
-    *term
-        .iter()
-        .find_map(|(par, deg)| if *par == i { Some(deg) } else { None })
-        .unwrap_or(&0)
+/* Symmetric bivariate polynomial of degree `degree` in each variable,
+`f(x, y) = ∑_{i,j} c_i_j * x^i * y^j` with `c_i_j = c_j_i`.
+Only the upper triangle is stored, row by row:
+`c_0_0, ..., c_0_t, c_1_1, ..., c_1_t, ..., c_t_t`. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bivar {
+    degree: usize,
+    coeffs: Vec<Scalar>,
 }
 
-// Bivariate polynomial
-pub struct Bivar(SparsePolynomial<Scalar, SparseTerm>);
-
 impl Bivar {
-    // get the degree of the first term
-    fn fst_degree(&self) -> usize {
-        self.0
-            .terms
-            .iter()
-            .map(|(_, term)| param_pow(term, 0))
-            .max()
-            .unwrap()
+    // the number of coefficients in the upper triangle
+    fn triangle_len(degree: usize) -> usize {
+        (degree + 1) * (degree + 2) / 2
     }
 
-    // get the degree of the second term
-    fn snd_degree(&self) -> usize {
-        self.0
-            .terms
-            .iter()
-            .map(|(_, term)| param_pow(term, 1))
-            .max()
-            .unwrap()
+    // the index of `c_i_j` in the upper triangle, for `i <= j`
+    fn index(&self, i: usize, j: usize) -> usize {
+        let n = self.degree + 1;
+        i * n - i * (i.saturating_sub(1)) / 2 + (j - i)
     }
 
-    pub fn coeffs(&self) -> Vec<Vec<Scalar>> {
-        let fst_degree = self.fst_degree();
-        let snd_degree = self.snd_degree();
+    // the pairs `(i, j)` with `i <= j`, in storage order
+    fn triangle(degree: usize) -> impl Iterator<Item = (usize, usize)> {
+        (0..=degree).flat_map(move |i| (i..=degree).map(move |j| (i, j)))
+    }
 
-        let mut res =
-            vec![vec![Scalar::zero(); snd_degree + 1]; fst_degree + 1];
+    /* Expand values laid out as the upper triangle
+    into a full symmetric matrix */
+    fn expand<T: Clone>(&self, triangle: &[T]) -> Vec<Vec<T>> {
+        (0..=self.degree)
+            .map(|i| {
+                (0..=self.degree)
+                    .map(|j| {
+                        triangle[self.index(usize::min(i, j), usize::max(i, j))]
+                            .clone()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 
-        self.0.terms.iter().for_each(|(coeff, term)| {
-            let pow_fst = param_pow(term, 0);
-            let pow_snd = param_pow(term, 1);
-            res[pow_fst][pow_snd] += coeff;
-        });
+    // get the degree in each variable
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
 
-        res
+    // get the coefficient of `x^i * y^j`
+    pub fn coeff(&self, i: usize, j: usize) -> Scalar {
+        if i > self.degree || j > self.degree {
+            Scalar::zero()
+        } else {
+            self.coeffs[self.index(usize::min(i, j), usize::max(i, j))]
+        }
     }
 
-    pub fn from_coeffs(coeffs: &Vec<Vec<Scalar>>) -> Self {
-        let mut coeffs_vec = Vec::new();
+    // the full matrix of coefficients, where `res[i][j] = c_i_j`
+    pub fn coeffs(&self) -> Vec<Vec<Scalar>> {
+        self.expand(&self.coeffs)
+    }
 
-        for i in 0..coeffs.len() {
-            for j in 0..coeffs[0].len() {
-                let term = SparseTerm::new(vec![(0, i), (1, j)]);
-                let coeff = (coeffs[i][j], term);
-                coeffs_vec.push(coeff)
-            }
+    /* Construct from a symmetric matrix of coefficients.
+    Only the upper triangle is read.
+    An empty matrix is the zero polynomial. */
+    pub fn from_coeffs(coeffs: &[Vec<Scalar>]) -> Self {
+        if coeffs.is_empty() {
+            let coeffs = vec![Scalar::zero()];
+            return Bivar { degree: 0, coeffs };
         }
-
-        Bivar(SparsePolynomial::from_coefficients_vec(2, coeffs_vec))
+        let degree = coeffs.len() - 1;
+        debug_assert!(
+            Self::triangle(degree).all(|(i, j)| coeffs[i][j] == coeffs[j][i])
+        );
+        let coeffs =
+            Self::triangle(degree).map(|(i, j)| coeffs[i][j]).collect();
+        Bivar { degree, coeffs }
     }
 
     // evaluate at the first term
     pub fn eval_fst(&self, fst: Scalar) -> Univar {
-        let coeffs = self.coeffs();
-        let pows_fst = powers(fst, self.fst_degree() + 1); // powers of fst
-
-        let mut res = vec![Scalar::zero(); self.snd_degree() + 1];
+        let pows_fst = powers(fst, self.degree); // powers of fst
+        let mut res = vec![Scalar::zero(); self.degree + 1];
+        Self::triangle(self.degree)
+            .zip(self.coeffs.iter())
+            .for_each(|((i, j), coeff)| {
+                res[j] += *coeff * pows_fst[i];
+                if i != j {
+                    res[i] += *coeff * pows_fst[j]
+                }
+            });
+        Univar::from_coefficients_vec(res)
+    }
 
-        coeffs.into_iter().enumerate().for_each(|(i, coeffs_snds)| {
-            coeffs_snds
-                .into_iter()
-                .enumerate()
-                .for_each(|(j, coeff)| res[j] += coeff * pows_fst[i]);
-        });
+    // evaluate at the second term, which is the same by symmetry
+    pub fn eval_snd(&self, snd: Scalar) -> Univar {
+        self.eval_fst(snd)
+    }
 
-        Univar::from_coefficients_vec(res)
+    // evaluate at `(x, y)`
+    pub fn evaluate(&self, x: Scalar, y: Scalar) -> Scalar {
+        self.eval_fst(x).evaluate(&y)
     }
 
     // Generate a random symmetric bivariate polynomial of order `threshold`.
@@ -123,22 +150,35 @@ impl Bivar {
         threshold: u32,
         rng: &mut R,
     ) -> Secret {
-        let threshold = threshold as usize;
-        let mut coeffs =
-            vec![vec![Scalar::zero(); threshold + 1]; threshold + 1];
-
-        // by symmetry, `res[i][j] = res[j][i]`
-        for i in 0..=threshold {
-            for j in 0..=i {
-                let coeff = Scalar::rand(rng);
-                coeffs[i][j] += coeff;
-                if i != j {
-                    coeffs[j][i] += coeff
-                }
-            }
-        }
+        let degree = threshold as usize;
+        let coeffs = (0..Self::triangle_len(degree))
+            .map(|_| Scalar::rand(rng))
+            .collect();
+        Bivar { degree, coeffs }
+    }
+}
+
+impl<'a> Add<&'a Bivar> for &'a Bivar {
+    type Output = Bivar;
+
+    fn add(self, rhs: &'a Bivar) -> Bivar {
+        let degree = usize::max(self.degree, rhs.degree);
+        let coeffs = Bivar::triangle(degree)
+            .map(|(i, j)| self.coeff(i, j) + rhs.coeff(i, j))
+            .collect();
+        Bivar { degree, coeffs }
+    }
+}
 
-        Bivar::from_coeffs(&coeffs)
+impl Mul<Scalar> for &Bivar {
+    type Output = Bivar;
+
+    fn mul(self, rhs: Scalar) -> Bivar {
+        let coeffs = self.coeffs.iter().map(|coeff| *coeff * rhs).collect();
+        Bivar {
+            degree: self.degree,
+            coeffs,
+        }
     }
 }
 
@@ -171,8 +211,9 @@ A secret polynomial, used during the setup phase.
 The element at (0, 0) is the free coefficient of the polynomial.
 For example, the polynomial
 `f(x, y) = c_0_0 + ... + c_i_j * x^i * j^i + ... + c_{t-1}_{t-1} * x^{t-1} * y^{t-1}`
-is encoded as
-`vec![vec![c_0_0, ..., c_0_{t-1}], ..., vec![c_{t-1}_0, ..., c_{t-1}_{t-1}]]`.
+has the coefficients
+`vec![vec![c_0_0, ..., c_0_{t-1}], ..., vec![c_{t-1}_0, ..., c_{t-1}_{t-1}]]`,
+of which only the upper triangle is stored.
 */
 pub type Secret = Bivar;
 
@@ -192,23 +233,10 @@ pub fn random_secret<R: rand::Rng + Sized>(
     s: Scalar,
     rng: &mut R,
 ) -> Secret {
-    let threshold = threshold as usize;
-    let mut coeffs = vec![vec![Scalar::zero(); threshold + 1]; threshold + 1];
-
     // secret polynomials are symmetric, so res[i][j] = res[j][i]
-    for i in 0..=threshold {
-        for j in 0..=i {
-            let coeff = Scalar::rand(rng);
-            coeffs[i][j] += coeff;
-            if i != j {
-                coeffs[j][i] += coeff
-            }
-        }
-    }
-
-    coeffs[0][0] = s;
-
-    Bivar::from_coeffs(&coeffs)
+    let mut secret = Bivar::random_symmetric_secret(threshold, rng);
+    secret.coeffs[0] = s; // c_0_0
+    secret
 }
 
 fn mul_g1proj(lhs: G1Projective, rhs: Scalar) -> G1Projective {
@@ -217,37 +245,92 @@ fn mul_g1proj(lhs: G1Projective, rhs: Scalar) -> G1Projective {
     lhs
 }
 
-// Generate the public polynomial for a given secret polynomial.
+/* Generate the public polynomial for a given secret polynomial.
+By symmetry, each coefficient in the upper triangle is committed once. */
 pub fn public(secret: &Secret) -> Public {
-    secret
-        .coeffs()
-        .into_iter()
-        .map(|coeffs| {
-            coeffs
-                .into_iter()
-                .map(|coeff| {
-                    mul_g1proj(G1Projective::prime_subgroup_generator(), coeff)
-                        .into_affine()
-                })
-                .collect()
+    let triangle: Vec<G1Affine> = secret
+        .coeffs
+        .iter()
+        .map(|coeff| {
+            mul_g1proj(G1Projective::prime_subgroup_generator(), *coeff)
+                .into_affine()
         })
-        .collect()
+        .collect();
+    secret.expand(&triangle)
 }
 
 pub fn public_wnaf(secret: &Secret) -> Public {
     let window_size = 4; // arbitrarily chosen
     let wnaf = WnafContext::new(window_size);
-    secret
-        .coeffs()
+    let triangle: Vec<G1Affine> = secret
+        .coeffs
         .iter()
-        .map(|coeffs| {
-            coeffs
-                .iter()
-                .map(|coeff| {
-                    wnaf.mul(G1Projective::prime_subgroup_generator(), coeff)
-                        .into_affine()
-                })
-                .collect()
+        .map(|coeff| {
+            wnaf.mul(G1Projective::prime_subgroup_generator(), coeff)
+                .into_affine()
+        })
+        .collect();
+    secret.expand(&triangle)
+}
+
+lazy_static::lazy_static! {
+    // hashing to the curve is slow, so the generator is computed once
+    static ref PEDERSEN_GENERATOR: G1Affine = {
+        let h =
+            crate::hash_to_curve::htp_bls12381_g1(b"ferveo-pedersen-generator");
+        let mut bytes = h.to_uncompressed();
+        bytes[0] &= 0x1f; // clear the flag bits
+        let x = Fq::from_be_bytes_mod_order(&bytes[..48]);
+        let y = Fq::from_be_bytes_mod_order(&bytes[48..]);
+        G1Affine::new(x, y, false)
+    };
+}
+
+/* An independent generator of G1 for pedersen commitments.
+It is derived with hash-to-curve, so no discrete log relative to
+the standard generator is known. */
+pub fn pedersen_generator() -> G1Affine {
+    *PEDERSEN_GENERATOR
+}
+
+/* Generate the pedersen commitment to a secret polynomial,
+hidden by a random blinding polynomial of the same degree.
+Each coefficient is committed as `g * c_i_j + h * b_i_j`,
+so that the commitment reveals nothing about the secret.
+Returns the commitment and the blinding polynomial. */
+pub fn public_pedersen<R: rand::Rng + Sized>(
+    secret: &Secret,
+    h: G1Affine,
+    rng: &mut R,
+) -> (Public, Secret) {
+    let blinding = Bivar::random_symmetric_secret(secret.degree as u32, rng);
+    let triangle: Vec<G1Affine> = secret
+        .coeffs
+        .iter()
+        .zip(blinding.coeffs.iter())
+        .map(|(coeff, blinding_coeff)| {
+            let g = G1Projective::prime_subgroup_generator();
+            (mul_g1proj(g, *coeff) + mul_g1proj(h.into(), *blinding_coeff))
+                .into_affine()
+        })
+        .collect();
+    (secret.expand(&triangle), blinding)
+}
+
+/* Remove the blinding from a pedersen commitment,
+given the coefficients `r` of the blinding polynomial at `(x, 0)`.
+The result commits to the secret polynomial at `(x, 0)`,
+with a single element in each row,
+so it can be evaluated with `public_share`.
+Empty rows are treated as the identity. */
+pub fn unblind(p: &Public, r: &[Scalar], h: G1Affine) -> Public {
+    p.iter()
+        .enumerate()
+        .map(|(j, p_j)| {
+            let r_j = r.get(j).cloned().unwrap_or_else(Scalar::zero);
+            let p_j0: G1Projective =
+                p_j.first().cloned().unwrap_or_else(G1Affine::zero).into();
+            vec![(p_j0 - mul_g1proj(h.into(), r_j)).into_affine()]
         })
         .collect()
 }
@@ -257,85 +340,311 @@ pub fn share(secret: &Secret, j: Scalar) -> Share {
     secret.eval_fst(j)
 }
 
-/// Generate `participants` many secret shares
+/* Generate `participants` many secret shares in one FFT pass,
+where the `k`th share is at the `k`th power of the domain generator.
+Each coefficient of the shares is the FFT of a column of coefficients,
+folded modulo the domain size, since `omega^size = 1`. */
 pub fn multi_share(secret: &Secret, participants: usize) -> Vec<Share> {
     let domain = fft::domain(participants);
     let (omega, log_n) = (domain.group_gen, domain.log_size_of_group);
+    let size = domain.size as usize;
 
-    let num_eval_pts =
-        usize::max(participants, secret.fst_degree() + 1).next_power_of_two();
-
-    let mut evals = Vec::with_capacity(secret.snd_degree() + 1);
-    for col in secret.coeffs().iter() {
-        let mut row = Vec::with_capacity(num_eval_pts);
-        row.extend(col.iter());
-        row.resize(num_eval_pts, Scalar::zero());
-        fft::fft(&mut row, omega, log_n);
-        evals.push(row);
-    }
-    let mut shares = Vec::with_capacity(secret.snd_degree() + 1);
-    let mut evals_iters: Vec<_> = evals.iter_mut().map(|e| e.iter()).collect();
-
-    for _ in 0..=secret.snd_degree() {
-        let mut share = Vec::with_capacity(num_eval_pts);
-        for i in &mut evals_iters {
-            if let Some(v) = i.next() {
-                share.push(*v);
+    // `evals[j][k]` is the coefficient of `y^j` in the `k`th share
+    let evals: Vec<Vec<Scalar>> = (0..=secret.degree)
+        .map(|j| {
+            let mut col = vec![Scalar::zero(); size];
+            for i in 0..=secret.degree {
+                col[i % size] += secret.coeff(i, j);
             }
-        }
-        shares.push(Share::from_coefficients_vec(share));
+            fft::fft(&mut col, omega, log_n);
+            col
+        })
+        .collect();
+    (0..participants)
+        .map(|k| {
+            let share = evals.iter().map(|evals_j| evals_j[k]).collect();
+            Share::from_coefficients_vec(share)
+        })
+        .collect()
+}
+
+/* The index of the first domain point of each participant,
+for participant weights `w`.
+Participant `j` has the `w[j]` consecutive domain points
+starting from this index. */
+pub fn share_indexes(w: &[u32]) -> Vec<usize> {
+    w.iter()
+        .scan(0usize, |total, weight| {
+            let index = *total;
+            *total += *weight as usize;
+            Some(index)
+        })
+        .collect()
+}
+
+/* Evaluate the public polynomial at `(x, 0)`.
+This is the public counterpart of the secret `f(x, 0)`.
+Empty rows are treated as the identity. */
+pub fn public_share(p: &Public, x: Scalar) -> G1Affine {
+    // ∑_{j=0}^t (p_j_0 * x^j)
+    let x_j = powers(x, p.len());
+    let (bases, scalars): (Vec<G1Affine>, Vec<Scalar>) =
+        column_terms(p, 0, &x_j).unzip();
+    msm(&bases, &scalars).into_affine()
+}
+
+// ∑_k bases_k * scalars_k, as one multi-scalar multiplication
+fn msm(bases: &[G1Affine], scalars: &[Scalar]) -> G1Projective {
+    let scalars: Vec<_> = scalars.iter().map(|s| s.into_repr()).collect();
+    VariableBaseMSM::multi_scalar_mul(bases, &scalars)
+}
+
+/* Check that `1_{G1} * x + h * x_hat = ∑_k base_k * scalar_k`
+for the given terms, with a single MSM.
+The blinding term `h * x_hat` is only included in pedersen mode. */
+fn check_msm<I>(
+    terms: I,
+    x: Scalar,
+    blinding: Option<(Scalar, G1Affine)>,
+) -> bool
+where
+    I: IntoIterator<Item = (G1Affine, Scalar)>,
+{
+    let (mut bases, mut scalars): (Vec<G1Affine>, Vec<Scalar>) =
+        terms.into_iter().unzip();
+    bases.push(G1Affine::prime_subgroup_generator());
+    scalars.push(-x);
+    if let Some((x_hat, h)) = blinding {
+        bases.push(h);
+        scalars.push(-x_hat);
     }
-    shares
+    msm(&bases, &scalars).is_zero()
 }
 
-// Verify that the given share with index `i` is consistent with the public polynomial.
-pub fn verify_share(p: &Public, s: &Share, i: Scalar) -> bool {
-    // ∀ l ∈ [0, t]. 1_{G1} * s_l = ∑_{j=0}^t (p_j_l * i^j)
-    s.coeffs().iter().enumerate().all(|(l, s_l)| {
-        let lhs = mul_g1proj(G1Projective::prime_subgroup_generator(), *s_l);
-        let mut rhs = G1Projective::zero();
-        for (j, pj) in p.iter().enumerate() {
-            rhs += mul_g1proj(pj[l].into(), scalar_exp_usize(i, j))
-        }
-        lhs == rhs
-    })
+/* determine if the public polynomial has `t + 1` rows of `t + 1` points,
+and is symmetric, as for a secret polynomial of degree `t` */
+pub fn shaped(p: &Public, t: u32) -> bool {
+    let len = t as usize + 1;
+    p.len() == len
+        && p.iter().all(|p_j| p_j.len() == len)
+        && (0..len).all(|j| (0..j).all(|l| p[j][l] == p[l][j]))
 }
 
-// Verify that the given share with x coordinate `x` is consistent with the public polynomial.
-fn verify_share_fft(p: &Public, s: &Share, x: Scalar) -> bool {
-    // ∀ l ∈ [0, t]. 1_{G1} * s_l = ∑_{j=0}^t (p_j_l * i^j)
-    s.iter().enumerate().all(|(l, sl)| {
-        let lhs = mul_g1proj(G1Projective::prime_subgroup_generator(), *sl);
-        let mut rhs = G1Projective::zero();
-        let mut X = Scalar::one();
-        for pj in p.iter() {
-            rhs += mul_g1proj(pj[l].into(), X);
-            X *= x;
-        }
-        lhs == rhs
+// the length of the longest row of the public polynomial
+fn width(p: &Public) -> usize {
+    p.iter().map(Vec::len).max().unwrap_or(0)
+}
+
+/* the terms `(p_j_l, i^j)` of the `l`th column of the public polynomial,
+skipping rows that are too short */
+fn column_terms<'a>(
+    p: &'a Public,
+    l: usize,
+    i_j: &'a [Scalar],
+) -> impl Iterator<Item = (G1Affine, Scalar)> + 'a {
+    p.iter()
+        .zip(i_j.iter())
+        .filter_map(move |(p_j, i_j)| p_j.get(l).map(|p_jl| (*p_jl, *i_j)))
+}
+
+/* the terms `(p_j_l, u_j * v_l)` of the public polynomial,
+where `u` and `v` are power vectors of the x and y coordinates */
+fn grid_terms<'a>(
+    p: &'a Public,
+    u: &'a [Scalar],
+    v: &'a [Scalar],
+) -> impl Iterator<Item = (G1Affine, Scalar)> + 'a {
+    p.iter().zip(u.iter()).flat_map(move |(p_j, u_j)| {
+        p_j.iter()
+            .zip(v.iter())
+            .map(move |(p_jl, v_l)| (*p_jl, *u_j * v_l))
     })
 }
 
+/* Check every coefficient of the share `s` with index `i`
+against a column of the public polynomial,
+with the blinding share against `h` in pedersen mode.
+Missing coefficients are zero, and shares with more coefficients
+than the public polynomial has columns are rejected. */
+fn verify_share_columns(
+    p: &Public,
+    s: &Share,
+    blinding: Option<(&Share, G1Affine)>,
+    i: Scalar,
+) -> bool {
+    // ∀ l ∈ [0, t]. 1_{G1} * s_l + h * s_hat_l = ∑_{j=0}^t (p_j_l * i^j)
+    let coeff = |u: &Share, l: usize| {
+        u.coeffs().get(l).cloned().unwrap_or_else(Scalar::zero)
+    };
+    let len = p.first().map_or(0, |p_0| p_0.len());
+    let i_j = powers(i, p.len());
+    s.coeffs().len() <= len
+        && blinding
+            .iter()
+            .all(|(s_hat, _)| s_hat.coeffs().len() <= len)
+        && (0..len).all(|l| {
+            let blinding = blinding.map(|(s_hat, h)| (coeff(s_hat, l), h));
+            check_msm(column_terms(p, l, &i_j), coeff(s, l), blinding)
+        })
+}
+
+// Verify that the given share with index `i` is consistent with the public polynomial.
+pub fn verify_share(p: &Public, s: &Share, i: Scalar) -> bool {
+    verify_share_columns(p, s, None, i)
+}
+
 // Verify that a given point from node `m` with index `i` is consistent with the public polynomial.
 pub fn verify_point(p: &Public, i: Scalar, m: Scalar, x: Scalar) -> bool {
     // 1_{G1} * x = ∑_{j,l=0}^t (p_j_l * m^j * i^l)
-    let lhs = mul_g1proj(G1Projective::prime_subgroup_generator(), x);
-    let rhs: G1Projective = p
-        .into_iter()
-        .enumerate()
-        .map(|(j, p_j)| {
-            p_j.into_iter()
-                .enumerate()
-                .map(|(l, p_jl)| {
-                    mul_g1proj(
-                        p_jl.clone().into(),
-                        scalar_exp_usize(m, j) * scalar_exp_usize(i, l),
-                    )
-                })
-                .sum::<G1Projective>()
-        })
-        .sum();
-    lhs == rhs
+    let m_j = powers(m, p.len());
+    let i_l = powers(i, width(p));
+    check_msm(grid_terms(p, &m_j, &i_l), x, None)
+}
+
+/* Verify that the given share and blinding share with index `i`
+are consistent with the pedersen commitment `p` */
+pub fn verify_share_pedersen(
+    p: &Public,
+    s: &Share,
+    s_hat: &Share,
+    i: Scalar,
+    h: G1Affine,
+) -> bool {
+    verify_share_columns(p, s, Some((s_hat, h)), i)
+}
+
+/* Verify that a given point and blinding point from node `m` with index `i`
+are consistent with the pedersen commitment `p` */
+pub fn verify_point_pedersen(
+    p: &Public,
+    i: Scalar,
+    m: Scalar,
+    x: Scalar,
+    x_hat: Scalar,
+    h: G1Affine,
+) -> bool {
+    // 1_{G1} * x + h * x_hat = ∑_{j,l=0}^t (p_j_l * m^j * i^l)
+    let m_j = powers(m, p.len());
+    let i_l = powers(i, width(p));
+    check_msm(grid_terms(p, &m_j, &i_l), x, Some((x_hat, h)))
+}
+
+// ∑_a w_a * (1, x_a, x_a^2, ..., x_a^n)
+fn weighted_powers(w: &[Scalar], xs: &[Scalar], n: usize) -> Vec<Scalar> {
+    let mut res = vec![Scalar::zero(); n + 1];
+    for (w_a, x_a) in w.iter().zip(xs.iter()) {
+        let mut term = *w_a; // w_a * x_a^j
+        for res_j in res.iter_mut() {
+            *res_j += term;
+            term *= x_a;
+        }
+    }
+    res
+}
+
+/* Check the points with indexes `range` against the public polynomial `p`
+with a single random linear combination,
+`1_{G1} * ∑_k r_k * x_k = ∑_{j,l=0}^t (p_j_l * ∑_k r_k * m_k^j * i_k^l)`.
+The points are grouped by `i`, so that for `D` distinct `i`s,
+the scalars for the MSM take `O(K * t + D * t^2)` field operations. */
+fn check_points_batch<R: rand::Rng>(
+    p: &Public,
+    points: &[(Scalar, Scalar, Scalar)],
+    blinding: Option<(&[Scalar], G1Affine)>,
+    range: Range<usize>,
+    rng: &mut R,
+) -> bool {
+    let r: Vec<Scalar> = range.clone().map(|_| Scalar::rand(rng)).collect();
+    // the weights and x coordinates of the points, for each `i`
+    let mut groups: HashMap<Scalar, (Vec<Scalar>, Vec<Scalar>)> =
+        HashMap::new();
+    for (r_k, (m_k, i_k, _)) in r.iter().zip(points[range.clone()].iter()) {
+        let (r_i, m_i) = groups.entry(*i_k).or_default();
+        r_i.push(*r_k);
+        m_i.push(*m_k);
+    }
+    // s_j_l = ∑_i (∑_{k : i_k = i} r_k * m_k^j) * i^l
+    let width = width(p);
+    let mut s = vec![vec![Scalar::zero(); width + 1]; p.len() + 1];
+    for (i, (r_i, m_i)) in groups.iter() {
+        let u = weighted_powers(r_i, m_i, p.len());
+        let v = powers(*i, width);
+        for (s_j, u_j) in s.iter_mut().zip(u.iter()) {
+            for (s_jl, v_l) in s_j.iter_mut().zip(v.iter()) {
+                *s_jl += *u_j * v_l
+            }
+        }
+    }
+    let terms = p.iter().zip(s.iter()).flat_map(|(p_j, s_j)| {
+        p_j.iter()
+            .zip(s_j.iter())
+            .map(|(p_jl, s_jl)| (*p_jl, *s_jl))
+    });
+    let combine = |xs: &[Scalar]| -> Scalar {
+        r.iter()
+            .zip(xs[range.clone()].iter())
+            .map(|(r_k, x_k)| *r_k * x_k)
+            .sum()
+    };
+    let x: Vec<Scalar> = points.iter().map(|(_, _, x)| *x).collect();
+    let blinding = blinding.map(|(x_hat, h)| (combine(x_hat), h));
+    check_msm(terms, combine(&x), blinding)
+}
+
+/* Find the invalid points with indexes in `range`,
+by bisecting the range until the batch checks pass */
+fn find_invalid_points<R: rand::Rng>(
+    p: &Public,
+    points: &[(Scalar, Scalar, Scalar)],
+    blinding: Option<(&[Scalar], G1Affine)>,
+    range: Range<usize>,
+    rng: &mut R,
+    invalid: &mut Vec<usize>,
+) {
+    if range.is_empty()
+        || check_points_batch(p, points, blinding, range.clone(), rng)
+    {
+        return;
+    }
+    if range.len() == 1 {
+        invalid.push(range.start);
+        return;
+    }
+    let mid = range.start + range.len() / 2;
+    find_invalid_points(p, points, blinding, range.start..mid, rng, invalid);
+    find_invalid_points(p, points, blinding, mid..range.end, rng, invalid);
+}
+
+/* Batch verify many points `(m, i, x)` against the public polynomial `p`,
+where each `x` should be the evaluation at `(m, i)`,
+and `x_hat` the blinding evaluation against `h` in pedersen mode.
+All of the points are checked with a single random linear combination.
+If the batch fails, it is bisected to find the invalid points,
+which takes `O(b * log(K))` checks for `b` invalid points out of `K`.
+Returns the indexes of the invalid points, in increasing order,
+which are all of the points if there is not one blinding point for each. */
+pub fn verify_points_batch<R: rand::Rng>(
+    p: &Public,
+    points: &[(Scalar, Scalar, Scalar)],
+    blinding: Option<(&[Scalar], G1Affine)>,
+    rng: &mut R,
+) -> Vec<usize> {
+    if blinding
+        .iter()
+        .any(|(x_hat, _)| x_hat.len() != points.len())
+    {
+        return (0..points.len()).collect();
+    }
+    let mut invalid = Vec::new();
+    find_invalid_points(
+        p,
+        points,
+        blinding,
+        0..points.len(),
+        rng,
+        &mut invalid,
+    );
+    invalid
 }
 
 // Univariate polynomial product
@@ -349,41 +658,359 @@ fn poly_prod(x: &Univar, y: &Univar) -> Univar {
     Univar::from_coefficients_vec(coeffs)
 }
 
-// lagrange basis polynomial L_n_j(x)
-fn lagrange_basis(j: usize, xs: &Vec<Scalar>) -> Vec<Scalar> {
-    // numerator
-    let mut num = Univar::from_coefficients_vec(vec![Scalar::one()]);
-    let mut den = Scalar::one(); // denominator
-    for (k, xk) in xs.iter().enumerate() {
-        if k != j {
-            num = poly_prod(
-                &num,
-                &Univar::from_coefficients_vec(vec![-(*xk), Scalar::one()]),
-            );
-            den *= xs[j] - *xk
+/* Degree below which polynomials are multiplied and divided
+with the schoolbook algorithms, rather than with FFTs */
+const FFT_THRESHOLD: usize = 32;
+
+// Univariate polynomial product, with an FFT for large degrees
+fn poly_mul(x: &Univar, y: &Univar) -> Univar {
+    if x.is_zero() || y.is_zero() {
+        Univar::zero()
+    } else if usize::min(x.degree(), y.degree()) < FFT_THRESHOLD {
+        poly_prod(x, y)
+    } else {
+        x * y
+    }
+}
+
+// the first `m` coefficients of `x`, ie. `x mod x^m`
+fn truncate(x: &Univar, m: usize) -> Univar {
+    Univar::from_coefficients_slice(&x.coeffs[..usize::min(m, x.coeffs.len())])
+}
+
+// the coefficients of `x` in reverse order, padded to length `len`
+fn reverse(x: &Univar, len: usize) -> Univar {
+    let mut coeffs = x.coeffs.clone();
+    coeffs.resize(len, Scalar::zero());
+    coeffs.reverse();
+    Univar::from_coefficients_vec(coeffs)
+}
+
+/* The inverse of `x` modulo `x^k` by Newton iteration,
+for `x` with a nonzero constant coefficient */
+fn poly_inv_mod(x: &Univar, k: usize) -> Univar {
+    let two = Univar::from_coefficients_vec(vec![Scalar::from(2u64)]);
+    let mut res =
+        Univar::from_coefficients_vec(vec![x.coeffs[0].inverse().unwrap()]);
+    let mut m = 1; // `res` is the inverse modulo `x^m`
+    while m < k {
+        m = usize::min(2 * m, k);
+        // res = res * (2 - x * res) mod x^m
+        let x_res = truncate(&poly_mul(&truncate(x, m), &res), m);
+        res = truncate(&poly_mul(&res, &(&two - &x_res)), m);
+    }
+    res
+}
+
+/* Univariate polynomial division, returning the quotient and remainder.
+Large divisions use a Newton inverse of the reversed divisor,
+so that they take O(n log n) time. */
+fn poly_div_rem_fast(x: &Univar, y: &Univar) -> (Univar, Univar) {
+    if x.is_zero() || x.degree() < y.degree() || y.degree() < FFT_THRESHOLD {
+        return poly_div_rem(x, y);
+    }
+    // rev(q) = rev(x) / rev(y) mod x^(m + 1)
+    let m = x.degree() - y.degree();
+    let x_rev = reverse(x, x.degree() + 1);
+    let y_rev = reverse(y, y.degree() + 1);
+    let q_rev =
+        poly_mul(&truncate(&x_rev, m + 1), &poly_inv_mod(&y_rev, m + 1));
+    let q = reverse(&truncate(&q_rev, m + 1), m + 1);
+    let r = x - &poly_mul(&q, y);
+    (q, r)
+}
+
+// the formal derivative of `x`
+fn derivative(x: &Univar) -> Univar {
+    let coeffs = x
+        .coeffs
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, x_i)| Scalar::from(i as u64) * x_i)
+        .collect();
+    Univar::from_coefficients_vec(coeffs)
+}
+
+/* A subproduct tree over the points `xs`.
+The leaves are `x - x_i`, each node is the product of its children,
+and the root is `∏_i (x - x_i)`.
+An unpaired node is carried up to the next level unchanged. */
+struct SubproductTree(Vec<Vec<Univar>>);
+
+impl SubproductTree {
+    fn new(xs: &[Scalar]) -> Self {
+        let leaves = xs
+            .iter()
+            .map(|x| Univar::from_coefficients_vec(vec![-*x, Scalar::one()]))
+            .collect();
+        let mut levels: Vec<Vec<Univar>> = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [lhs, rhs] => poly_mul(lhs, rhs),
+                    [node] => node.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        SubproductTree(levels)
+    }
+
+    fn root(&self) -> Univar {
+        self.0.last().unwrap().first().cloned().unwrap_or_else(|| {
+            Univar::from_coefficients_vec(vec![Scalar::one()])
+        })
+    }
+
+    /* Evaluate `f` at every point,
+    by reducing it modulo each node from the root down to the leaves */
+    fn evaluate(&self, f: &Univar) -> Vec<Scalar> {
+        let mut rems = vec![f.clone()];
+        for level in self.0.iter().rev() {
+            rems = level
+                .iter()
+                .enumerate()
+                .map(|(k, node)| poly_div_rem_fast(&rems[k / 2], node).1)
+                .collect();
+        }
+        rems.iter()
+            .map(|rem| rem.coeffs.first().cloned().unwrap_or_else(Scalar::zero))
+            .collect()
+    }
+
+    /* The linear combination `∑_i c_i * ∏_{j != i} (x - x_j)`,
+    combining pairs of nodes from the leaves up to the root */
+    fn combine(&self, cs: &[Scalar]) -> Univar {
+        let mut res: Vec<Univar> = cs
+            .iter()
+            .map(|c| Univar::from_coefficients_vec(vec![*c]))
+            .collect();
+        for level in self.0[..self.0.len() - 1].iter() {
+            res = res
+                .chunks(2)
+                .zip(level.chunks(2))
+                .map(|pair| match pair {
+                    ([lhs, rhs], [lhs_node, rhs_node]) => {
+                        &poly_mul(lhs, rhs_node) + &poly_mul(rhs, lhs_node)
+                    }
+                    ([node], _) => node.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        res.pop().unwrap_or_else(Univar::zero)
+    }
+}
+
+/* If the points are exactly the elements of an FFT domain, in any order,
+interpolate with a single inverse FFT */
+fn interpolate_domain(xs: &[Scalar], ys: &[Scalar]) -> Option<Univar> {
+    let n = xs.len();
+    if !n.is_power_of_two() {
+        return None;
+    }
+    let domain = fft::domain(n);
+    let index: HashMap<Scalar, usize> =
+        domain.elements().enumerate().map(|(k, x)| (x, k)).collect();
+    let mut evals = vec![None; n];
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        if evals[*index.get(x)?].replace(*y).is_some() {
+            return None;
         }
     }
-    den = den.inverse().unwrap();
-    num.coeffs().iter().map(|v| *v * den).collect()
+    let evals: Vec<Scalar> = evals.into_iter().collect::<Option<_>>()?;
+    Some(Univar::from_coefficients_vec(domain.ifft(&evals)))
 }
 
+/* Interpolate the polynomial of degree less than `n` through `n` points,
+with distinct domain points.
+Takes O(n log^2 n) time with a subproduct tree:
+the weights `1 / M'(x_i)`, where `M = ∏_i (x - x_i)`,
+are found by evaluating `M'` down the tree and one batch inversion,
+and `∑_i (y_i / M'(x_i)) * M(x) / (x - x_i)` is combined up the tree. */
 pub fn lagrange_interpolate<I>(points: I) -> Univar
 where
     I: IntoIterator<Item = (Scalar, Scalar)>,
 {
     let (xs, ys): (Vec<Scalar>, Vec<Scalar>) = points.into_iter().unzip();
-    let mut res = Univar::from_coefficients_vec(Vec::new());
-    for (j, yj) in ys.iter().enumerate() {
-        res += &Univar::from_coefficients_vec(
-            lagrange_basis(j, &xs)
-                .into_iter()
-                .map(|v| v * *yj)
-                .collect(),
-        )
+    if xs.is_empty() {
+        return Univar::zero();
+    }
+    if let Some(res) = interpolate_domain(&xs, &ys) {
+        return res;
     }
+    let tree = SubproductTree::new(&xs);
+    let mut weights = tree.evaluate(&derivative(&tree.root()));
+    batch_inversion(&mut weights);
+    let cs: Vec<Scalar> = weights.iter().zip(ys).map(|(w, y)| *w * y).collect();
+    tree.combine(&cs)
+}
+
+/* The lagrange coefficients at `x` for the domain points `xs`,
+such that `f(x) = ∑_i λ_i * f(x_i)` for `f` of degree less than `n`.
+`λ_i = ∏_{j != i} (x - x_j) / (x_i - x_j)`,
+with all of the denominators inverted in one batch. */
+pub fn lagrange_coefficients(xs: &[Scalar], x: Scalar) -> Vec<Scalar> {
+    if let Some(k) = xs.iter().position(|x_k| *x_k == x) {
+        let mut res = vec![Scalar::zero(); xs.len()];
+        res[k] = Scalar::one();
+        return res;
+    }
+    // ∏_j (x - x_j)
+    let numerator: Scalar = xs.iter().map(|x_j| x - x_j).product();
+    // the denominators `(x - x_i) * ∏_{j != i} (x_i - x_j)`
+    let mut res: Vec<Scalar> = xs
+        .iter()
+        .enumerate()
+        .map(|(i, x_i)| {
+            xs.iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .fold(x - x_i, |den, (_, x_j)| den * (*x_i - x_j))
+        })
+        .collect();
+    batch_inversion(&mut res);
+    res.iter_mut().for_each(|lambda_i| *lambda_i *= numerator);
     res
 }
 
+/* Combine the values `y_i` at the domain points `x_i` with the lagrange
+coefficients at `x`, ie. evaluate the interpolating polynomial at `x`.
+The values may be scalars, or group elements,
+to interpolate "in the exponent". */
+pub fn interpolate_at<T>(points: &[(Scalar, T)], x: Scalar) -> T
+where
+    T: Clone + Zero + AddAssign + MulAssign<Scalar>,
+{
+    let xs: Vec<Scalar> = points.iter().map(|(x_i, _)| *x_i).collect();
+    combine(
+        &lagrange_coefficients(&xs, x),
+        points.iter().map(|(_, y)| y),
+    )
+}
+
+// ∑_i λ_i * y_i
+fn combine<'a, T, I>(lambda: &[Scalar], ys: I) -> T
+where
+    T: 'a + Clone + Zero + AddAssign + MulAssign<Scalar>,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut res = T::zero();
+    for (lambda_i, y_i) in lambda.iter().zip(ys) {
+        let mut y_i = y_i.clone();
+        y_i *= *lambda_i;
+        res += y_i
+    }
+    res
+}
+
+/* A cache of the lagrange coefficients at a fixed point `x`,
+for subsets of the points of an FFT domain.
+Coefficients are keyed by the set of indexes `k` of the domain points `omega^k`,
+so repeated combinations over the same subset
+skip recomputing the coefficients. */
+pub struct LagrangeCache {
+    cache: HashMap<BTreeSet<usize>, Vec<Scalar>>,
+    domain: Radix2EvaluationDomain<Scalar>,
+    x: Scalar,
+}
+
+impl LagrangeCache {
+    pub fn new(domain: Radix2EvaluationDomain<Scalar>, x: Scalar) -> Self {
+        LagrangeCache {
+            cache: HashMap::new(),
+            domain,
+            x,
+        }
+    }
+
+    /* The lagrange coefficients at `x` for the domain points with indexes
+    `indexes`, in increasing order of index */
+    pub fn coefficients(&mut self, indexes: &BTreeSet<usize>) -> &[Scalar] {
+        let (domain, x) = (self.domain, self.x);
+        self.cache.entry(indexes.clone()).or_insert_with(|| {
+            let xs: Vec<Scalar> = indexes
+                .iter()
+                .map(|k| domain.group_gen.pow([*k as u64]))
+                .collect();
+            lagrange_coefficients(&xs, x)
+        })
+    }
+
+    /* Combine the values at the domain points with the given indexes,
+    ie. evaluate the interpolating polynomial at `x` */
+    pub fn interpolate<T>(&mut self, points: &BTreeMap<usize, T>) -> T
+    where
+        T: Clone + Zero + AddAssign + MulAssign<Scalar>,
+    {
+        let indexes = points.keys().cloned().collect();
+        combine(self.coefficients(&indexes), points.values())
+    }
+}
+
+// Univariate polynomial division, returning the quotient and remainder
+fn poly_div_rem(x: &Univar, y: &Univar) -> (Univar, Univar) {
+    assert!(!y.is_zero(), "division by the zero polynomial");
+    if x.is_zero() || x.degree() < y.degree() {
+        return (Univar::zero(), x.clone());
+    }
+    let mut rem = x.coeffs().to_vec();
+    let lead_inv = y.coeffs().last().unwrap().inverse().unwrap();
+    let mut quot = vec![Scalar::zero(); x.degree() - y.degree() + 1];
+    for i in (0..quot.len()).rev() {
+        let q_i = rem[i + y.degree()] * lead_inv;
+        for (j, yj) in y.coeffs().iter().enumerate() {
+            rem[i + j] -= q_i * yj
+        }
+        quot[i] = q_i
+    }
+    (
+        Univar::from_coefficients_vec(quot),
+        Univar::from_coefficients_vec(rem),
+    )
+}
+
+/* Decode a polynomial of degree at most `t` from the given points,
+some of which may be wrong, using Gao's Reed-Solomon decoding algorithm.
+With `n` points, decoding succeeds if at most `(n - t - 1) / 2` are wrong.
+Returns `None` if no such polynomial is found. */
+pub fn gao_decode(points: &[(Scalar, Scalar)], t: usize) -> Option<Univar> {
+    let n = points.len();
+    let k = t + 1;
+    if n < k {
+        return None;
+    }
+    // g_0 = ∏_i (x - x_i)
+    let xs: Vec<Scalar> = points.iter().map(|(x, _)| *x).collect();
+    let g0 = SubproductTree::new(&xs).root();
+    // g_1 interpolates every point
+    let g1 = lagrange_interpolate(points.iter().cloned());
+    /* run the extended euclidean algorithm on g_0 and g_1,
+    until the remainder has degree less than (n + k) / 2 */
+    let (mut r0, mut r1) = (g0, g1);
+    let (mut v0, mut v1) = (
+        Univar::zero(),
+        Univar::from_coefficients_vec(vec![Scalar::one()]),
+    );
+    while !r1.is_zero() && 2 * r1.degree() >= n + k {
+        let (q, r) = poly_div_rem_fast(&r0, &r1);
+        let v = &v0 - &poly_mul(&q, &v1);
+        r0 = std::mem::replace(&mut r1, r);
+        v0 = std::mem::replace(&mut v1, v);
+    }
+    // v_1 is the error locator, and r_1 = f * v_1
+    let (f, rem) = poly_div_rem_fast(&r1, &v1);
+    if rem.is_zero() && (f.is_zero() || f.degree() < k) {
+        Some(f)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,6 +1027,122 @@ mod tests {
         }
     }
 
+    #[test]
+    fn bivar_operations() {
+        let mut rng = rand::thread_rng();
+        let lhs = random_secret(4, Scalar::rand(&mut rng), &mut rng);
+        let rhs = random_secret(6, Scalar::rand(&mut rng), &mut rng);
+        let (x, y) = (Scalar::rand(&mut rng), Scalar::rand(&mut rng));
+        let z = Scalar::rand(&mut rng);
+
+        // evaluation agrees with the full matrix of coefficients
+        let expected: Scalar = lhs
+            .coeffs()
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| {
+                row.iter().enumerate().map(move |(j, c)| {
+                    *c * scalar_exp_usize(x, i) * scalar_exp_usize(y, j)
+                })
+            })
+            .sum();
+        assert_eq!(lhs.evaluate(x, y), expected);
+        assert_eq!(lhs.evaluate(x, y), lhs.evaluate(y, x));
+        assert_eq!(lhs.eval_snd(y).evaluate(&x), expected);
+        assert_eq!(Bivar::from_coeffs(&lhs.coeffs()), lhs);
+
+        let sum = &lhs + &rhs;
+        assert_eq!(sum.degree(), 6);
+        assert_eq!(sum.evaluate(x, y), expected + rhs.evaluate(x, y));
+        assert_eq!((&lhs * z).evaluate(x, y), expected * z);
+    }
+
+    // an empty matrix of coefficients is the zero polynomial
+    #[test]
+    fn bivar_from_empty_coeffs() {
+        let mut rng = rand::thread_rng();
+        let zero = Bivar::from_coeffs(&[]);
+        assert_eq!(zero.degree(), 0);
+        assert_eq!(zero.coeffs(), vec![vec![Scalar::zero()]]);
+        let (x, y) = (Scalar::rand(&mut rng), Scalar::rand(&mut rng));
+        assert_eq!(zero.evaluate(x, y), Scalar::zero());
+    }
+
+    #[test]
+    fn fast_interpolation() {
+        let mut rng = rand::thread_rng();
+        for n in [1, 2, 5, 33, 100, 257].iter() {
+            let f = Univar::rand(n - 1, &mut rng);
+            let points: Vec<(Scalar, Scalar)> = (0..*n)
+                .map(|_| {
+                    let x = Scalar::rand(&mut rng);
+                    (x, f.evaluate(&x))
+                })
+                .collect();
+            assert_eq!(lagrange_interpolate(points.clone()), f);
+            // the polynomial is decoded with no wrong points
+            assert_eq!(gao_decode(&points, n - 1), Some(f));
+        }
+
+        // the points of an FFT domain, out of order
+        let f = Univar::rand(40, &mut rng);
+        let domain = fft::domain(64);
+        let mut points: Vec<(Scalar, Scalar)> =
+            domain.elements().map(|x| (x, f.evaluate(&x))).collect();
+        points.reverse();
+        assert_eq!(lagrange_interpolate(points.clone()), f);
+        // a subset of an FFT domain
+        points.truncate(50);
+        assert_eq!(lagrange_interpolate(points), f);
+
+        // large divisions agree with schoolbook division
+        let x = Univar::rand(300, &mut rng);
+        let y = Univar::rand(100, &mut rng);
+        assert_eq!(poly_div_rem_fast(&x, &y), poly_div_rem(&x, &y));
+    }
+
+    #[test]
+    fn lagrange_coefficients_at_point() {
+        let mut rng = rand::thread_rng();
+        let f = Univar::rand(9, &mut rng);
+        let domain = fft::domain(16);
+        let x = Scalar::rand(&mut rng);
+        let points: BTreeMap<usize, Scalar> =
+            [0, 2, 3, 5, 7, 8, 11, 12, 14, 15]
+                .iter()
+                .map(|k| (*k, f.evaluate(&domain.element(*k))))
+                .collect();
+        let xy: Vec<(Scalar, Scalar)> = points
+            .iter()
+            .map(|(k, y)| (domain.element(*k), *y))
+            .collect();
+        assert_eq!(interpolate_at(&xy, x), f.evaluate(&x));
+        assert_eq!(interpolate_at(&xy, Scalar::zero()), f.coeffs[0]);
+        assert_eq!(interpolate_at(&xy, xy[3].0), xy[3].1);
+        // the coefficients interpolate constants
+        let xs: Vec<Scalar> = xy.iter().map(|(x_i, _)| *x_i).collect();
+        let lambda = lagrange_coefficients(&xs, x);
+        assert_eq!(lambda.iter().sum::<Scalar>(), Scalar::one());
+
+        // in the exponent
+        let g = G1Projective::prime_subgroup_generator();
+        let xg: Vec<(Scalar, G1Projective)> = xy
+            .iter()
+            .map(|(x_i, y_i)| (*x_i, mul_g1proj(g, *y_i)))
+            .collect();
+        assert_eq!(interpolate_at(&xg, x), mul_g1proj(g, f.evaluate(&x)));
+
+        // cached coefficients give the same results
+        let mut cache = LagrangeCache::new(domain, x);
+        assert_eq!(cache.interpolate(&points), f.evaluate(&x));
+        assert_eq!(cache.interpolate(&points), f.evaluate(&x));
+        let points_g = points
+            .iter()
+            .map(|(k, y)| (*k, mul_g1proj(g, *y)))
+            .collect();
+        assert_eq!(cache.interpolate(&points_g), mul_g1proj(g, f.evaluate(&x)));
+    }
+
     #[test]
     fn share_verification() {
         let mut rng = rand::thread_rng();
@@ -409,6 +1152,17 @@ mod tests {
         for i in 0..(threshold * 2) {
             assert!(verify_share(&public, &share(&secret, i.into()), i.into()))
         }
+
+        // shares with missing or extra coefficients are invalid
+        let i = Scalar::from(1u64);
+        let mut coeffs = share(&secret, i).coeffs;
+        coeffs.pop();
+        let truncated = Share::from_coefficients_slice(&coeffs);
+        assert!(!verify_share(&public, &truncated, i));
+        assert!(!verify_share(&public, &Share::zero(), i));
+        coeffs.extend([Scalar::one(), Scalar::one()].iter());
+        let extended = Share::from_coefficients_vec(coeffs);
+        assert!(!verify_share(&public, &extended, i));
     }
 
     #[test]
@@ -426,6 +1180,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn point_batch_verification() {
+        let mut rng = rand::thread_rng();
+        let threshold = 4;
+        let secret = random_secret(threshold, Scalar::rand(&mut rng), &mut rng);
+        let h = pedersen_generator();
+        let public = public(&secret);
+        let (public_h, blinding) = public_pedersen(&secret, h, &mut rng);
+        // points from many senders, at a few distinct `i`s
+        let coords: Vec<(Scalar, Scalar)> = (0..20u64)
+            .map(|k| (Scalar::from(k / 2), Scalar::from(100 + k % 3)))
+            .collect();
+        let mut points: Vec<(Scalar, Scalar, Scalar)> = coords
+            .iter()
+            .map(|(m, i)| (*m, *i, secret.evaluate(*m, *i)))
+            .collect();
+        let x_hat: Vec<Scalar> = coords
+            .iter()
+            .map(|(m, i)| blinding.evaluate(*m, *i))
+            .collect();
+        let pedersen = Some((&x_hat[..], h));
+        assert!(
+            verify_points_batch(&public, &points, None, &mut rng).is_empty()
+        );
+        assert!(verify_points_batch(&public_h, &points, pedersen, &mut rng)
+            .is_empty());
+
+        // the invalid points are found by bisection
+        for k in [3, 4, 17].iter() {
+            points[*k].2 += Scalar::one();
+        }
+        assert_eq!(
+            verify_points_batch(&public, &points, None, &mut rng),
+            [3, 4, 17]
+        );
+        assert_eq!(
+            verify_points_batch(&public_h, &points, pedersen, &mut rng),
+            [3, 4, 17]
+        );
+
+        // all of the points are invalid without a blinding point for each
+        let pedersen = Some((&x_hat[1..], h));
+        assert_eq!(
+            verify_points_batch(&public_h, &points, pedersen, &mut rng),
+            (0..points.len()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn gao_decoding() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let t = 4;
+        let n = 13;
+        let f = Univar::rand(t, &mut rng);
+        let omega = fft::domain(n).group_gen;
+        let mut points: Vec<(Scalar, Scalar)> = powers(omega, n - 1)
+            .into_iter()
+            .map(|x| (x, f.evaluate(&x)))
+            .collect();
+        // up to (n - t - 1) / 2 wrong points are corrected
+        for e in 0..=(n - t - 1) / 2 {
+            if e > 0 {
+                points[2 * e].1 = Scalar::rand(&mut rng);
+            }
+            assert_eq!(gao_decode(&points, t), Some(f.clone()));
+        }
+        // too few points
+        assert_eq!(gao_decode(&points[..t], t), None);
+    }
+
     #[test]
     fn test_multi_share() {
         let participants = 100u32;
@@ -437,15 +1262,21 @@ mod tests {
         let s = Scalar::rand(&mut rng);
         let secret = random_secret(threshold, s, &mut rng);
         let shares = multi_share(&secret, participants as usize);
+        assert_eq!(shares.len(), participants as usize);
         let omega = fft::domain(participants as usize).group_gen;
 
         let mut x = Scalar::one();
         for share in shares.iter() {
-            let actual = secret.eval_fst(x);
-            for (coeff, actual_coeff) in share.iter().zip(actual.iter()) {
-                assert_eq!(coeff, actual_coeff);
-            }
+            assert_eq!(*share, secret.eval_fst(x));
             x *= omega;
         }
+
+        // the threshold may exceed the domain size
+        let secret = random_secret(threshold, s, &mut rng);
+        let shares = multi_share(&secret, 8);
+        let omega = fft::domain(8).group_gen;
+        for (k, share) in shares.iter().enumerate() {
+            assert_eq!(*share, secret.eval_fst(omega.pow([k as u64])));
+        }
     }
 }
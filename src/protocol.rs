@@ -0,0 +1,22 @@
+/* Events produced by protocol contexts in response to messages */
+
+/* An event produced by a protocol context.
+`M` is the type of protocol messages,
+and `O` is the type of the protocol output. */
+#[derive(Clone, Debug)]
+pub enum Outgoing<M, O> {
+    Broadcast(M), // a message for every node, including this node
+    To(u32, M),   // a message for the node with the given index
+    Output(O),    // the terminal output of the protocol
+}
+
+/* Assert that `T` can be sent and shared between threads.
+Called from constant items, so that it is checked at compile time. */
+pub(crate) fn assert_send_sync<T: std::marker::Send + Sync>() {}
+
+/* determine if a weighted counter with value `x`
+has just reached the threshold `threshold`,
+after being incremented by `w` */
+pub(crate) fn reached(x: u32, w: u32, threshold: u32) -> bool {
+    x >= threshold && x - w < threshold
+}
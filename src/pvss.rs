@@ -0,0 +1,456 @@
+/*
+Publicly verifiable secret sharing (PVSS).
+Each participant's weighted shares are encrypted to its encryption key,
+so that a dealing can be posted on chain,
+and verified by any observer with pairings against the commitment.
+Each dealing is signed by its dealer,
+so that a dealing cannot be attributed to another participant.
+Dealings for the same session can be aggregated into a single transcript.
+*/
+
+#![allow(clippy::many_single_char_names)]
+#![allow(non_snake_case)]
+
+use crate::hybriddkg::SessionId;
+use crate::serialization::{Decode, Encode};
+use crate::{bls, fft, protocol, transcript};
+
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, PrimeField, UniformRand};
+use ark_poly::UVPolynomial;
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write,
+};
+use num::{One, Zero};
+use std::collections::BTreeMap;
+
+use crate::poly;
+
+type Scalar = Fr;
+
+// PVSS parameters
+#[derive(Clone)]
+pub struct Params {
+    pub ek: Vec<G2Affine>, // encryption key of each participant
+    pub epoch: u64,        // the epoch of the session
+    // the signing public key of each participant
+    pub pubkeys: Vec<bls12_381::G1Affine>,
+    pub t: u32,      // threshold
+    pub tau: u32,    // the session counter within the epoch
+    pub w: Vec<u32>, // weight of each participant
+}
+
+impl Params {
+    /* Fails unless there is exactly one encryption key
+    and one signing key for each participant. */
+    pub fn new(
+        session: SessionId,
+        t: u32,
+        w: Vec<u32>,
+        ek: Vec<G2Affine>,
+        pubkeys: Vec<bls12_381::G1Affine>,
+    ) -> Result<Self, Error> {
+        let SessionId { epoch, tau } = session;
+        let params = Params {
+            ek,
+            epoch,
+            pubkeys,
+            t,
+            tau,
+            w,
+        };
+        params.check_keys()?;
+        Ok(params)
+    }
+
+    // check that there is a key of each kind for each participant
+    fn check_keys(&self) -> Result<(), Error> {
+        if self.ek.len() != self.w.len() {
+            Err(Error::EncryptionKeys(self.n()))
+        } else if self.pubkeys.len() != self.w.len() {
+            Err(Error::PublicKeys(self.n()))
+        } else {
+            Ok(())
+        }
+    }
+
+    // the identifier of the session
+    pub fn session_id(&self) -> SessionId {
+        SessionId {
+            epoch: self.epoch,
+            tau: self.tau,
+        }
+    }
+
+    // return the number of participants in the setup
+    pub fn n(&self) -> u32 {
+        self.w.len() as u32
+    }
+
+    pub fn total_weight(&self) -> u32 {
+        self.w.iter().sum()
+    }
+
+    // the index of the first domain point of each participant
+    pub fn share_indexes(&self) -> Vec<usize> {
+        poly::share_indexes(&self.w)
+    }
+
+    // the index of the participant holding each domain point
+    fn owners(&self) -> Vec<usize> {
+        self.w
+            .iter()
+            .enumerate()
+            .flat_map(|(j, w_j)| (0..*w_j).map(move |_| j))
+            .collect()
+    }
+}
+
+// derive the encryption key for the decryption key `dk`
+pub fn encryption_key(dk: Scalar) -> G2Affine {
+    G2Affine::prime_subgroup_generator().mul(dk).into_affine()
+}
+
+/* A schnorr proof of knowledge of the secret of a dealer,
+for the commitment `F_0 = g * s`.
+The dealer signs the proof with its signing key,
+so that only the dealer can contribute a proof for itself. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pok {
+    pub F_0: G1Affine,
+    pub R: G1Affine,
+    pub z: Scalar,
+    pub sig: bls12_381::G2Affine, // the dealer's signature on the proof
+}
+
+impl Pok {
+    /* The hash of the proof, bound to the session and dealer,
+    so that proofs cannot be replayed by other dealers.
+    The challenge is derived from the hash, and the dealer signs it. */
+    fn hash(
+        session: &SessionId,
+        d: u32,
+        F_0: &G1Affine,
+        R: &G1Affine,
+    ) -> [u8; 32] {
+        let mut hash = transcript::Transcript::new(b"pvss-pok");
+        hash.append_encode(b"session", session);
+        hash.append_u32(b"d", d);
+        hash.append_encode(b"F_0", F_0);
+        hash.append_encode(b"R", R);
+        hash.finalize()
+    }
+
+    // prove knowledge of `s` as dealer `d`, with signing key `sk`
+    fn prove<R: rand::Rng + Sized>(
+        session: &SessionId,
+        d: u32,
+        s: Scalar,
+        sk: bls12_381::Scalar,
+        rng: &mut R,
+    ) -> Self {
+        let g = G1Affine::prime_subgroup_generator();
+        let r = Scalar::rand(rng);
+        let F_0 = g.mul(s).into_affine();
+        let R = g.mul(r).into_affine();
+        let hash = Self::hash(session, d, &F_0, &R);
+        let c = Scalar::from_le_bytes_mod_order(&hash);
+        Pok {
+            F_0,
+            R,
+            z: r + c * s,
+            sig: bls::sign_g2(sk, &hash),
+        }
+    }
+
+    // verify the proof for dealer `d`, with signing public key `pk`
+    pub fn verify(
+        &self,
+        session: &SessionId,
+        d: u32,
+        pk: &bls12_381::G1Affine,
+    ) -> bool {
+        let g = G1Affine::prime_subgroup_generator();
+        let hash = Self::hash(session, d, &self.F_0, &self.R);
+        let c = Scalar::from_le_bytes_mod_order(&hash);
+        bls::verify_g2(pk, &self.sig, &hash)
+            && g.mul(self.z) == self.R.into_projective() + self.F_0.mul(c)
+    }
+}
+
+/* A PVSS transcript, from a single dealing or an aggregate of dealings.
+`F` commits to the coefficients of the shared polynomial,
+and `Y[k]` is the share at the domain point with index `k`,
+encrypted to the participant holding that domain point. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcript {
+    pub session: SessionId,
+    pub F: Vec<G1Affine>,
+    pub Y: Vec<G2Affine>,
+    // the proof of knowledge of the secret of each dealer
+    pub dealers: BTreeMap<u32, Pok>,
+}
+
+// Errors that can occur when dealing or aggregating transcripts
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("dealer {0} is not a participant")]
+    Dealer(u32),
+    #[error("dealer {0} contributed to more than one transcript")]
+    DuplicateDealer(u32),
+    #[error("expected an encryption key for each of the {0} participants")]
+    EncryptionKeys(u32),
+    #[error("the transcripts have mismatched sizes")]
+    Mismatch,
+    #[error("no transcripts to aggregate")]
+    NoTranscripts,
+    #[error("expected a signing key for each of the {0} participants")]
+    PublicKeys(u32),
+    #[error("the transcripts are for different sessions")]
+    Session,
+}
+
+/* Deal the secret `s` as dealer `d`, with signing key `sk`,
+encrypting the shares of each participant to its encryption key */
+pub fn deal<R: rand::Rng + Sized>(
+    params: &Params,
+    d: u32,
+    s: Scalar,
+    sk: bls12_381::Scalar,
+    rng: &mut R,
+) -> Result<Transcript, Error> {
+    if d >= params.n() {
+        return Err(Error::Dealer(d));
+    }
+    params.check_keys()?;
+    let session = params.session_id();
+    let mut phi = poly::Univar::rand(params.t as usize, rng);
+    phi.coeffs[0] = s;
+    let g = G1Affine::prime_subgroup_generator();
+    let F = phi.coeffs.iter().map(|a| g.mul(*a).into_affine()).collect();
+    let domain = fft::domain(params.total_weight() as usize);
+    let evals = phi.evaluate_over_domain_by_ref(domain).evals;
+    let Y = params
+        .owners()
+        .into_iter()
+        .zip(evals)
+        .map(|(j, eval)| params.ek[j].mul(eval).into_affine())
+        .collect();
+    let mut dealers = BTreeMap::new();
+    dealers.insert(d, Pok::prove(&session, d, s, sk, rng));
+    Ok(Transcript {
+        session,
+        F,
+        Y,
+        dealers,
+    })
+}
+
+/* Aggregate transcripts for the same session from disjoint dealers.
+The aggregate shares the sum of the dealt secrets. */
+pub fn aggregate(transcripts: &[Transcript]) -> Result<Transcript, Error> {
+    let (first, rest) =
+        transcripts.split_first().ok_or(Error::NoTranscripts)?;
+    let mut res = first.clone();
+    for transcript in rest {
+        if transcript.session != res.session {
+            return Err(Error::Session);
+        }
+        if transcript.F.len() != res.F.len()
+            || transcript.Y.len() != res.Y.len()
+        {
+            return Err(Error::Mismatch);
+        }
+        for (d, pok) in transcript.dealers.iter() {
+            if res.dealers.insert(*d, pok.clone()).is_some() {
+                return Err(Error::DuplicateDealer(*d));
+            }
+        }
+        for (F_l, F_l_other) in res.F.iter_mut().zip(transcript.F.iter()) {
+            *F_l = *F_l + *F_l_other
+        }
+        for (Y_k, Y_k_other) in res.Y.iter_mut().zip(transcript.Y.iter()) {
+            *Y_k = *Y_k + *Y_k_other
+        }
+    }
+    Ok(res)
+}
+
+impl Transcript {
+    // the public key of the shared secret
+    pub fn public_key(&self) -> G1Affine {
+        self.F[0]
+    }
+
+    // evaluate the commitment at `x`
+    fn commitment_at(&self, x: Scalar) -> G1Affine {
+        // ∑_l (F_l * x^l)
+        let mut res = G1Projective::zero();
+        let mut xl = Scalar::one(); // x^l
+        for F_l in self.F.iter() {
+            res += F_l.mul(xl);
+            xl *= x;
+        }
+        res.into_affine()
+    }
+
+    /* Verify the transcript against the parameters.
+    Checks the signed proof of each dealer,
+    that the dealers' secrets sum to the committed secret,
+    and that each encrypted share matches the commitment:
+    `e(A_k, ek_j) = e(g, Y_k)`, where `A_k` is the commitment
+    evaluated at the domain point with index `k`,
+    held by participant `j`. */
+    pub fn verify(&self, params: &Params) -> bool {
+        let W = params.total_weight() as usize;
+        if self.session != params.session_id()
+            || self.F.len() != params.t as usize + 1
+            || self.Y.len() != W
+            || self.dealers.is_empty()
+            || params.check_keys().is_err()
+        {
+            return false;
+        }
+        let F_0: G1Projective = self
+            .dealers
+            .values()
+            .map(|pok| pok.F_0.into_projective())
+            .sum();
+        let dealers_valid = self.dealers.iter().all(|(d, pok)| {
+            params
+                .pubkeys
+                .get(*d as usize)
+                .iter()
+                .any(|pk| pok.verify(&self.session, *d, pk))
+        });
+        if !dealers_valid || F_0.into_affine() != self.F[0] {
+            return false;
+        }
+        let omega = fft::domain(W).group_gen;
+        let g = G1Affine::prime_subgroup_generator();
+        params.owners().into_iter().enumerate().all(|(k, j)| {
+            let A_k = self.commitment_at(omega.pow([k as u64]));
+            let ek_j = params.ek[j];
+            Bls12_381::pairing(A_k, ek_j) == Bls12_381::pairing(g, self.Y[k])
+        })
+    }
+
+    /* Decrypt the shares of participant `j` with its decryption key `dk`.
+    The share at the domain point `x` is returned as `h * f(x)`,
+    where `h` is the generator of G2.
+    Returns `None` if `j` is not a participant, `dk` is zero,
+    or the transcript does not have the shares of `j`. */
+    pub fn decrypt(
+        &self,
+        params: &Params,
+        j: u32,
+        dk: Scalar,
+    ) -> Option<Vec<G2Affine>> {
+        let start = *params.share_indexes().get(j as usize)?;
+        let w_j = params.w[j as usize] as usize;
+        let dk_inv = dk.inverse()?;
+        let Y_j = self.Y.get(start..start + w_j)?;
+        Some(
+            Y_j.iter()
+                .map(|Y_k| Y_k.mul(dk_inv).into_affine())
+                .collect(),
+        )
+    }
+}
+
+/* G1 and G2 points cannot both implement `Encode`,
+so G2 points are serialized with their canonical encoding */
+impl Encode for Pok {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.F_0.encode(writer)?;
+        self.R.encode(writer)?;
+        self.z.encode(writer)?;
+        self.sig.encode(writer)
+    }
+}
+
+impl Decode for Pok {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let F_0 = G1Affine::decode(reader)?;
+        let R = G1Affine::decode(reader)?;
+        let z = Scalar::decode(reader)?;
+        let sig = bls12_381::G2Affine::decode(reader)?;
+        Ok(Pok { F_0, R, z, sig })
+    }
+}
+
+impl Encode for Transcript {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.session.encode(writer)?;
+        self.F.encode(writer)?;
+        self.Y.serialize(&mut *writer)?;
+        self.dealers.encode(writer)
+    }
+}
+
+impl Decode for Transcript {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let session = SessionId::decode(reader)?;
+        let F = Vec::decode(reader)?;
+        let Y = Vec::deserialize(&mut *reader)?;
+        let dealers = BTreeMap::decode(reader)?;
+        Ok(Transcript {
+            session,
+            F,
+            Y,
+            dealers,
+        })
+    }
+}
+
+impl Encode for Params {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.ek.serialize(&mut *writer)?;
+        self.epoch.encode(writer)?;
+        self.pubkeys.encode(writer)?;
+        self.t.encode(writer)?;
+        self.tau.encode(writer)?;
+        self.w.encode(writer)
+    }
+}
+
+impl Decode for Params {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let ek = Vec::deserialize(&mut *reader)?;
+        let epoch = u64::decode(reader)?;
+        let pubkeys = Vec::decode(reader)?;
+        let t = u32::decode(reader)?;
+        let tau = u32::decode(reader)?;
+        let w = Vec::decode(reader)?;
+        let params = Params {
+            ek,
+            epoch,
+            pubkeys,
+            t,
+            tau,
+            w,
+        };
+        params
+            .check_keys()
+            .map_err(|_| SerializationError::InvalidData)?;
+        Ok(params)
+    }
+}
+
+impl_canonical!(Params, Pok, Transcript);
+
+// transcripts can be sent and shared between threads
+const _: fn() = || {
+    protocol::assert_send_sync::<Params>();
+    protocol::assert_send_sync::<Transcript>();
+};
@@ -0,0 +1,486 @@
+/*
+Serialization of protocol contexts and messages.
+
+Protocol state contains std collections and `bls12_381` types,
+which do not implement `CanonicalSerialize`,
+so state is serialized through the `Encode` and `Decode` traits.
+`impl_canonical!` implements `CanonicalSerialize` and `CanonicalDeserialize`
+for types implementing `Encode` and `Decode`.
+
+Sets and maps are encoded in key order,
+and decoding rejects keys that are not strictly increasing,
+so that each value has exactly one encoding.
+
+Context snapshots contain secret material,
+such as the node's keys and shares,
+and must be stored as securely as the keys themselves.
+*/
+
+use ark_bls12_381::{Fr, G1Affine};
+use ark_poly::UVPolynomial;
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::poly;
+
+/* The version of the serialization format for contexts.
+Must be incremented whenever the encoding of a context changes,
+so that stale snapshots are rejected rather than misread. */
+pub const VERSION: u8 = 13;
+
+pub trait Encode {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError>;
+}
+
+pub trait Decode: Sized {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError>;
+}
+
+// write the serialization format version
+pub fn encode_version<W: Write>(
+    writer: &mut W,
+) -> Result<(), SerializationError> {
+    VERSION.serialize(writer)
+}
+
+// read the serialization format version, failing if it is not `VERSION`
+pub fn decode_version<R: Read>(
+    reader: &mut R,
+) -> Result<(), SerializationError> {
+    if u8::deserialize(reader)? == VERSION {
+        Ok(())
+    } else {
+        Err(SerializationError::InvalidData)
+    }
+}
+
+// A writer that counts the number of bytes written to it
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> ark_std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ark_std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// the number of bytes in the encoding of `x`
+pub fn encoded_size<T: Encode>(x: &T) -> usize {
+    let mut counter = ByteCounter(0);
+    x.encode(&mut counter).unwrap();
+    counter.0
+}
+
+/* Implement `CanonicalSerialize` and `CanonicalDeserialize`
+for types implementing `Encode` and `Decode` */
+macro_rules! impl_canonical {
+    ($($t:ty),* $(,)?) => {$(
+        impl ark_serialize::CanonicalSerialize for $t {
+            fn serialize<W: ark_serialize::Write>(
+                &self,
+                mut writer: W,
+            ) -> Result<(), ark_serialize::SerializationError> {
+                crate::serialization::Encode::encode(self, &mut writer)
+            }
+
+            fn serialized_size(&self) -> usize {
+                crate::serialization::encoded_size(self)
+            }
+        }
+
+        impl ark_serialize::CanonicalDeserialize for $t {
+            fn deserialize<R: ark_serialize::Read>(
+                mut reader: R,
+            ) -> Result<Self, ark_serialize::SerializationError> {
+                crate::serialization::Decode::decode(&mut reader)
+            }
+        }
+    )*};
+}
+
+// encode a collection length
+fn encode_len<W: Write>(
+    len: usize,
+    writer: &mut W,
+) -> Result<(), SerializationError> {
+    (len as u64).serialize(writer)
+}
+
+// decode a collection length
+fn decode_len<R: Read>(reader: &mut R) -> Result<usize, SerializationError> {
+    Ok(u64::deserialize(reader)? as usize)
+}
+
+// types that are encoded with `CanonicalSerialize`
+macro_rules! impl_encode_canonical {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode<W: Write>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), SerializationError> {
+                self.serialize(writer)
+            }
+        }
+
+        impl Decode for $t {
+            fn decode<R: Read>(
+                reader: &mut R,
+            ) -> Result<Self, SerializationError> {
+                Self::deserialize(reader)
+            }
+        }
+    )*};
+}
+
+impl_encode_canonical!(u8, u32, u64, Fr, G1Affine);
+
+impl Encode for bool {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        (*self as u8).encode(writer)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+impl Encode for usize {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        (*self as u64).encode(writer)
+    }
+}
+
+impl Decode for usize {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok(u64::decode(reader)? as usize)
+    }
+}
+
+impl Encode for [u8; 32] {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        writer.write_all(self)?;
+        Ok(())
+    }
+}
+
+impl Decode for [u8; 32] {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; 32];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Encode for bls12_381::Scalar {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.to_bytes().encode(writer)
+    }
+}
+
+impl Decode for bls12_381::Scalar {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let bytes = <[u8; 32]>::decode(reader)?;
+        Option::from(bls12_381::Scalar::from_bytes(&bytes))
+            .ok_or(SerializationError::InvalidData)
+    }
+}
+
+impl Encode for bls12_381::G1Affine {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        writer.write_all(&self.to_compressed())?;
+        Ok(())
+    }
+}
+
+impl Decode for bls12_381::G1Affine {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; 48];
+        reader.read_exact(&mut bytes)?;
+        Option::from(bls12_381::G1Affine::from_compressed(&bytes))
+            .ok_or(SerializationError::InvalidData)
+    }
+}
+
+impl Encode for bls12_381::G2Affine {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        writer.write_all(&self.to_compressed())?;
+        Ok(())
+    }
+}
+
+impl Decode for bls12_381::G2Affine {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; 96];
+        reader.read_exact(&mut bytes)?;
+        Option::from(bls12_381::G2Affine::from_compressed(&bytes))
+            .ok_or(SerializationError::InvalidData)
+    }
+}
+
+// univariate polynomials are encoded by their coefficients
+impl Encode for poly::Univar {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.coeffs.encode(writer)
+    }
+}
+
+impl Decode for poly::Univar {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok(poly::Univar::from_coefficients_vec(Vec::decode(reader)?))
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            None => false.encode(writer),
+            Some(x) => {
+                true.encode(writer)?;
+                x.encode(writer)
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        if bool::decode(reader)? {
+            Ok(Some(T::decode(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// shared values are encoded by value, so sharing is not restored
+impl<T: Encode> Encode for Arc<T> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        (**self).encode(writer)
+    }
+}
+
+impl<T: Decode> Decode for Arc<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok(Arc::new(T::decode(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_len(self.len(), writer)?;
+        self.iter().try_for_each(|x| x.encode(writer))
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let len = decode_len(reader)?;
+        (0..len).map(|_| T::decode(reader)).collect()
+    }
+}
+
+/* decode a length-prefixed sequence,
+failing unless the keys of its elements are strictly increasing */
+fn decode_sorted<R, T, K>(
+    reader: &mut R,
+    key: impl Fn(&T) -> &K,
+) -> Result<Vec<T>, SerializationError>
+where
+    R: Read,
+    T: Decode,
+    K: Ord,
+{
+    let len = decode_len(reader)?;
+    let xs = (0..len)
+        .map(|_| T::decode(reader))
+        .collect::<Result<Vec<T>, _>>()?;
+    if xs.windows(2).all(|pair| key(&pair[0]) < key(&pair[1])) {
+        Ok(xs)
+    } else {
+        Err(SerializationError::InvalidData)
+    }
+}
+
+impl<T: Encode> Encode for BTreeSet<T> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_len(self.len(), writer)?;
+        self.iter().try_for_each(|x| x.encode(writer))
+    }
+}
+
+impl<T: Decode + Ord> Decode for BTreeSet<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok(decode_sorted(reader, |x: &T| x)?.into_iter().collect())
+    }
+}
+
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        encode_len(self.len(), writer)?;
+        self.iter().try_for_each(|(k, v)| {
+            k.encode(writer)?;
+            v.encode(writer)
+        })
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let entries = decode_sorted(reader, |(k, _): &(K, V)| k)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+// hash sets are encoded in order, so that the encoding is deterministic
+impl<T: Encode + Ord> Encode for HashSet<T> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        let sorted: BTreeSet<&T> = self.iter().collect();
+        encode_len(sorted.len(), writer)?;
+        sorted.into_iter().try_for_each(|x| x.encode(writer))
+    }
+}
+
+impl<T> Decode for HashSet<T>
+where
+    T: Decode + Ord + std::hash::Hash,
+{
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        Ok(decode_sorted(reader, |x: &T| x)?.into_iter().collect())
+    }
+}
+
+// hash maps are encoded in key order, so that the encoding is deterministic
+impl<K: Encode + Ord, V: Encode> Encode for HashMap<K, V> {
+    fn encode<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SerializationError> {
+        let sorted: BTreeMap<&K, &V> = self.iter().collect();
+        encode_len(sorted.len(), writer)?;
+        sorted.into_iter().try_for_each(|(k, v)| {
+            k.encode(writer)?;
+            v.encode(writer)
+        })
+    }
+}
+
+impl<K, V> Decode for HashMap<K, V>
+where
+    K: Decode + Ord + std::hash::Hash,
+    V: Decode,
+{
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SerializationError> {
+        let entries = decode_sorted(reader, |(k, _): &(K, V)| k)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // encode a sequence of `u32` keys as a set, without sorting them
+    fn encode_keys(keys: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode_len(keys.len(), &mut bytes).unwrap();
+        keys.iter().for_each(|k| k.encode(&mut bytes).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn decode_strictly_increasing_keys() {
+        let bytes = encode_keys(&[0, 2, 5]);
+        let set = BTreeSet::<u32>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![0, 2, 5]);
+        assert!(HashSet::<u32>::decode(&mut &bytes[..]).is_ok());
+
+        for keys in [&[0, 2, 2][..], &[2, 0, 5][..]].iter() {
+            let bytes = encode_keys(keys);
+            assert!(BTreeSet::<u32>::decode(&mut &bytes[..]).is_err());
+            assert!(HashSet::<u32>::decode(&mut &bytes[..]).is_err());
+        }
+
+        let map: HashMap<u32, bool> =
+            vec![(3, true), (1, false)].into_iter().collect();
+        let mut bytes = Vec::new();
+        map.encode(&mut bytes).unwrap();
+        assert_eq!(HashMap::decode(&mut &bytes[..]).unwrap(), map);
+        // swap the two entries, of 5 bytes each
+        let (len, entries) = bytes.split_at(8);
+        let swapped = [len, &entries[5..], &entries[..5]].concat();
+        assert!(HashMap::<u32, bool>::decode(&mut &swapped[..]).is_err());
+        assert!(BTreeMap::<u32, bool>::decode(&mut &swapped[..]).is_err());
+    }
+}
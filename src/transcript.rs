@@ -0,0 +1,53 @@
+/*
+Domain-separated hashing of protocol data.
+A transcript is a sha2-256 hash over a sequence of labelled items.
+Each item is framed as the length of its label, the label,
+the length of its data, and the data,
+so that distinct sequences of items are hashed as distinct inputs.
+*/
+
+use crate::serialization::Encode;
+
+use digest::Digest;
+use sha2::Sha256;
+
+#[derive(Clone)]
+pub struct Transcript(Sha256);
+
+impl Transcript {
+    // start a transcript for the protocol `protocol`
+    pub fn new(protocol: &[u8]) -> Self {
+        let mut res = Transcript(Sha256::new());
+        res.append(b"protocol", protocol);
+        res
+    }
+
+    // write a length prefix
+    fn append_len(&mut self, len: usize) {
+        self.0.update((len as u64).to_le_bytes())
+    }
+
+    // append the item `bytes`, with label `label`
+    pub fn append(&mut self, label: &[u8], bytes: &[u8]) {
+        self.append_len(label.len());
+        self.0.update(label);
+        self.append_len(bytes.len());
+        self.0.update(bytes);
+    }
+
+    pub fn append_u32(&mut self, label: &[u8], x: u32) {
+        self.append(label, &x.to_le_bytes())
+    }
+
+    // append the encoding of `x`, with label `label`
+    pub fn append_encode<T: Encode>(&mut self, label: &[u8], x: &T) {
+        let mut bytes = Vec::new();
+        x.encode(&mut bytes).unwrap();
+        self.append(label, &bytes)
+    }
+
+    // the sha2-256 hash of the transcript
+    pub fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
@@ -0,0 +1,44 @@
+use ark_bls12_381::Fr;
+use ark_ff::UniformRand;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ferveo::ecies::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+type Scalar = Fr;
+
+// ciphertexts decrypt to the message, also after a serialization round trip
+#[test]
+fn encrypt_decrypt() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let dk = Scalar::rand(rng);
+    let ek = encryption_key(dk);
+    let ct = encrypt(&ek, b"aad", b"message", rng);
+    assert_eq!(decrypt(dk, b"aad", &ct), Some(b"message".to_vec()));
+
+    let mut bytes = Vec::new();
+    ct.serialize(&mut bytes).unwrap();
+    let decoded = Ciphertext::deserialize(&bytes[..]).unwrap();
+    assert_eq!(decoded, ct);
+    assert_eq!(decrypt(dk, b"aad", &decoded), Some(b"message".to_vec()));
+}
+
+/* ciphertexts fail to decrypt with the wrong key or associated data,
+or once tampered with */
+#[test]
+fn decrypt_invalid() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let dk = Scalar::rand(rng);
+    let ek = encryption_key(dk);
+    let ct = encrypt(&ek, b"aad", b"message", rng);
+    assert_eq!(decrypt(Scalar::rand(rng), b"aad", &ct), None);
+    assert_eq!(decrypt(dk, b"other", &ct), None);
+
+    let mut tampered = ct.clone();
+    tampered.c[0] ^= 1;
+    assert_eq!(decrypt(dk, b"aad", &tampered), None);
+
+    let mut tampered = ct;
+    tampered.R = encryption_key(Scalar::rand(rng));
+    assert_eq!(decrypt(dk, b"aad", &tampered), None);
+}
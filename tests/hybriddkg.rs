@@ -3,19 +3,25 @@
 #![feature(bindings_after_at)]
 
 use ark_bls12_381::Fr;
-use ark_ff::UniformRand;
-use either::Either;
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{Field, UniformRand, Zero};
+use ark_poly::Polynomial;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bls12_381::Scalar as BlsScalar;
 use ferveo::hybriddkg::*;
 use ferveo::poly;
 use rand::rngs::StdRng;
 use rand::Rng;
 use rand::SeedableRng;
-use std::rc::Rc;
+use std::sync::Arc;
 
 mod hybridvss;
 
 type Scalar = Fr;
 
+// The session used by each test scheme
+const SESSION: SessionId = SessionId { epoch: 0, tau: 0 };
+
 // Fixed seed for reproducability
 fn rng() -> StdRng {
     StdRng::seed_from_u64(0)
@@ -25,6 +31,7 @@ fn rng() -> StdRng {
 struct Scheme {
     params: Params,
     nodes: Vec<Context>,
+    sks: Vec<BlsScalar>, // the signing key of each node
 }
 
 impl Scheme {
@@ -33,19 +40,59 @@ impl Scheme {
     threshold `t`,
     and a randomly selected leader */
     fn init<R: Rng>(f: u32, t: u32, w: Vec<u32>, rng: &mut R) -> Self {
-        let params = Params::random_leader(f, t, w, rng);
-        let nodes = (0..params.n())
-            .map(|i| Context::init(params.clone(), i))
+        Self::init_session(SESSION, f, t, w, rng)
+    }
+
+    // as `init`, for the session `session`
+    fn init_session<R: Rng>(
+        session: SessionId,
+        f: u32,
+        t: u32,
+        w: Vec<u32>,
+        rng: &mut R,
+    ) -> Self {
+        Self::init_mode(session, f, t, w, false, rng)
+    }
+
+    // as `init`, with pedersen commitments
+    fn init_pedersen<R: Rng>(f: u32, t: u32, w: Vec<u32>, rng: &mut R) -> Self {
+        Self::init_mode(SESSION, f, t, w, true, rng)
+    }
+
+    /* as `init_session`,
+    with pedersen commitments if `pedersen` is set */
+    fn init_mode<R: Rng>(
+        session: SessionId,
+        f: u32,
+        t: u32,
+        w: Vec<u32>,
+        pedersen: bool,
+        rng: &mut R,
+    ) -> Self {
+        // signing keys for each node
+        let sks: Vec<BlsScalar> = w
+            .iter()
+            .map(|_| <BlsScalar as ff::Field>::random(&mut *rng))
             .collect();
-        Scheme { params, nodes }
+        let pubkeys = sks.iter().map(ferveo::bls::pubkey).collect();
+        let params = Params {
+            pedersen,
+            ..Params::random_leader(session, f, t, w, pubkeys, rng).unwrap()
+        };
+        let nodes = sks
+            .iter()
+            .enumerate()
+            .map(|(i, sk)| Context::init(params.clone(), i as u32, *sk))
+            .collect();
+        Scheme { params, nodes, sks }
     }
 
     // run hybridvss_sh protocol for dealer `d`
     fn hybridvss_sh(&self, d: u32) -> Vec<Shared> {
         let mut rng = StdRng::seed_from_u64(0);
-        let Params { f, t, w, .. } = self.params.clone();
-        let params = ferveo::hybridvss::Params { d, f, t, w };
-        let mut scheme = hybridvss::Scheme::new(params);
+        let params = self.params.vss_params(d);
+        let mut scheme =
+            hybridvss::Scheme::with_signing_keys(params, self.sks.clone());
 
         let share = ferveo::hybridvss::sh::Share {
             s: Scalar::rand(&mut rng),
@@ -62,14 +109,40 @@ impl Scheme {
             .ready_threshold_each(ready_messages, &mut rng)
             .into_iter()
             .map(|ready_response| match ready_response {
-                Some(Either::Right(ferveo::hybridvss::sh::Shared { C, s })) => {
-                    Shared { C, d, s_id: s }
+                Some(ferveo::hybridvss::sh::Shared { C, s, s_hat, .. }) => {
+                    let R = self
+                        .nodes
+                        .iter()
+                        .map(|node| node.vss_ready(d, &C))
+                        .collect();
+                    Shared {
+                        session: SESSION,
+                        C,
+                        d,
+                        s_id: s,
+                        s_hat_id: s_hat,
+                        R,
+                    }
                 }
                 _ => panic!(),
             })
             .collect()
     }
 
+    /* the reveal message of dealer `d` in pedersen mode,
+    from the same dealing as `hybridvss_sh` */
+    fn vss_reveal(&self, d: u32) -> ferveo::hybridvss::sh::Reveal {
+        let mut rng = StdRng::seed_from_u64(0);
+        let params = self.params.vss_params(d);
+        let sk = self.sks[d as usize];
+        let mut dealer = ferveo::hybridvss::sh::Context::init(params, d, sk);
+        let share = ferveo::hybridvss::sh::Share {
+            s: Scalar::rand(&mut rng),
+        };
+        dealer.share(&mut rng, share);
+        dealer.reveal().unwrap()
+    }
+
     // run hybridvss_sh protocol for each dealer
     fn run_hybridvss_sh(&self) -> Vec<Vec<Shared>> {
         // shared messages for each dealer
@@ -88,6 +161,23 @@ impl Scheme {
     }
 }
 
+/* signed hybridvss ready messages from every node,
+for each dealer with commitments `Cs` */
+fn vss_readies(
+    nodes: &[Context],
+    Cs: &[Arc<poly::Public>],
+) -> Vec<Vec<Signed<VssReady>>> {
+    Cs.iter()
+        .enumerate()
+        .map(|(d, C)| {
+            nodes
+                .iter()
+                .map(|node| node.vss_ready(d as u32, C))
+                .collect()
+        })
+        .collect()
+}
+
 #[test]
 /* test that the leader sends after enough valid shares,
 and the other nodes delay */
@@ -105,9 +195,10 @@ fn shared_send_valid() {
         .map(|_| {
             let secret =
                 poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
-            Rc::new(poly::public(&secret))
+            Arc::new(poly::public(&secret))
         })
         .collect();
+    let readies = vss_readies(&nodes, &Cs);
 
     for (i, node) in nodes.iter_mut().enumerate() {
         /* generate a shared message for each other node.
@@ -119,9 +210,12 @@ fn shared_send_valid() {
             .cloned()
             .enumerate()
             .map(|(d, C)| Shared {
+                session: SESSION,
                 C,
                 d: (d as u32),
-                s_id: Scalar::rand(&mut rng),
+                s_id: vec![Scalar::rand(&mut rng)],
+                s_hat_id: Vec::new(),
+                R: readies[d].clone(),
             })
             .collect();
 
@@ -164,9 +258,10 @@ fn send_echo_valid() {
         .map(|_| {
             let secret =
                 poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
-            Rc::new(poly::public(&secret))
+            Arc::new(poly::public(&secret))
         })
         .collect();
+    let readies = vss_readies(&nodes, &Cs);
 
     /* generate a shared message for each other node.
     the individual shares are expected to be invalid. */
@@ -175,9 +270,12 @@ fn send_echo_valid() {
         .cloned()
         .enumerate()
         .map(|(d, C)| Shared {
+            session: SESSION,
             C,
             d: (d as u32),
-            s_id: Scalar::rand(&mut rng),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
         })
         .collect();
 
@@ -202,6 +300,141 @@ fn send_echo_valid() {
     }
 }
 
+#[test]
+/* Test that nodes echo only the first send from an equivocating leader */
+fn send_equivocation() {
+    let mut rng = rng();
+    let n = 6;
+    let t = 2u32;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l as usize;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+    let shared_messages: Vec<Shared> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+
+    /* the leader runs two copies of its context,
+    and proposes a different set of dealers from each */
+    let mut twin = round_trip(&nodes[l]);
+    let propose = |leader: &mut Context, dealers: &[Shared]| {
+        dealers
+            .iter()
+            .find_map(|shared| match leader.shared(shared) {
+                Some(SharedAction::Send(send)) => Some(send),
+                _ => None,
+            })
+            .unwrap()
+    };
+    let send = propose(&mut nodes[l], &shared_messages[..(t + 1) as usize]);
+    let other = propose(&mut twin, &shared_messages[(t + 1) as usize..]);
+
+    for node in nodes.iter_mut() {
+        assert!(node.send(send.clone()).is_some());
+        assert!(node.send(other.clone()).is_none());
+        // nor is the first send echoed twice
+        assert!(node.send(send.clone()).is_none());
+    }
+}
+
+#[test]
+/* Test that nodes reject sends and echos with invalid signatures */
+fn send_echo_forged() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 6;
+    let t = 4u32;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    /* generate a shared message for each other node.
+    the individual shares are expected to be invalid. */
+    let shared_messages: Vec<Shared> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+
+    // leader accepts shared messages from t + 1 nodes, in random order
+    let mut send = None;
+    shared_messages
+        .into_iter()
+        .choose_multiple(&mut rng, (t + 1) as usize)
+        .into_iter()
+        .for_each(|shared_message| {
+            let shared_response = nodes[l as usize].shared(&shared_message);
+            if let Some(SharedAction::Send(s)) = shared_response {
+                send = Some(s)
+            }
+        });
+
+    let send = send.unwrap();
+    let echo = nodes[l as usize].send(send.clone()).unwrap();
+
+    // a send attributed to a node other than the leader
+    let not_leader = Signed {
+        signer: (l + 1) % n,
+        ..send.clone()
+    };
+    // a send with a signature from a different message
+    let bad_sig = Signed {
+        sig: echo.sig,
+        ..send.clone()
+    };
+    // an echo attributed to a node other than the signer
+    let bad_echo = Signed {
+        signer: (l + 1) % n,
+        ..echo
+    };
+
+    for node in nodes.iter_mut() {
+        assert!(node.send(not_leader.clone()).is_none());
+        assert!(node.send(bad_sig.clone()).is_none());
+        assert!(node.echo(bad_echo.clone()).is_none());
+    }
+}
+
 #[test]
 /* Test that nodes return ready messages, given enough valid echos */
 fn echo_ready_valid() {
@@ -220,9 +453,10 @@ fn echo_ready_valid() {
         .map(|_| {
             let secret =
                 poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
-            Rc::new(poly::public(&secret))
+            Arc::new(poly::public(&secret))
         })
         .collect();
+    let readies = vss_readies(&nodes, &Cs);
 
     /* generate a shared message for each other node.
     the individual shares are expected to be invalid. */
@@ -231,9 +465,12 @@ fn echo_ready_valid() {
         .cloned()
         .enumerate()
         .map(|(d, C)| Shared {
+            session: SESSION,
             C,
             d: (d as u32),
-            s_id: Scalar::rand(&mut rng),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
         })
         .collect();
 
@@ -253,7 +490,7 @@ fn echo_ready_valid() {
 
     let send = send.unwrap().clone();
 
-    let echos: Vec<Echo> = nodes
+    let echos: Vec<Signed<Echo>> = nodes
         .iter_mut()
         .map(|node| node.send(send.clone()).unwrap())
         .collect();
@@ -274,6 +511,206 @@ fn echo_ready_valid() {
     }
 }
 
+#[test]
+/* Test that replayed echos from a single node are counted once */
+fn echo_replay() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 6;
+    let t = 4u32;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    // leader accepts shared messages from t + 1 nodes
+    let mut send = None;
+    for (d, C) in Cs.iter().cloned().enumerate().take((t + 1) as usize) {
+        let shared_message = Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        };
+        if let Some(SharedAction::Send(s)) =
+            nodes[l as usize].shared(&shared_message)
+        {
+            send = Some(s)
+        }
+    }
+    let send = send.unwrap();
+
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| node.send(send.clone()).unwrap())
+        .collect();
+
+    /* the echo threshold is `ceil ((n+t+1)/2)`,
+    which is exactly n here */
+    let threshold = num::integer::div_ceil(n + t + 1, 2) as usize;
+    assert_eq!(threshold, n as usize);
+    let node = &mut nodes[0];
+
+    // replaying one echo `threshold` times is not enough
+    for _ in 0..threshold {
+        assert!(node.echo(echos[1].clone()).is_none())
+    }
+    // the remaining echos, in random order, reach the threshold
+    let rest = echos
+        .iter()
+        .enumerate()
+        .filter(|(j, _)| *j != 1)
+        .map(|(_, echo)| echo)
+        .choose_multiple(&mut rng, threshold - 1);
+    for (count, echo) in rest.into_iter().cloned().enumerate() {
+        let response = node.echo(echo);
+        if count == threshold - 2 {
+            assert!(response.is_some())
+        } else {
+            assert!(response.is_none())
+        }
+    }
+}
+
+#[test]
+/* Test that echo and ready thresholds are weighted */
+fn echo_ready_complete_weighted() {
+    let mut rng = rng();
+    let n = 6;
+    let t = 2u32;
+    let f = 0;
+    // W = 8, so the echo threshold is 6 and the complete threshold is 6
+    let w = vec![3, 1, 1, 1, 1, 1];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    // leader accepts shared messages from t + 1 nodes
+    let mut send = None;
+    for (d, C) in Cs.iter().cloned().enumerate().take((t + 1) as usize) {
+        let shared_message = Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        };
+        if let Some(SharedAction::Send(s)) =
+            nodes[l as usize].shared(&shared_message)
+        {
+            send = Some(s)
+        }
+    }
+    let send = send.unwrap();
+
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| node.send(send.clone()).unwrap())
+        .collect();
+
+    // echos from nodes 0..=3 have weight 3 + 1 + 1 + 1 = 6
+    let mut ready_messages = Vec::new();
+    for node in nodes.iter_mut() {
+        for echo in echos[0..3].iter().cloned() {
+            assert!(node.echo(echo).is_none())
+        }
+        ready_messages.push(node.echo(echos[3].clone()).unwrap());
+    }
+
+    // readies from nodes 0..=3 have weight 6 = W - t - f
+    let node = &mut nodes[5];
+    assert!(node.ready(ready_messages[0].clone()).is_empty());
+    // a replayed ready message from node 0 is not counted again
+    assert!(node.ready(ready_messages[0].clone()).is_empty());
+    for ready in ready_messages[1..3].iter().cloned() {
+        assert!(node.ready(ready).is_empty())
+    }
+    match node.ready(ready_messages[3].clone()).as_slice() {
+        [ReadyAction::Complete] => (),
+        _ => panic!(),
+    }
+}
+
+#[test]
+/* Test that a single ready message from a heavy node
+can cross both the ready and complete thresholds */
+fn ready_complete_heavy() {
+    let mut rng = rng();
+    let n = 5;
+    let t = 2u32;
+    let f = 2;
+    // W = 10, so the echo threshold is 7 and the complete threshold is 6
+    let w = vec![6, 1, 1, 1, 1];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    let mut send = None;
+    for (d, C) in Cs.iter().cloned().enumerate().take((t + 1) as usize) {
+        let shared_message = Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        };
+        if let Some(SharedAction::Send(s)) =
+            nodes[l as usize].shared(&shared_message)
+        {
+            send = Some(s)
+        }
+    }
+    let send = send.unwrap();
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| node.send(send.clone()).unwrap())
+        .collect();
+
+    // echos from nodes 0 and 1 have weight 7
+    assert!(nodes[0].echo(echos[0].clone()).is_none());
+    let ready = nodes[0].echo(echos[1].clone()).unwrap();
+
+    // node 4 has seen no echos, and node 0 has weight 6
+    match nodes[4].ready(ready).as_slice() {
+        [ReadyAction::Ready(_), ReadyAction::Complete] => (),
+        _ => panic!("unexpected action"),
+    }
+}
+
 //TODO: test ready-ready
 
 #[test]
@@ -295,9 +732,10 @@ fn ready_complete_valid() {
         .map(|_| {
             let secret =
                 poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
-            Rc::new(poly::public(&secret))
+            Arc::new(poly::public(&secret))
         })
         .collect();
+    let readies = vss_readies(&nodes, &Cs);
 
     /* generate a shared message for each other node.
     the individual shares are expected to be invalid. */
@@ -306,9 +744,12 @@ fn ready_complete_valid() {
         .cloned()
         .enumerate()
         .map(|(d, C)| Shared {
+            session: SESSION,
             C,
             d: (d as u32),
-            s_id: Scalar::rand(&mut rng),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
         })
         .collect();
 
@@ -328,12 +769,12 @@ fn ready_complete_valid() {
 
     let send = send.unwrap().clone();
 
-    let echos: Vec<Echo> = nodes
+    let echos: Vec<Signed<Echo>> = nodes
         .iter_mut()
         .map(|node| node.send(send.clone()).unwrap())
         .collect();
 
-    let mut ready_messages: Vec<Option<Ready>> = vec![None; n as usize];
+    let mut ready_messages: Vec<Option<Signed<Ready>>> = vec![None; n as usize];
     for (i, node) in nodes.iter_mut().enumerate() {
         /* choose `ceil ((n+t+1)/2)` echos to accept */
         let threshold = num::integer::div_ceil(n + t + 1, 2) as usize;
@@ -344,7 +785,7 @@ fn ready_complete_valid() {
         }
     }
     assert!(ready_messages.iter().all(|ready| ready.is_some()));
-    let ready_messages: Vec<Ready> = ready_messages
+    let ready_messages: Vec<Signed<Ready>> = ready_messages
         .into_iter()
         .map(|ready| ready.unwrap())
         .collect();
@@ -357,12 +798,12 @@ fn ready_complete_valid() {
         for (count, ready) in ready_messages.into_iter().cloned().enumerate() {
             let response = node.ready(ready);
             if count == (n - t - f - 1) as usize {
-                match response {
-                    Some(ReadyAction::Complete) => (),
-                    _ => assert!(false),
+                match response.as_slice() {
+                    [ReadyAction::Complete] => (),
+                    _ => panic!("unexpected action"),
                 }
             } else {
-                assert!(response.is_none())
+                assert!(response.is_empty())
             }
         }
     }
@@ -401,13 +842,13 @@ fn shared_output_finalize_valid() {
 
     let send = send.unwrap().clone();
 
-    let echos: Vec<Echo> = scheme
+    let echos: Vec<Signed<Echo>> = scheme
         .nodes
         .iter_mut()
         .map(|node| node.send(send.clone()).unwrap())
         .collect();
 
-    let mut ready_messages: Vec<Option<Ready>> = vec![None; n as usize];
+    let mut ready_messages: Vec<Option<Signed<Ready>>> = vec![None; n as usize];
     for (i, node) in scheme.nodes.iter_mut().enumerate() {
         /* choose `ceil ((n+t+1)/2)` echos to accept */
         let threshold = num::integer::div_ceil(n + t + 1, 2) as usize;
@@ -418,7 +859,7 @@ fn shared_output_finalize_valid() {
         }
     }
     assert!(ready_messages.iter().all(|ready| ready.is_some()));
-    let ready_messages: Vec<Ready> = ready_messages
+    let ready_messages: Vec<Signed<Ready>> = ready_messages
         .into_iter()
         .map(|ready| ready.unwrap())
         .collect();
@@ -432,20 +873,1107 @@ fn shared_output_finalize_valid() {
         for (count, ready) in ready_messages.into_iter().cloned().enumerate() {
             let response = node.ready(ready);
             if count == threshold {
-                match response {
-                    Some(ReadyAction::Complete) => (),
-                    _ => assert!(false),
+                match response.as_slice() {
+                    [ReadyAction::Complete] => (),
+                    _ => panic!("unexpected action"),
                 }
             } else {
-                assert!(response.is_none())
+                assert!(response.is_empty())
             }
         }
     }
 
-    // finalize and check that commitments match
-    let outputs: Vec<_> = (0..n)
-        .map(|i| finalize(&shared_messages[i as usize]))
+    // finalize and check that commitments and public keys match
+    let outputs: Vec<DkgOutput> = scheme
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| node.finalize(&shared_messages[i]).unwrap())
+        .collect();
+    let output_0 = &outputs[0];
+    assert!(outputs.iter().all(|output| output.C == output_0.C
+        && output.pubkey == output_0.pubkey
+        && output.pubkey_shares == output_0.pubkey_shares));
+    assert_eq!(output_0.pubkey, output_0.C[0][0]);
+
+    // each node's private share matches its public key share
+    assert!(outputs.iter().all(|output| output.verify()));
+
+    // the private shares interpolate to the distributed private key
+    let secret = interpolate_outputs(&outputs, n);
+    let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+    assert_eq!(g.mul(secret).into_affine(), output_0.pubkey);
+}
+
+/* interpolate the private shares from dkg outputs at zero,
+with total weight `W` */
+fn interpolate_outputs(outputs: &[DkgOutput], W: u32) -> Scalar {
+    let omega = ferveo::fft::domain(W as usize).group_gen;
+    let points = outputs.iter().flat_map(|output| {
+        output.shares.iter().enumerate().map(move |(k, share)| {
+            (omega.pow([(output.share_index + k) as u64]), *share)
+        })
+    });
+    poly::lagrange_interpolate(points).evaluate(&Scalar::zero())
+}
+
+#[test]
+/* Test that weighted nodes finalize with one share per unit of weight */
+fn finalize_weighted() {
+    let mut rng = rng();
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let W = w.iter().sum();
+    let scheme = Scheme::init(0, t, w.clone(), &mut rng);
+
+    // finalize with the shared messages of t + 1 dealers
+    let shared_messages = scheme.run_hybridvss_sh();
+    let outputs: Vec<DkgOutput> = scheme
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            node.finalize(&shared_messages[i][0..=t as usize]).unwrap()
+        })
         .collect();
-    let (C_0, _) = &outputs[0];
-    assert!(outputs.iter().all(|(C, _)| C == C_0));
+
+    let output_0 = &outputs[0];
+    assert_eq!(output_0.pubkey_shares.len(), W as usize);
+    for (i, output) in outputs.iter().enumerate() {
+        assert_eq!(output.shares.len(), w[i] as usize);
+        assert_eq!(output.pubkey_shares, output_0.pubkey_shares);
+        assert!(output.verify());
+    }
+
+    let secret = interpolate_outputs(&outputs, W);
+    let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+    assert_eq!(g.mul(secret).into_affine(), output_0.pubkey);
+
+    // a share vector of the wrong length is rejected
+    let mut shared = shared_messages[0][0].clone();
+    shared.s_id.pop();
+    assert_eq!(
+        scheme.nodes[0].finalize(&[shared]),
+        Err(Error::ShareCount(0))
+    );
+}
+
+#[test]
+/* Test that finalize rejects an empty or duplicated set of dealers */
+fn finalize_invalid() {
+    let mut rng = rng();
+    let n = 4;
+    let t = 1;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let node = &scheme.nodes[0];
+
+    assert_eq!(node.finalize(&[]), Err(Error::NoShares));
+
+    let secret = poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+    let C = Arc::new(poly::public(&secret));
+    let shared = Shared {
+        session: SESSION,
+        C: C.clone(),
+        d: 2,
+        s_id: vec![Scalar::rand(&mut rng)],
+        s_hat_id: Vec::new(),
+        R: vss_readies(&scheme.nodes, &[C])[0].clone(),
+    };
+    let output = node.finalize(&[shared.clone()]);
+    assert!(output.is_ok());
+    assert_eq!(
+        node.finalize(&[shared.clone(), shared]),
+        Err(Error::DuplicateDealer(2))
+    );
+}
+
+// Deliver each lead-ch message to each node, returning the resulting actions
+fn deliver_lead_chs(
+    nodes: &mut [Context],
+    lead_chs: Vec<Signed<LeadCh>>,
+) -> Vec<Vec<LeadChAction>> {
+    nodes
+        .iter_mut()
+        .map(|node| {
+            lead_chs
+                .iter()
+                .cloned()
+                .filter_map(|lead_ch| node.lead_ch(lead_ch))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+/* Test that the nodes change leader if the first leader never sends,
+and that the nodes echo the proposal of the new leader */
+fn lead_ch_silent_leader() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 7;
+    let t = 2;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    /* generate a shared message for each other node.
+    the individual shares are expected to be invalid. */
+    let shared_messages: Vec<Shared> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+
+    // every node accepts shared messages from t + 1 nodes, in random order
+    for node in nodes.iter_mut() {
+        shared_messages
+            .iter()
+            .choose_multiple(&mut rng, (t + 1) as usize)
+            .into_iter()
+            .for_each(|shared_message| {
+                node.shared(shared_message);
+            });
+    }
+
+    // the leader never sends, so every node times out
+    let lead_chs: Vec<Signed<LeadCh>> = nodes
+        .iter_mut()
+        .map(|node| node.timeout().unwrap())
+        .collect();
+    // timing out again does not request another leader change
+    assert!(nodes.iter_mut().all(|node| node.timeout().is_none()));
+
+    let new_l = (l + 1) % n;
+    let actions = deliver_lead_chs(&mut nodes, lead_chs);
+    let mut send = None;
+    for (i, node_actions) in actions.into_iter().enumerate() {
+        // each node changes leader exactly once
+        assert_eq!(node_actions.len(), 1);
+        match node_actions.into_iter().next().unwrap() {
+            LeadChAction::Send(s) => {
+                assert!(i as u32 == new_l);
+                send = Some(s)
+            }
+            LeadChAction::Delay => assert!(i as u32 != new_l),
+            LeadChAction::LeadCh(_) | LeadChAction::Echo(_) => {
+                panic!("unexpected action")
+            }
+        }
+    }
+    assert!(nodes.iter().all(|node| node.leader() == new_l));
+
+    let send = send.expect("the new leader did not send");
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| node.send(send.clone()).unwrap())
+        .collect();
+
+    for node in nodes.iter_mut() {
+        /* choose `ceil ((n+t+1)/2)` echos to accept */
+        let threshold = num::integer::div_ceil(n + t + 1, 2) as usize;
+        let echos = echos.iter().choose_multiple(&mut rng, threshold);
+
+        for (count, echo) in echos.into_iter().cloned().enumerate() {
+            let response = node.echo(echo);
+            if count == threshold - 1 {
+                assert!(response.is_some())
+            } else {
+                assert!(response.is_none())
+            }
+        }
+    }
+}
+
+#[test]
+/* Test that a send from the new leader that arrives before a node
+has changed leader is echoed once the node changes leader,
+and that lead-ch messages for distant leader numbers are ignored */
+fn lead_ch_early_send() {
+    let mut rng = rng();
+    let n = 7;
+    let t = 2;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+    // copies of the nodes, which change leader many times
+    let mut far: Vec<Context> = nodes.iter().map(round_trip).collect();
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+    let shared_messages: Vec<Shared> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+    for node in nodes.iter_mut() {
+        for shared_message in shared_messages.iter().take((t + 1) as usize) {
+            node.shared(shared_message);
+        }
+    }
+
+    // the leader never sends, so every node times out
+    let lead_chs: Vec<Signed<LeadCh>> = nodes
+        .iter_mut()
+        .map(|node| node.timeout().unwrap())
+        .collect();
+
+    // the new leader changes leader first, and sends
+    let new_l = ((l + 1) % n) as usize;
+    let actions = deliver_lead_chs(&mut nodes[new_l..=new_l], lead_chs.clone());
+    let send = match actions[0].as_slice() {
+        [LeadChAction::Send(send)] => send.clone(),
+        _ => panic!("the new leader did not send"),
+    };
+
+    // the send is buffered by the other nodes, and echoed on changing leader
+    for (i, node) in nodes.iter_mut().enumerate() {
+        if i != new_l {
+            assert!(node.send(send.clone()).is_none());
+            let actions =
+                deliver_lead_chs(std::slice::from_mut(node), lead_chs.clone());
+            match actions[0].as_slice() {
+                [LeadChAction::Echo(_)] => (),
+                _ => panic!("expected an echo"),
+            }
+        }
+    }
+
+    // lead-ch messages for distant leader numbers are ignored
+    for _ in 0..=n {
+        let lead_chs =
+            far.iter_mut().map(|node| node.timeout().unwrap()).collect();
+        deliver_lead_chs(&mut far, lead_chs);
+    }
+    let distant = far[0].timeout().unwrap();
+    // the lead-ch message is not recorded
+    let size = nodes[1].serialized_size();
+    assert!(nodes[1].lead_ch(distant).is_none());
+    assert_eq!(nodes[1].serialized_size(), size);
+}
+
+#[test]
+/* Test that nodes join a leader change after `t + f + 1` lead-ch messages,
+even if they have not timed out themselves */
+fn lead_ch_join() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 7;
+    let t = 2;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // only `t + f + 1` nodes time out
+    let timed_out = (0..n).choose_multiple(&mut rng, (t + f + 1) as usize);
+    let lead_chs: Vec<Signed<LeadCh>> = timed_out
+        .iter()
+        .map(|m| nodes[*m as usize].timeout().unwrap())
+        .collect();
+
+    // the remaining nodes join the leader change
+    let actions = deliver_lead_chs(&mut nodes, lead_chs.clone());
+    let mut joined = Vec::new();
+    for (m, node_actions) in actions.into_iter().enumerate() {
+        if timed_out.contains(&(m as u32)) {
+            assert!(node_actions.is_empty())
+        } else {
+            assert_eq!(node_actions.len(), 1);
+            match node_actions.into_iter().next().unwrap() {
+                LeadChAction::LeadCh(lead_ch) => joined.push(lead_ch),
+                _ => panic!("unexpected action"),
+            }
+        }
+    }
+    assert!(nodes.iter().all(|node| node.leader() == l));
+
+    // all nodes change leader after `W - t - f` lead-ch messages
+    let actions = deliver_lead_chs(&mut nodes, joined);
+    for node_actions in actions.into_iter() {
+        assert_eq!(node_actions.len(), 1);
+        match node_actions.into_iter().next().unwrap() {
+            // no node has enough shared messages to propose a set
+            LeadChAction::Delay => (),
+            _ => panic!("unexpected action"),
+        }
+    }
+    assert!(nodes.iter().all(|node| node.leader() == (l + 1) % n));
+
+    // stale lead-ch messages are ignored
+    let actions = deliver_lead_chs(&mut nodes, lead_chs);
+    assert!(actions.iter().all(|node_actions| node_actions.is_empty()));
+}
+
+#[test]
+/* Test that shared messages without a valid proof are ignored */
+fn shared_invalid_proof() {
+    let mut rng = rng();
+    let n = 7;
+    let t = 2;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    for (d, C) in Cs.iter().cloned().enumerate() {
+        // too few hybridvss ready messages
+        let mut R = readies[d].clone();
+        R.truncate((n - t - f - 1) as usize);
+        let too_few = Shared {
+            session: SESSION,
+            C: C.clone(),
+            d: d as u32,
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R,
+        };
+        // hybridvss ready messages for a different dealer
+        let wrong_dealer = Shared {
+            session: SESSION,
+            C,
+            d: d as u32,
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[(d + 1) % n as usize].clone(),
+        };
+        assert!(nodes[l as usize].shared(&too_few).is_none());
+        assert!(nodes[l as usize].shared(&wrong_dealer).is_none());
+    }
+}
+
+#[test]
+/* Test that a new leader re-proposes the set that nodes sent ready for,
+justified by the certificate of echo messages */
+fn lead_ch_q_bar() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 7;
+    let t = 2;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    /* generate a shared message for each other node.
+    the individual shares are expected to be invalid. */
+    let shared_messages: Vec<Shared> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+
+    // every node accepts shared messages from t + 1 nodes, in random order
+    let mut send = None;
+    for (i, node) in nodes.iter_mut().enumerate() {
+        shared_messages
+            .iter()
+            .choose_multiple(&mut rng, (t + 1) as usize)
+            .into_iter()
+            .for_each(|shared_message| {
+                if let Some(SharedAction::Send(s)) = node.shared(shared_message)
+                {
+                    assert!(i as u32 == l);
+                    send = Some(s)
+                }
+            });
+    }
+
+    let send = send.unwrap();
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| node.send(send.clone()).unwrap())
+        .collect();
+
+    // every node sends a ready message, but the ready messages are lost
+    for node in nodes.iter_mut() {
+        /* choose `ceil ((n+t+1)/2)` echos to accept */
+        let threshold = num::integer::div_ceil(n + t + 1, 2) as usize;
+        let echos = echos.iter().choose_multiple(&mut rng, threshold);
+        let mut response = None;
+        for echo in echos.into_iter().cloned() {
+            response = node.echo(echo);
+        }
+        assert!(response.is_some())
+    }
+
+    let lead_chs: Vec<Signed<LeadCh>> = nodes
+        .iter_mut()
+        .map(|node| node.timeout().unwrap())
+        .collect();
+
+    let new_l = (l + 1) % n;
+    let mut new_send = None;
+    for (i, node_actions) in deliver_lead_chs(&mut nodes, lead_chs)
+        .into_iter()
+        .enumerate()
+    {
+        for action in node_actions.into_iter() {
+            if let LeadChAction::Send(s) = action {
+                assert!(i as u32 == new_l);
+                new_send = Some(s)
+            }
+        }
+    }
+
+    // nodes only echo the set that they sent ready messages for
+    let new_send = new_send.expect("the new leader did not send");
+    for node in nodes.iter_mut() {
+        assert!(node.send(new_send.clone()).is_some())
+    }
+}
+
+// serialize and deserialize, checking that the encoding is stable
+fn round_trip<T: CanonicalSerialize + CanonicalDeserialize>(x: &T) -> T {
+    let mut bytes = Vec::new();
+    x.serialize(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), x.serialized_size());
+    let res = T::deserialize(&bytes[..]).unwrap();
+    let mut res_bytes = Vec::new();
+    res.serialize(&mut res_bytes).unwrap();
+    assert_eq!(bytes, res_bytes);
+    res
+}
+
+#[test]
+/* Test that nodes restored from snapshots taken mid-protocol
+complete, given enough valid ready messages */
+fn context_snapshot_restore() {
+    use rand::seq::IteratorRandom;
+
+    let mut rng = rng();
+    let n = 6;
+    let t = 4;
+    let f = 0;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(f, t, w, &mut rng);
+    let mut nodes = scheme.nodes;
+    let l = scheme.params.l;
+
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+
+    // leader accepts shared messages from t + 1 nodes
+    let mut send = None;
+    for (d, C) in Cs.iter().cloned().enumerate().take((t + 1) as usize) {
+        let shared_message = round_trip(&Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        });
+        if let Some(SharedAction::Send(s)) =
+            nodes[l as usize].shared(&shared_message)
+        {
+            send = Some(s)
+        }
+    }
+    // the send message remains valid after a round trip
+    let send = round_trip(&send.unwrap());
+
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .map(|node| round_trip(&node.send(send.clone()).unwrap()))
+        .collect();
+
+    let ready_messages: Vec<Signed<Ready>> = nodes
+        .iter_mut()
+        .map(|node| {
+            let mut ready = None;
+            for echo in echos.iter().cloned() {
+                ready = ready.or(node.echo(echo));
+            }
+            round_trip(&ready.unwrap())
+        })
+        .collect();
+
+    // snapshot and restore each node
+    let mut nodes: Vec<Context> = nodes.iter().map(round_trip).collect();
+
+    // restored nodes do not count echos twice
+    assert!(nodes[0].echo(echos[0].clone()).is_none());
+
+    for node in nodes.iter_mut() {
+        let ready_messages =
+            ready_messages.iter().choose_multiple(&mut rng, n as usize);
+
+        for (count, ready) in ready_messages.into_iter().cloned().enumerate() {
+            let response = node.ready(ready);
+            if count == (n - t - f - 1) as usize {
+                match response.as_slice() {
+                    [ReadyAction::Complete] => (),
+                    _ => panic!(),
+                }
+            } else {
+                assert!(response.is_empty())
+            }
+        }
+    }
+}
+
+#[test]
+/* Test that snapshots and outputs round trip,
+and that snapshots with an unknown version are rejected */
+fn snapshot_version() {
+    let mut rng = rng();
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+
+    let shared_messages = scheme.run_hybridvss_sh();
+    let output = scheme.nodes[2]
+        .finalize(&shared_messages[2][0..=t as usize])
+        .unwrap();
+    assert_eq!(round_trip(&output), output);
+
+    let mut bytes = Vec::new();
+    scheme.nodes[2].serialize(&mut bytes).unwrap();
+    bytes[0] += 1;
+    assert!(Context::deserialize(&bytes[..]).is_err());
+    let mut bytes = Vec::new();
+    output.serialize(&mut bytes).unwrap();
+    bytes[0] += 1;
+    assert!(DkgOutput::deserialize(&bytes[..]).is_err());
+}
+
+#[test]
+/* test that messages from another session are rejected,
+even when signed by the same keys */
+fn session_mismatch() {
+    let n = 6;
+    let t = 4u32;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w.clone(), &mut rng());
+    // the same keys and leader, in the next session
+    let next = SessionId { epoch: 0, tau: 1 };
+    let other = Scheme::init_session(next, 0, t, w, &mut rng());
+    let mut nodes = scheme.nodes;
+    let mut other_nodes = other.nodes;
+    let l = scheme.params.l;
+    assert_eq!(l, other.params.l);
+
+    let mut rng = rng();
+    // Commitments for each dealer
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+    let shared_messages: Vec<_> = Cs
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(d, C)| Shared {
+            session: SESSION,
+            C,
+            d: (d as u32),
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[d].clone(),
+        })
+        .collect();
+
+    // shared messages are only accepted in their own session
+    let mut send = None;
+    for shared_message in shared_messages.iter().take((t + 1) as usize) {
+        assert!(other_nodes[l as usize].shared(shared_message).is_none());
+        if let Some(SharedAction::Send(s)) =
+            nodes[l as usize].shared(shared_message)
+        {
+            send = Some(s)
+        }
+    }
+    let send = send.unwrap();
+    assert!(send.verify(&scheme.params));
+    assert!(!send.verify(&other.params));
+
+    // sends and echos are only accepted in their own session
+    let echos: Vec<Signed<Echo>> = nodes
+        .iter_mut()
+        .zip(other_nodes.iter_mut())
+        .map(|(node, other_node)| {
+            assert!(other_node.send(send.clone()).is_none());
+            node.send(send.clone()).unwrap()
+        })
+        .collect();
+    let mut ready = None;
+    for echo in echos {
+        assert!(other_nodes[0].echo(echo.clone()).is_none());
+        ready = nodes[0].echo(echo);
+    }
+    assert!(ready.is_some());
+
+    // finalize rejects shared messages from another session
+    let output = other_nodes[0].finalize(&shared_messages);
+    assert_eq!(output.err(), Some(Error::Session(0)));
+}
+
+#[test]
+/* Test that every node outputs the same public key,
+when messages are handled in order */
+fn handle_output() {
+    use std::collections::VecDeque;
+
+    let mut rng = rng();
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let n = w.len();
+    let W = w.iter().sum();
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let shared_messages = scheme.run_hybridvss_sh();
+    let mut nodes = scheme.nodes;
+
+    // queue of (sender, receiver, message) triples
+    let mut queue: VecDeque<(u32, u32, Message)> = VecDeque::new();
+    for (i, shared_i) in shared_messages.into_iter().enumerate() {
+        for shared in shared_i {
+            queue.push_back((i as u32, i as u32, Message::Shared(shared)))
+        }
+    }
+    let mut outputs: Vec<Option<DkgOutput>> = vec![None; n];
+    while let Some((sender, receiver, msg)) = queue.pop_front() {
+        for out in nodes[receiver as usize].handle(sender, msg) {
+            match out {
+                Outgoing::Broadcast(msg) => queue
+                    .extend((0..n as u32).map(|j| (receiver, j, msg.clone()))),
+                Outgoing::To(j, msg) => queue.push_back((receiver, j, msg)),
+                Outgoing::Output(output) => {
+                    assert!(outputs[receiver as usize].is_none());
+                    outputs[receiver as usize] = Some(output)
+                }
+            }
+        }
+    }
+
+    let outputs: Vec<DkgOutput> =
+        outputs.into_iter().map(Option::unwrap).collect();
+    for (i, output) in outputs.iter().enumerate() {
+        assert!(output.verify());
+        assert_eq!(output.pubkey, outputs[0].pubkey);
+        assert_eq!(nodes[i].output(), Some(output));
+    }
+    let secret = interpolate_outputs(&outputs, W);
+    let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+    assert_eq!(g.mul(secret).into_affine(), outputs[0].pubkey);
+
+    // the output survives a snapshot
+    let restored: Context = round_trip(&nodes[0]);
+    assert_eq!(restored.output(), Some(&outputs[0]));
+}
+
+#[test]
+// Test that the hybridvss instances of each dealer can run in parallel
+fn hybridvss_sh_parallel() {
+    let n = 4;
+    let scheme = Arc::new(Scheme::init(0, 2, vec![1; n], &mut rng()));
+    let handles: Vec<_> = (0..n as u32)
+        .map(|d| {
+            let scheme = scheme.clone();
+            std::thread::spawn(move || scheme.hybridvss_sh(d))
+        })
+        .collect();
+    let shared_messages: Vec<Vec<Shared>> = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect();
+
+    // each node finalizes with the shared messages of every dealer
+    let outputs: Vec<DkgOutput> = scheme
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let shares: Vec<Shared> = shared_messages
+                .iter()
+                .map(|shared_d| shared_d[i].clone())
+                .collect();
+            node.finalize(&shares).unwrap()
+        })
+        .collect();
+    for output in outputs.iter() {
+        assert!(output.verify());
+        assert_eq!(output.pubkey, outputs[0].pubkey);
+    }
+}
+
+#[test]
+/* Test that a valid complaint excludes the dealer from proposals,
+and that forged complaints are rejected */
+fn complaint_excludes_dealer() {
+    use ferveo::hybridvss::sh;
+
+    let mut rng = rng();
+    let n = 6;
+    let t = 2u32;
+    let w = vec![1; n as usize];
+    let scheme = Scheme::init(0, t, w, &mut rng);
+    let params = scheme.params.clone();
+    let mut nodes = scheme.nodes;
+    let l = params.l;
+    let d = (l + 1) % n;
+    let m = (l + 2) % n;
+
+    // the dealer sends node j the shares of node k
+    let (j, k) = ((d + 1) % n, (d + 2) % n);
+    let sks = &scheme.sks;
+    let vss =
+        |i: u32| sh::Context::init(params.vss_params(d), i, sks[i as usize]);
+    let mut dealer = vss(d);
+    let share = sh::Share {
+        s: Scalar::rand(&mut rng),
+    };
+    let sends = dealer.share(&mut rng, share);
+    let bad_send = sh::Send {
+        a: sends[k as usize].a.clone(),
+        ..sends[j as usize].clone()
+    };
+    let bad_send = dealer.sign_send(j, bad_send);
+    let vss_j = vss(j);
+    assert!(vss_j.accuse(&sends[j as usize]).is_none());
+    let accusation = vss_j.accuse(&bad_send).unwrap();
+    let complaint = Complaint {
+        accusation: accusation.clone(),
+    };
+    assert!(params.verify_complaint(&complaint));
+    assert_eq!(complaint.dealer(), d);
+
+    // the dealer's signature must be on the accused send message
+    let forged = Complaint {
+        accusation: sh::Accusation {
+            sig: sends[j as usize].sig,
+            ..accusation.clone()
+        },
+    };
+    assert!(!params.verify_complaint(&forged));
+    // the send message must be signed by the dealer
+    let forged = Complaint {
+        accusation: sh::Accusation {
+            sig: vss(k).sign_send(j, bad_send.clone()).sig,
+            ..accusation.clone()
+        },
+    };
+    assert!(!params.verify_complaint(&forged));
+    // the accused shares must be invalid
+    let forged = Complaint {
+        accusation: sh::Accusation {
+            share_hashes: sh::share_hashes(&sends[j as usize]),
+            a_k: Some(sends[j as usize].a[0].clone()),
+            sig: sends[j as usize].sig,
+            ..accusation
+        },
+    };
+    assert!(!params.verify_complaint(&forged));
+    let complaint = round_trip(&complaint);
+
+    // a leader without the complaint proposes the dealer
+    let mut unaware_leader: Context = round_trip(&nodes[l as usize]);
+    for node in [l, m].iter() {
+        let msg = Message::Complaint(complaint.clone());
+        assert!(nodes[*node as usize].handle(j, msg).is_empty());
+        assert!(nodes[*node as usize].complaints().contains_key(&d));
+        assert!(!nodes[*node as usize].complaint(&complaint));
+    }
+
+    // Commitments for each dealer, starting from the accused dealer
+    let dealers: Vec<u32> = (0..n).map(|i| (d + i) % n).collect();
+    let Cs: Vec<_> = (0..n)
+        .map(|_| {
+            let secret =
+                poly::random_secret(t, Scalar::rand(&mut rng), &mut rng);
+            Arc::new(poly::public(&secret))
+        })
+        .collect();
+    let readies = vss_readies(&nodes, &Cs);
+    let shared_messages: Vec<Shared> = dealers
+        .iter()
+        .map(|d| Shared {
+            session: SESSION,
+            C: Cs[*d as usize].clone(),
+            d: *d,
+            s_id: vec![Scalar::rand(&mut rng)],
+            s_hat_id: Vec::new(),
+            R: readies[*d as usize].clone(),
+        })
+        .collect();
+    let propose = |leader: &mut Context| {
+        shared_messages
+            .iter()
+            .find_map(|shared| match leader.shared(shared) {
+                Some(SharedAction::Send(send)) => Some(send),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    // the proposal including the dealer is rejected
+    let send = propose(&mut unaware_leader);
+    assert!(nodes[m as usize].send(send).is_none());
+    // the proposal excluding the dealer is accepted
+    let send = propose(&mut nodes[l as usize]);
+    assert!(nodes[m as usize].send(send).is_some());
+}
+
+#[test]
+/* Test that in pedersen mode,
+the public key is only revealed once the dealers reveal their blinding */
+fn finalize_pedersen() {
+    let mut rng = rng();
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let W = w.iter().sum();
+    let scheme = Scheme::init_pedersen(0, t, w.clone(), &mut rng);
+    let q = t as usize + 1; // the number of agreed dealers
+    let shared_messages = scheme.run_hybridvss_sh();
+    let reveals: Vec<_> = (0..=t).map(|d| scheme.vss_reveal(d)).collect();
+
+    // the reveal of each dealer is needed to finalize
+    let node = &scheme.nodes[0];
+    let shares = &shared_messages[0][..q];
+    assert_eq!(node.finalize(shares), Err(Error::Reveal(0)));
+    assert_eq!(
+        node.finalize_pedersen(shares, &reveals[1..]),
+        Err(Error::Reveal(0))
+    );
+    // a wrong reveal is rejected
+    let mut wrong = reveals.clone();
+    wrong[0].r[0] = Scalar::rand(&mut rng);
+    assert_eq!(
+        node.finalize_pedersen(shares, &wrong),
+        Err(Error::Reveal(0))
+    );
+
+    let outputs: Vec<DkgOutput> = scheme
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let shares = &shared_messages[i][..q];
+            node.finalize_pedersen(shares, &reveals).unwrap()
+        })
+        .collect();
+    let output_0 = &outputs[0];
+    assert!(outputs.iter().all(|output| output.pubkey == output_0.pubkey
+        && output.pubkey_shares == output_0.pubkey_shares));
+    assert!(outputs.iter().all(|output| output.verify()));
+
+    // the blinded commitments do not reveal the public key
+    let blinded = shared_messages[0][..q]
+        .iter()
+        .map(|shared| shared.C[0][0])
+        .fold(ark_bls12_381::G1Projective::zero(), |sum, C_00| {
+            sum + C_00.into_projective()
+        });
+    assert_ne!(blinded.into_affine(), output_0.pubkey);
+
+    // the private shares interpolate to the distributed private key
+    let secret = interpolate_outputs(&outputs, W);
+    let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+    assert_eq!(g.mul(secret).into_affine(), output_0.pubkey);
+}
+
+#[test]
+/* Test that in pedersen mode,
+an agreed dealer that withholds its reveal stalls finalization,
+until the reveal is published */
+fn withheld_reveal() {
+    use std::collections::VecDeque;
+
+    let mut rng = rng();
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let n = w.len();
+    let scheme = Scheme::init_pedersen(0, t, w, &mut rng);
+    let shared_messages = scheme.run_hybridvss_sh();
+    let reveals: Vec<_> = (0..n as u32).map(|d| scheme.vss_reveal(d)).collect();
+    let mut nodes = scheme.nodes;
+
+    // deliver each message in the queue, collecting the outputs
+    let run = |nodes: &mut Vec<Context>,
+               mut queue: VecDeque<(u32, u32, Message)>| {
+        let mut outputs = Vec::new();
+        while let Some((sender, receiver, msg)) = queue.pop_front() {
+            for out in nodes[receiver as usize].handle(sender, msg) {
+                match out {
+                    Outgoing::Broadcast(msg) => queue.extend(
+                        (0..n as u32).map(|j| (receiver, j, msg.clone())),
+                    ),
+                    Outgoing::To(j, msg) => queue.push_back((receiver, j, msg)),
+                    Outgoing::Output(output) => outputs.push(output),
+                }
+            }
+        }
+        outputs
+    };
+    // the reveal of dealer `d`, broadcast to every node
+    let reveal = |d: u32| -> VecDeque<(u32, u32, Message)> {
+        (0..n as u32)
+            .map(|j| (d, j, Message::Reveal(reveals[d as usize].clone())))
+            .collect()
+    };
+
+    let mut queue = VecDeque::new();
+    for (i, shared_i) in shared_messages.into_iter().enumerate() {
+        for shared in shared_i {
+            queue.push_back((i as u32, i as u32, Message::Shared(shared)))
+        }
+    }
+    assert!(run(&mut nodes, queue).is_empty());
+    let q = nodes[0].q_final().unwrap().clone();
+    assert!(nodes.iter().all(|node| node.q_final() == Some(&q)));
+
+    // every other dealer reveals, but one agreed dealer withholds its reveal
+    let withheld = *q.iter().next().unwrap();
+    let queue = (0..n as u32)
+        .filter(|d| *d != withheld)
+        .flat_map(reveal)
+        .collect();
+    assert!(run(&mut nodes, queue).is_empty());
+    assert!(nodes.iter().all(|node| node.output().is_none()));
+
+    // the dkg only finalizes once the withheld reveal is published
+    let outputs = run(&mut nodes, reveal(withheld));
+    assert_eq!(outputs.len(), n);
+    assert!(outputs
+        .iter()
+        .all(|output| output.verify() && output.pubkey == outputs[0].pubkey));
+}
+
+#[test]
+/* Test that encryption keys are only accepted for each participant,
+and are passed to the hybridvss parameters */
+fn params_encryption_keys() {
+    let mut rng = rng();
+    let w = vec![2, 1, 1, 1];
+    let n = w.len() as u32;
+    let params = Scheme::init(0, 2, w, &mut rng).params;
+    let ek: Vec<_> = (0..n)
+        .map(|_| ferveo::ecies::encryption_key(Scalar::rand(&mut rng)))
+        .collect();
+
+    assert_eq!(
+        params.clone().with_encryption_keys(ek[1..].to_vec()).err(),
+        Some(Error::EncryptionKeys(n))
+    );
+    let sealed = params.clone().with_encryption_keys(ek.clone()).unwrap();
+    assert_eq!(sealed.vss_params(0).ek, ek);
+    assert_eq!(round_trip(&sealed).ek, ek);
+
+    // parameters with missing encryption keys do not decode
+    let invalid = Params {
+        ek: ek[1..].to_vec(),
+        ..params
+    };
+    let mut bytes = Vec::new();
+    invalid.serialize(&mut bytes).unwrap();
+    assert!(Params::deserialize(&bytes[..]).is_err());
+}
+
+#[test]
+/* Test that parameters are only built and decoded
+with a signing key for each participant */
+fn params_public_keys() {
+    let mut rng = rng();
+    let w = vec![2, 1, 1, 1];
+    let n = w.len() as u32;
+    let params = Scheme::init(0, 2, w.clone(), &mut rng).params;
+    let pubkeys = params.pubkeys[1..].to_vec();
+
+    assert_eq!(
+        Params::new(SESSION, 0, 0, 2, w.clone(), pubkeys.clone()).err(),
+        Some(Error::PublicKeys(n))
+    );
+    assert_eq!(
+        Params::random_leader(SESSION, 0, 2, w, pubkeys.clone(), &mut rng)
+            .err(),
+        Some(Error::PublicKeys(n))
+    );
+
+    // parameters with missing signing keys do not decode
+    let invalid = Params { pubkeys, ..params };
+    let mut bytes = Vec::new();
+    invalid.serialize(&mut bytes).unwrap();
+    assert!(Params::deserialize(&bytes[..]).is_err());
 }
@@ -1,17 +1,42 @@
 #![allow(clippy::many_single_char_names)]
 #![allow(non_snake_case)]
 
-use ark_bls12_381::Fr;
-use ark_ff::UniformRand;
+use ark_bls12_381::{Fr, G1Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{Field, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bls12_381::Scalar as BlsScalar;
+use either::Either;
+use ferveo::ecies;
 use ferveo::hybridvss::sh::*;
 use ferveo::hybridvss::Params;
+use ferveo::poly;
 use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
 use rand::Rng;
 use rand::SeedableRng;
+use std::sync::Arc;
 
 type Scalar = Fr;
 
+// the signing key of each of `n` participants, from a fixed seed
+pub fn signing_keys(n: u32) -> Vec<BlsScalar> {
+    let mut rng = StdRng::seed_from_u64(1);
+    (0..n)
+        .map(|_| <BlsScalar as ff::Field>::random(&mut rng))
+        .collect()
+}
+
+/* parameters with a random dealer for epoch and session counter 0,
+with the signing keys from `signing_keys` */
+fn random_dealer<R: Rng>(f: u32, t: u32, w: Vec<u32>, rng: &mut R) -> Params {
+    let pubkeys = signing_keys(w.len() as u32)
+        .iter()
+        .map(ferveo::bls::pubkey)
+        .collect();
+    Params::random_dealer(0, 0, f, t, w, pubkeys, rng).unwrap()
+}
+
 // A HybridVss_sh scheme
 pub struct Scheme {
     pub nodes: Vec<Context>,
@@ -21,10 +46,19 @@ pub struct Scheme {
 impl Scheme {
     /* Generate a fresh setup with `n` participants,
     failure threshold `f`,
-    threshold `t` */
+    threshold `t`,
+    and the signing keys from `signing_keys` */
     pub fn new(params: Params) -> Self {
-        let nodes = (0..params.n())
-            .map(|i| Context::init(params.clone(), i))
+        let sks = signing_keys(params.n());
+        Self::with_signing_keys(params, sks)
+    }
+
+    // as `new`, with the signing key `sks[i]` for each node `i`
+    pub fn with_signing_keys(params: Params, sks: Vec<BlsScalar>) -> Self {
+        let nodes = sks
+            .into_iter()
+            .enumerate()
+            .map(|(i, sk)| Context::init(params.clone(), i as u32, sk))
             .collect();
         Scheme { nodes, params }
     }
@@ -86,9 +120,11 @@ impl Scheme {
         let W = self.params.total_weight();
         let threshold = num::integer::div_ceil(W + t + 1, 2) as usize;
         let echos = echos.iter().enumerate().choose_multiple(rng, threshold);
+        /* with weighted nodes, the threshold may be reached
+        before the last echo, so keep the last response */
         let mut response = None;
         for (m, echo) in echos {
-            response = self.echo(i, m as u32, echo);
+            response = self.echo(i, m as u32, echo).or(response);
         }
         response
     }
@@ -113,13 +149,14 @@ impl Scheme {
         self.node_mut(i).ready(m, ready)
     }
 
-    // node i responds to `n-t-f` randomly chosen ready messages
+    /* node i responds to `n-t-f` randomly chosen ready messages,
+    returning the shared message if one was produced */
     fn ready_threshold<R: Rng>(
         &mut self,
         i: u32,
         ready_messages: Vec<&Ready>,
         rng: &mut R,
-    ) -> ReadyResponse {
+    ) -> Option<Shared> {
         let Params { t, f, .. } = self.params;
         let W = self.params.total_weight();
         let threshold = (W - t - f) as usize;
@@ -127,11 +164,12 @@ impl Scheme {
             .iter()
             .enumerate()
             .choose_multiple(rng, threshold);
-        let mut response = None;
-        for (m, ready) in ready_messages {
-            response = self.ready(i, m as u32, ready);
-        }
-        response
+        /* with weighted nodes, the threshold may be reached
+        before the last ready message */
+        ready_messages
+            .into_iter()
+            .flat_map(|(m, ready)| self.ready(i, m as u32, ready))
+            .find_map(Either::right)
     }
 
     // each node responds to `ceil ((n+t+1)/2)` randomly chosen echos
@@ -139,7 +177,7 @@ impl Scheme {
         &mut self,
         ready_messages: Vec<Vec<Ready>>,
         rng: &mut R,
-    ) -> Vec<ReadyResponse> {
+    ) -> Vec<Option<Shared>> {
         (0..self.params.n())
             .map(|i| {
                 let ready_messages: Vec<&Ready> = ready_messages
@@ -157,7 +195,7 @@ impl Scheme {
 fn verify_share_valid() {
     let mut rng = StdRng::seed_from_u64(0);
     let w = vec![1; 6];
-    let params = Params::random_dealer(0, 4, w, &mut rng);
+    let params = random_dealer(0, 4, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -175,7 +213,7 @@ fn verify_share_valid() {
 fn verify_share_invalid() {
     let mut rng = StdRng::seed_from_u64(0);
     let w = vec![1; 6];
-    let params = Params::random_dealer(0, 4, w, &mut rng);
+    let params = random_dealer(0, 4, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -197,7 +235,7 @@ fn verify_share_invalid() {
 fn send_echo_valid() {
     let mut rng = StdRng::seed_from_u64(0);
     let w = vec![2; 6];
-    let params = Params::random_dealer(0, 4, w, &mut rng);
+    let params = random_dealer(0, 4, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -212,7 +250,7 @@ fn send_echo_valid() {
 fn send_echo_invalid() {
     let mut rng = StdRng::seed_from_u64(0);
     let w = vec![1; 6];
-    let params = Params::random_dealer(0, 4, w, &mut rng);
+    let params = random_dealer(0, 4, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -234,7 +272,7 @@ fn send_verify_point_valid() {
     let mut rng = StdRng::seed_from_u64(0);
     let n = 8;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(0, 5, w, &mut rng);
+    let params = random_dealer(0, 5, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -244,7 +282,7 @@ fn send_verify_point_valid() {
     let echos = scheme.send_valid_each(sends);
     for i in 0..n {
         echos.iter().enumerate().for_each(|(m, m_echos)| {
-            let Echo { C, alpha } = &m_echos[i];
+            let Echo { C, alpha, .. } = &m_echos[i];
             assert!(scheme.nodes[i].verify_point(m as u32, C, alpha))
         })
     }
@@ -256,7 +294,7 @@ fn send_verify_point_invalid() {
     let mut rng = StdRng::seed_from_u64(0);
     let n = 8;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(0, 5, w, &mut rng);
+    let params = random_dealer(0, 5, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -266,7 +304,7 @@ fn send_verify_point_invalid() {
     let echos = scheme.send_valid_each(sends);
     for i in 0..n {
         echos.iter().enumerate().for_each(|(m, m_echos)| {
-            let Echo { C, alpha } = &m_echos[i];
+            let Echo { C, alpha, .. } = &m_echos[i];
             // should fail with incorrect m
             for not_m in 0..n {
                 if not_m != m {
@@ -280,7 +318,7 @@ fn send_verify_point_invalid() {
             // should fail with correct m and mismatched echo
             for not_i in 0..n {
                 if not_i != i {
-                    let Echo { C, alpha } = &m_echos[not_i];
+                    let Echo { C, alpha, .. } = &m_echos[not_i];
                     assert!(!scheme.nodes[i].verify_point(m as u32, C, alpha))
                 }
             }
@@ -289,12 +327,117 @@ fn send_verify_point_invalid() {
 }
 
 #[test]
-// test that all nodes generate ready messages with enough valid echos
+/* test batch verification of the echo points from all nodes,
+including finding the invalid points */
+fn echo_verify_points_batch() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for pedersen in [false, true].iter() {
+        let w = vec![2, 1, 3, 1, 2];
+        let params = Params {
+            pedersen: *pedersen,
+            ..random_dealer(0, 3, w, &mut rng)
+        };
+        let mut scheme = Scheme::new(params.clone());
+        let share = Share {
+            s: Scalar::rand(&mut rng),
+        };
+
+        let sends = scheme.dealer_share(share, &mut rng);
+        let echos = scheme.send_valid_each(sends);
+        for i in 0..params.n() as usize {
+            let C = &echos[0][i].C;
+            let mut points: Vec<Point> = echos
+                .iter()
+                .enumerate()
+                .flat_map(|(m, m_echos)| {
+                    let Echo {
+                        alpha, alpha_hat, ..
+                    } = &m_echos[i];
+                    message_points(m as u32, alpha, alpha_hat)
+                })
+                .collect();
+            let node = &scheme.nodes[i];
+            assert!(node.verify_points_batch(C, &points, &mut rng).is_empty());
+
+            // invalid and out of range points are found
+            points[1].alpha += Scalar::from(1u64);
+            points[4].alpha_hat += Scalar::from(1u64);
+            let last = points.len() - 1;
+            points[last].sender = params.n();
+            // blinding points are invalid outside of pedersen mode
+            let invalid = vec![1, 4, last];
+            assert_eq!(node.verify_points_batch(C, &points, &mut rng), invalid);
+        }
+    }
+}
+
+#[test]
+/* test that all nodes output their shares when echo and ready messages
+are handled in batches, and that invalid or repeated messages are not counted */
+fn echo_ready_batch() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for pedersen in [false, true].iter() {
+        let w = vec![2, 1, 3, 1, 2];
+        let params = Params {
+            pedersen: *pedersen,
+            ..random_dealer(0, 3, w.clone(), &mut rng)
+        };
+        let n = params.n() as usize;
+        let mut scheme = Scheme::new(params);
+        let share = Share {
+            s: Scalar::rand(&mut rng),
+        };
+
+        let sends = scheme.dealer_share(share, &mut rng);
+        let echos = scheme.send_valid_each(sends);
+        let ready_messages: Vec<Vec<Ready>> = (0..n)
+            .map(|i| {
+                let mut batch: Vec<(u32, Echo)> = echos
+                    .iter()
+                    .enumerate()
+                    .map(|(m, echos_m)| (m as u32, echos_m[i].clone()))
+                    .collect();
+                // an invalid echo, and a repeated echo
+                let mut invalid = batch[1].1.clone();
+                invalid.alpha[0][0] += Scalar::from(1u64);
+                batch.insert(0, (1, invalid));
+                batch.push(batch[2].clone());
+                let mut responses =
+                    scheme.nodes[i].echo_batch(&batch, &mut rng);
+                assert_eq!(responses.len(), 1);
+                assert_eq!(
+                    scheme.nodes[i].e.values().sum::<u32>(),
+                    w.iter().sum()
+                );
+                responses.remove(0)
+            })
+            .collect();
+
+        for i in 0..n {
+            let batch: Vec<(u32, Ready)> = ready_messages
+                .iter()
+                .enumerate()
+                .map(|(m, ready_m)| (m as u32, ready_m[i].clone()))
+                .collect();
+            let responses = scheme.nodes[i].ready_batch(&batch, &mut rng);
+            let shared: Vec<Shared> =
+                responses.into_iter().filter_map(Either::right).collect();
+            assert_eq!(shared.len(), 1);
+            assert_eq!(shared[0].s.len(), w[i] as usize);
+            // the ready messages are not counted again
+            assert!(scheme.nodes[i].ready_batch(&batch, &mut rng).is_empty());
+        }
+    }
+}
+
+#[test]
+/* test that all nodes generate ready messages with enough valid echos,
+and that later echos are not answered */
 fn echo_ready_threshold() {
     let mut rng = StdRng::seed_from_u64(0);
     let n = 8;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(0, 5, w, &mut rng);
+    let params = random_dealer(0, 5, w, &mut rng);
     let mut scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -313,7 +456,8 @@ fn echo_ready_threshold() {
             .enumerate()
             .for_each(|(count, (m, echo))| {
                 let echo_response = scheme.echo(i, m as u32, &echo);
-                if count >= 7 - 1 {
+                // only the echo that crosses the threshold is answered
+                if count == 7 - 1 {
                     assert!(echo_response.is_some())
                 } else {
                     assert!(echo_response.is_none())
@@ -330,7 +474,7 @@ fn echo_verify_point_valid() {
     let n = 8;
     let t = 5;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(f, t, w, &mut rng);
+    let params = random_dealer(f, t, w, &mut rng);
     let scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -376,7 +520,7 @@ fn ready_shared_threshold() {
     let n = 8;
     let t = 5;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(f, t, w, &mut rng);
+    let params = random_dealer(f, t, w, &mut rng);
     let scheme = Scheme::new(params);
     let share = Share {
         s: Scalar::rand(&mut rng),
@@ -408,7 +552,7 @@ fn ready_shared_threshold() {
         .collect();
     nodes.iter_mut().enumerate().for_each(|(i, node)| {
         use rand::seq::IteratorRandom;
-        let mut res = None;
+        let mut res = Vec::new();
         ready_messages
             .iter()
             .map(|rs| rs[i].clone())
@@ -417,11 +561,11 @@ fn ready_shared_threshold() {
             .into_iter()
             .for_each(|(m, ready)| {
                 assert!(node.verify_point(m as u32, &ready.C, &ready.alpha));
-                assert!(res.is_none());
+                assert!(res.is_empty());
                 res = node.ready(m as u32, &ready);
             });
-        assert!(res.is_some());
-        assert!(res.unwrap().is_right())
+        assert_eq!(res.len(), 1);
+        assert!(res[0].is_right())
     })
 }
 
@@ -433,7 +577,7 @@ fn reconstruct_share() {
     let n = 8;
     let t = 5;
     let w = vec![1; n as usize];
-    let params = Params::random_dealer(f, t, w, &mut rng);
+    let params = random_dealer(f, t, w, &mut rng);
     let scheme = Scheme::new(params.clone());
     let s = Scalar::rand(&mut rng);
     let mut nodes = scheme.nodes;
@@ -465,7 +609,7 @@ fn reconstruct_share() {
         .iter_mut()
         .enumerate()
         .map(|(i, node)| {
-            let mut res = None;
+            let mut res = Vec::new();
             ready_messages
                 .iter()
                 .enumerate()
@@ -474,10 +618,11 @@ fn reconstruct_share() {
                 .map(|(m, ready_messages_m)| (m, ready_messages_m[i].clone()))
                 .into_iter()
                 .for_each(|(m, ready_message)| {
-                    assert!(res.is_none());
+                    assert!(res.is_empty());
                     res = node.ready(m as u32, &ready_message);
                 });
-            res.expect("Unexpected failure to generate shared message")
+            res.pop()
+                .expect("Unexpected failure to generate shared message")
                 .expect_right("Unexpected failure to generate shared message")
         })
         .collect();
@@ -486,7 +631,7 @@ fn reconstruct_share() {
     let mut rec_node = {
         let C = (*shared_messages[i as usize].C).clone();
         let domain = nodes[i].domain;
-        let s = shared_messages[i as usize].s;
+        let s = shared_messages[i as usize].s.clone();
         ferveo::hybridvss::rec::Context::init(params, C, domain, s)
     };
     // accept T + 1 shares
@@ -498,8 +643,858 @@ fn reconstruct_share() {
         .into_iter()
         .for_each(|(j, shared_message)| {
             assert!(z_i.is_none());
-            z_i = rec_node.reconstruct_share(j as u32, shared_message.s);
+            z_i = rec_node.reconstruct_share(j as u32, &shared_message.s);
         });
     let z_i = z_i.expect("failed to reconstruct share");
     assert!(z_i == s);
 }
+
+#[test]
+// test that weighted nodes finish with one valid share per unit of weight
+fn ready_shared_weighted() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let params = random_dealer(0, t, w.clone(), &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+    let ready_messages = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .map(|echo_response| echo_response.unwrap())
+        .collect();
+    let shared_messages: Vec<Shared> = scheme
+        .ready_threshold_each(ready_messages, &mut rng)
+        .into_iter()
+        .map(|ready_response| {
+            ready_response
+                .expect("Unexpected failure to generate shared message")
+        })
+        .collect();
+
+    // each share matches the public polynomial at its domain point
+    let g = G1Affine::prime_subgroup_generator();
+    let omega = scheme.nodes[0].domain.group_gen;
+    let share_indexes = params.share_indexes();
+    for (j, shared_message) in shared_messages.iter().enumerate() {
+        assert_eq!(shared_message.s.len(), w[j] as usize);
+        for (k, s_k) in shared_message.s.iter().enumerate() {
+            let x = omega.pow([(share_indexes[j] + k) as u64]);
+            assert_eq!(
+                g.mul(*s_k).into_affine(),
+                ferveo::poly::public_share(&shared_message.C, x)
+            );
+        }
+    }
+
+    // reconstruct from nodes with total weight at least t + 1
+    let mut rec_node = {
+        let C = (*shared_messages[1].C).clone();
+        let domain = scheme.nodes[1].domain;
+        let s = shared_messages[1].s.clone();
+        ferveo::hybridvss::rec::Context::init(params, C, domain, s)
+    };
+    assert!(rec_node
+        .reconstruct_share(1, &shared_messages[1].s)
+        .is_none());
+    assert!(rec_node
+        .reconstruct_share(3, &shared_messages[3].s)
+        .is_none());
+    let z_i = rec_node.reconstruct_share(2, &shared_messages[2].s);
+    assert_eq!(z_i, Some(s));
+}
+
+#[test]
+// test that nodes restored from snapshots finish with the same shares
+fn ready_shared_restore() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let params = random_dealer(0, t, w, &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+    let ready_messages: Vec<Vec<Ready>> = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .map(|echo_response| echo_response.unwrap())
+        .collect();
+
+    // snapshot and restore each node after the echo phase
+    let mut restored = Scheme::new(params.clone());
+    restored.nodes = scheme
+        .nodes
+        .iter()
+        .map(|node| {
+            let mut bytes = Vec::new();
+            node.serialize(&mut bytes).unwrap();
+            Context::deserialize(&bytes[..]).unwrap()
+        })
+        .collect();
+    let ready_messages_restored = ready_messages
+        .iter()
+        .map(|ready_messages_m| {
+            ready_messages_m
+                .iter()
+                .map(|ready| {
+                    let mut bytes = Vec::new();
+                    ready.serialize(&mut bytes).unwrap();
+                    Ready::deserialize(&bytes[..]).unwrap()
+                })
+                .collect()
+        })
+        .collect();
+
+    let shared = |responses: Vec<Option<Shared>>| -> Vec<Shared> {
+        responses
+            .into_iter()
+            .map(|response| response.expect("no shares"))
+            .collect()
+    };
+    let mut rng_restored = rng.clone();
+    let shared_messages =
+        shared(scheme.ready_threshold_each(ready_messages, &mut rng));
+    let shared_messages_restored = shared(
+        restored
+            .ready_threshold_each(ready_messages_restored, &mut rng_restored),
+    );
+    for (shared, shared_restored) in
+        shared_messages.iter().zip(shared_messages_restored.iter())
+    {
+        assert_eq!(shared.s, shared_restored.s);
+    }
+
+    // a restored reconstruction context continues reconstruction
+    let mut rec_node = {
+        let C = (*shared_messages[1].C).clone();
+        let domain = scheme.nodes[1].domain;
+        let s = shared_messages[1].s.clone();
+        ferveo::hybridvss::rec::Context::init(params, C, domain, s)
+    };
+    assert!(rec_node
+        .reconstruct_share(1, &shared_messages[1].s)
+        .is_none());
+    let mut bytes = Vec::new();
+    rec_node.serialize(&mut bytes).unwrap();
+    let mut rec_node =
+        ferveo::hybridvss::rec::Context::deserialize(&bytes[..]).unwrap();
+    assert!(rec_node
+        .reconstruct_share(3, &shared_messages[3].s)
+        .is_none());
+    let z_i = rec_node.reconstruct_share(2, &shared_messages[2].s);
+    assert_eq!(z_i, Some(s));
+}
+
+#[test]
+// test that sends and echos from another session are rejected
+fn session_mismatch() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let n = 6;
+    let w = vec![1; n];
+    let params = random_dealer(0, 4, w, &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    // the same dealer and weights, in the next session
+    let mut other = Scheme::new(Params { tau: 1, ..params });
+    let share = Share {
+        s: Scalar::rand(&mut rng),
+    };
+
+    let sends = scheme.dealer_share(share, &mut rng);
+    for (i, send) in sends.iter().enumerate() {
+        assert!(scheme.nodes[i].verify_share(send));
+        assert!(!other.nodes[i].verify_share(send));
+    }
+    let echos = scheme.send_valid_each(sends);
+    for (m, m_echos) in echos.iter().enumerate() {
+        for (i, echo) in m_echos.iter().enumerate() {
+            assert!(other.echo(i as u32, m as u32, echo).is_none())
+        }
+    }
+    // a valid echo is counted only in its own session
+    let Echo { C, alpha, .. } = &echos[0][1];
+    assert!(other.nodes[1].verify_point(0, C, alpha));
+    assert!(other.nodes[1].e.is_empty());
+}
+
+#[test]
+// test that each node outputs its shares when messages are handled in order
+fn handle_shared() {
+    use std::collections::VecDeque;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let n = w.len();
+    let params = random_dealer(0, t, w.clone(), &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    // queue of (sender, event) pairs
+    let d = params.d;
+    let mut queue: VecDeque<(u32, Outgoing)> = scheme.nodes[d as usize]
+        .deal(&mut rng, Share { s })
+        .into_iter()
+        .map(|out| (d, out))
+        .collect();
+    let mut outputs: Vec<Option<Shared>> = vec![None; n];
+    while let Some((sender, out)) = queue.pop_front() {
+        match out {
+            Outgoing::To(j, msg) => {
+                let responses = scheme.nodes[j as usize].handle(sender, msg);
+                queue.extend(responses.into_iter().map(|out| (j, out)))
+            }
+            Outgoing::Output(shared) => {
+                assert!(outputs[sender as usize].is_none());
+                outputs[sender as usize] = Some(shared)
+            }
+            Outgoing::Broadcast(_) => panic!("unexpected broadcast"),
+        }
+    }
+    let outputs: Vec<Shared> =
+        outputs.into_iter().map(Option::unwrap).collect();
+    for (j, output) in outputs.iter().enumerate() {
+        assert_eq!(output.s.len(), w[j] as usize);
+    }
+
+    // reconstruct from nodes with total weight at least t + 1
+    let mut rec_node = {
+        let C = (*outputs[0].C).clone();
+        let domain = scheme.nodes[0].domain;
+        let s = outputs[0].s.clone();
+        ferveo::hybridvss::rec::Context::init(params, C, domain, s)
+    };
+    assert_eq!(rec_node.reconstruct_share(0, &outputs[0].s), Some(s));
+
+    // send messages from nodes other than the dealer are ignored
+    let other = (d + 1) % n as u32;
+    let mut other_scheme = Scheme::new(scheme.params.clone());
+    let sends = other_scheme.nodes[d as usize].deal(&mut rng, Share { s });
+    for (j, send) in sends.into_iter().enumerate() {
+        if let Outgoing::To(_, msg) = send {
+            assert!(other_scheme.nodes[j].handle(other, msg).is_empty())
+        }
+    }
+}
+
+#[test]
+// test that replayed echo and ready messages are only counted once
+fn echo_ready_replay() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let W: u32 = w.iter().sum();
+    let params = random_dealer(0, t, w.clone(), &mut rng);
+    let mut scheme = Scheme::new(params);
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+
+    /* the heaviest node replaying its echo does not reach
+    the echo threshold `ceil((W + t + 1) / 2)` */
+    let i = 1;
+    for _ in 0..W {
+        assert!(scheme.echo(i, 0, &echos[0][i as usize]).is_none());
+    }
+    let C_hash = *scheme.nodes[i as usize].e.keys().next().unwrap();
+    assert_eq!(scheme.nodes[i as usize].e[&C_hash], w[0]);
+
+    // the remaining echos reach the threshold at the third node
+    let responses: Vec<EchoResponse> = (1..w.len())
+        .map(|m| scheme.echo(i, m as u32, &echos[m][i as usize]))
+        .collect();
+    let first = responses.iter().position(Option::is_some);
+    assert_eq!(first, Some(1));
+    // replaying any echo has no effect
+    for (m, echos_m) in echos.iter().enumerate() {
+        assert!(scheme.echo(i, m as u32, &echos_m[i as usize]).is_none());
+    }
+    assert_eq!(scheme.nodes[i as usize].e[&C_hash], W);
+
+    let ready_messages: Vec<Vec<Ready>> = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .enumerate()
+        .map(|(j, echo_response)| match echo_response {
+            Some(ready_messages) => ready_messages,
+            // node i has already responded to every echo
+            None if j == i as usize => responses[1].clone().unwrap(),
+            None => panic!("Unexpected failure to generate ready messages"),
+        })
+        .collect();
+
+    /* the heaviest node replaying its ready message does not reach
+    the shared threshold `W - t - f` */
+    for _ in 0..W {
+        assert!(scheme
+            .ready(i, 0, &ready_messages[0][i as usize])
+            .is_empty());
+    }
+    assert_eq!(scheme.nodes[i as usize].r[&C_hash], w[0]);
+
+    // the remaining ready messages produce exactly one shared message
+    let shared: Vec<ReadyResponse> = (1..w.len())
+        .map(|m| scheme.ready(i, m as u32, &ready_messages[m][i as usize]))
+        .collect();
+    assert_eq!(shared.iter().filter(|r| !r.is_empty()).count(), 1);
+    assert!(shared.iter().flatten().all(|r| r.is_right()));
+    // replaying any ready message has no effect
+    for (m, ready_messages_m) in ready_messages.iter().enumerate() {
+        let ready = &ready_messages_m[i as usize];
+        assert!(scheme.ready(i, m as u32, ready).is_empty());
+    }
+    assert_eq!(scheme.nodes[i as usize].r[&C_hash], W);
+}
+
+#[test]
+// test that an invalid send message is answered with a verifiable accusation
+fn send_accusation() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let n = 6;
+    let w = vec![1; n];
+    let params = random_dealer(0, 3, w, &mut rng);
+    let d = params.d;
+    let mut scheme = Scheme::new(params.clone());
+    let share = Share {
+        s: Scalar::rand(&mut rng),
+    };
+    let sends = scheme.dealer_share(share, &mut rng);
+
+    // the dealer sends node j the shares of node k
+    let (j, k) = ((d as usize + 1) % n, (d as usize + 2) % n);
+    let unsigned_send = Send {
+        a: sends[k].a.clone(),
+        ..sends[j].clone()
+    };
+    // send messages that are not signed by the dealer are ignored
+    assert!(scheme.nodes[j].accuse(&unsigned_send).is_none());
+    let msg = Message::Send(unsigned_send.clone());
+    assert!(scheme.nodes[j].handle(d, msg).is_empty());
+    let bad_send = scheme.nodes[d as usize].sign_send(j as u32, unsigned_send);
+    let response = scheme.nodes[j].handle(d, Message::Send(bad_send.clone()));
+    let accusation = match &response[..] {
+        [Outgoing::Broadcast(Message::Accusation(accusation))] => {
+            accusation.clone()
+        }
+        _ => panic!("Unexpected response to an invalid send"),
+    };
+    assert_eq!(accusation.accuser, j as u32);
+    // only the invalid share is revealed
+    assert_eq!(accusation.a_k, Some(sends[k].a[0].clone()));
+    assert_eq!(accusation.send_hash(&params), hash_send(&params, &bad_send));
+    assert!(accusation.verify(&params));
+    assert!(scheme.nodes[j].accusations.contains_key(&(j as u32)));
+
+    // every node accepts the accusation once
+    let msg = {
+        let mut bytes = Vec::new();
+        Message::Accusation(accusation.clone())
+            .serialize(&mut bytes)
+            .unwrap();
+        Message::deserialize(&bytes[..]).unwrap()
+    };
+    for node in scheme.nodes.iter_mut() {
+        // accusations forwarded by other nodes are ignored
+        assert!(node.handle(k as u32, msg.clone()).is_empty());
+        assert!(node.handle(j as u32, msg.clone()).is_empty());
+        assert!(node.accusations.contains_key(&(j as u32)));
+        assert!(!node.accusation(&accusation));
+    }
+
+    // accusations against valid shares are rejected
+    let false_accusation = Accusation {
+        accuser: k as u32,
+        share_hashes: share_hashes(&sends[k]),
+        a_k: Some(sends[k].a[0].clone()),
+        ..accusation.clone()
+    };
+    assert!(!false_accusation.verify(&params));
+    assert!(!scheme.nodes[0].accusation(&false_accusation));
+    // the revealed share must match its hash
+    let false_accusation = Accusation {
+        a_k: Some(sends[j].a[0].clone()),
+        ..accusation.clone()
+    };
+    assert!(!false_accusation.verify(&params));
+    // the accused send message must be signed by the dealer
+    let false_accusation = Accusation {
+        sig: sends[j].sig,
+        ..accusation.clone()
+    };
+    assert!(!false_accusation.verify(&params));
+
+    // a send message with missing shares is accused without revealing any
+    let empty_send = Send {
+        a: Vec::new(),
+        ..sends[j].clone()
+    };
+    assert!(scheme.nodes[j].accuse(&empty_send).is_none());
+    let empty_send = scheme.nodes[d as usize].sign_send(j as u32, empty_send);
+    let empty_accusation = scheme.nodes[j].accuse(&empty_send).unwrap();
+    assert!(empty_accusation.a_k.is_none());
+    assert!(empty_accusation.verify(&params));
+    // but only if the dealer signed the send message with missing shares
+    let false_accusation = Accusation {
+        sig: sends[j].sig,
+        ..empty_accusation
+    };
+    assert!(!false_accusation.verify(&params));
+    // accusations for another session are rejected
+    let other = Params { tau: 1, ..params };
+    assert!(!accusation.verify(&other));
+}
+
+#[test]
+/* send messages with a commitment that is not shaped as
+`t + 1` symmetric rows of `t + 1` points are rejected without panicking,
+and the dealer is accused if it signed one */
+fn ragged_commitment() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let n = 6;
+    let w = vec![1; n];
+    let params = random_dealer(0, 3, w, &mut rng);
+    let d = params.d;
+    let mut scheme = Scheme::new(params.clone());
+    let share = Share {
+        s: Scalar::rand(&mut rng),
+    };
+    let sends = scheme.dealer_share(share, &mut rng);
+    let j = (d as usize + 1) % n;
+
+    let mut ragged = (*sends[j].C).clone();
+    ragged[3].pop();
+    let mut short = (*sends[j].C).clone();
+    short.pop();
+    let mut asymmetric = (*sends[j].C).clone();
+    asymmetric[0][1] = asymmetric[0][0];
+    let mut empty_row = (*sends[j].C).clone();
+    empty_row[1].clear();
+    for C in [ragged, short, asymmetric, empty_row] {
+        assert!(!poly::shaped(&C, params.t));
+        let C = Arc::new(C);
+        let send = Send {
+            C: C.clone(),
+            ..sends[j].clone()
+        };
+        let send = scheme.nodes[d as usize].sign_send(j as u32, send);
+        assert!(!verify_send(&params, j as u32, &send));
+        assert!(!scheme.nodes[j].verify_share(&send));
+        let accusation = scheme.nodes[j].accuse(&send).unwrap();
+        assert!(accusation.a_k.is_none());
+        assert!(accusation.verify(&params));
+
+        // points are never valid against a misshapen commitment
+        let alpha = vec![vec![Scalar::rand(&mut rng)]];
+        assert!(!scheme.nodes[j].verify_point(d, &C, &alpha));
+        let points = message_points(d, &alpha, &[]);
+        let invalid =
+            scheme.nodes[j].verify_points_batch(&C, &points, &mut rng);
+        assert_eq!(invalid, vec![0]);
+
+        // evaluating a misshapen commitment does not panic
+        let x = Scalar::rand(&mut rng);
+        let _ = poly::public_share(&C, x);
+        let unblinded = poly::unblind(&C, &[x], poly::pedersen_generator());
+        assert_eq!(unblinded.len(), C.len());
+        let _ = poly::public_share(&unblinded, x);
+    }
+    assert!(poly::shaped(&sends[j].C, params.t));
+}
+
+#[test]
+// test that a node that missed every message finishes with help from peers
+fn help_recovers_shares() {
+    use std::collections::VecDeque;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let params = random_dealer(0, t, w.clone(), &mut rng);
+    let d = params.d;
+    // a node of weight 1, other than the dealer
+    let offline = (0..w.len() as u32)
+        .find(|j| *j != d && w[*j as usize] == 1)
+        .unwrap();
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    // run the protocol, dropping every message to the offline node
+    let mut queue: VecDeque<(u32, Outgoing)> = scheme.nodes[d as usize]
+        .deal(&mut rng, Share { s })
+        .into_iter()
+        .map(|out| (d, out))
+        .collect();
+    let deliver = |scheme: &mut Scheme,
+                   queue: &mut VecDeque<(u32, Outgoing)>,
+                   drop: Option<u32>| {
+        let mut output = None;
+        while let Some((sender, out)) = queue.pop_front() {
+            match out {
+                Outgoing::To(j, _) if Some(j) == drop => (),
+                Outgoing::To(j, msg) => {
+                    let responses =
+                        scheme.nodes[j as usize].handle(sender, msg);
+                    queue.extend(responses.into_iter().map(|out| (j, out)))
+                }
+                Outgoing::Broadcast(msg) => {
+                    for j in (0..w.len() as u32).filter(|j| *j != sender) {
+                        queue.push_back((sender, Outgoing::To(j, msg.clone())))
+                    }
+                }
+                Outgoing::Output(shared) if sender == offline => {
+                    output = Some(shared)
+                }
+                Outgoing::Output(_) => (),
+            }
+        }
+        output
+    };
+    assert!(deliver(&mut scheme, &mut queue, Some(offline)).is_none());
+    assert!(scheme.nodes[offline as usize].A.is_empty());
+
+    // the offline node requests help, and finishes with valid shares
+    let request = scheme.nodes[offline as usize].request_help();
+    queue.extend(request.into_iter().map(|out| (offline, out)));
+    let shared = deliver(&mut scheme, &mut queue, None).unwrap();
+    assert_eq!(shared.s.len(), 1);
+    let g = G1Affine::prime_subgroup_generator();
+    let x = scheme.nodes[0]
+        .domain
+        .group_gen
+        .pow([params.share_indexes()[offline as usize] as u64]);
+    assert_eq!(
+        g.mul(shared.s[0]).into_affine(),
+        ferveo::poly::public_share(&shared.C, x)
+    );
+
+    // help requests from each node are rate limited
+    let help = Help {
+        session: params.session_id(),
+    };
+    for _ in 1..HELP_LIMIT {
+        let readies = scheme.nodes[d as usize].help(offline, &help);
+        assert_eq!(readies.len(), 1);
+        // the answer is the dealer's ready message for the offline node
+        let Ready { C, alpha, .. } = &readies[0];
+        assert!(scheme.nodes[offline as usize].verify_point(d, C, alpha));
+    }
+    assert!(scheme.nodes[d as usize].help(offline, &help).is_empty());
+}
+
+#[test]
+// test that reconstruction succeeds despite faulty senders
+fn reconstruct_faulty() {
+    use ferveo::hybridvss::rec;
+    use std::collections::VecDeque;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let f = 1;
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let params = random_dealer(f, t, w.clone(), &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+    let ready_messages = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .map(|echo_response| echo_response.unwrap())
+        .collect();
+    let shared_messages: Vec<Shared> = scheme
+        .ready_threshold_each(ready_messages, &mut rng)
+        .into_iter()
+        .map(|ready_response| ready_response.expect("no shares"))
+        .collect();
+    let mut nodes: Vec<rec::Context> = shared_messages
+        .iter()
+        .enumerate()
+        .map(|(j, shared)| {
+            let C = (*shared.C).clone();
+            let domain = scheme.nodes[j].domain;
+            rec::Context::init(params.clone(), C, domain, shared.s.clone())
+        })
+        .collect();
+
+    /* the heaviest node is faulty, and is the first sender,
+    so its shares would be interpolated first if they were counted */
+    let faulty = 0;
+    let mut queue: VecDeque<(u32, rec::Outgoing)> = VecDeque::new();
+    for (j, node) in nodes.iter().enumerate() {
+        let mut outgoing = node.broadcast_share();
+        if j == faulty {
+            let msg = rec::ReconstructShare {
+                session: params.session_id(),
+                s: (0..w[j]).map(|_| Scalar::rand(&mut rng)).collect(),
+            };
+            outgoing = vec![rec::Outgoing::Broadcast(msg)]
+        }
+        // every message is delivered twice
+        queue.extend(outgoing.clone().into_iter().map(|out| (j as u32, out)));
+        queue.extend(outgoing.into_iter().map(|out| (j as u32, out)));
+    }
+    let mut outputs = vec![None; w.len()];
+    while let Some((sender, out)) = queue.pop_front() {
+        let msg = match out {
+            rec::Outgoing::Broadcast(msg) => msg,
+            _ => panic!("unexpected outgoing message"),
+        };
+        for (j, node) in nodes.iter_mut().enumerate() {
+            for out in node.handle(sender, msg.clone()) {
+                match out {
+                    rec::Outgoing::Output(z) => {
+                        assert!(outputs[j].is_none());
+                        outputs[j] = Some(z)
+                    }
+                    _ => panic!("unexpected outgoing message"),
+                }
+            }
+        }
+    }
+    for node in nodes.iter_mut() {
+        // shares from a faulty node are never counted
+        assert!(node
+            .reconstruct_share(faulty as u32, &shared_messages[faulty].s)
+            .is_none());
+        assert_eq!(node.reconstruct(), Some(s));
+    }
+    assert!(outputs.into_iter().all(|z| z == Some(s)));
+}
+
+#[test]
+// test that robust reconstruction corrects wrong shares and names their senders
+fn reconstruct_robust() {
+    use ferveo::hybridvss::rec;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let f = 2;
+    let t = 2;
+    let w = vec![1; 7];
+    let params = random_dealer(f, t, w.clone(), &mut rng);
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+    let ready_messages = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .map(|echo_response| echo_response.unwrap())
+        .collect();
+    let shared_messages: Vec<Shared> = scheme
+        .ready_threshold_each(ready_messages, &mut rng)
+        .into_iter()
+        .map(|ready_response| ready_response.expect("no shares"))
+        .collect();
+
+    // (n - t - 1) / 2 nodes send wrong shares
+    let faulty = vec![0, 3];
+    let messages: Vec<rec::ReconstructShare> = shared_messages
+        .iter()
+        .enumerate()
+        .map(|(j, shared)| rec::ReconstructShare {
+            session: params.session_id(),
+            s: if faulty.contains(&(j as u32)) {
+                vec![Scalar::rand(&mut rng)]
+            } else {
+                shared.s.clone()
+            },
+        })
+        .collect();
+    for (i, shared) in shared_messages.iter().enumerate() {
+        let mut node = {
+            let C = (*shared.C).clone();
+            let domain = scheme.nodes[i].domain;
+            let s = shared.s.clone();
+            rec::Context::init_robust(params.clone(), C, domain, s)
+        };
+        let mut outputs = Vec::new();
+        for (j, msg) in messages.iter().enumerate() {
+            outputs.extend(node.handle(j as u32, msg.clone()));
+            // replayed messages are ignored
+            assert!(node.handle(j as u32, msg.clone()).is_empty());
+        }
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(node.reconstruct(), Some(s));
+        assert_eq!(node.faulty(), &faulty[..]);
+    }
+}
+
+#[test]
+// test that pedersen commitments hide the secret until the dealer reveals
+fn pedersen_shared() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let params = Params {
+        pedersen: true,
+        ..random_dealer(0, t, w.clone(), &mut rng)
+    };
+    let mut scheme = Scheme::new(params.clone());
+    let s = Scalar::rand(&mut rng);
+
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let echos = scheme.send_valid_each(sends);
+    let ready_messages = scheme
+        .echo_threshold_each(echos, &mut rng)
+        .into_iter()
+        .map(|echo_response| echo_response.unwrap())
+        .collect();
+    let shared_messages: Vec<Shared> = scheme
+        .ready_threshold_each(ready_messages, &mut rng)
+        .into_iter()
+        .map(|ready_response| ready_response.expect("no shares"))
+        .collect();
+
+    // the commitment does not reveal the secret
+    let g = G1Affine::prime_subgroup_generator();
+    let C = &shared_messages[0].C;
+    assert_ne!(C[0][0], g.mul(s).into_affine());
+
+    // each node checks the reveal against the commitment
+    let reveal = scheme.nodes[params.d as usize].reveal().unwrap();
+    let omega = scheme.nodes[0].domain.group_gen;
+    let share_indexes = params.share_indexes();
+    for (j, shared) in shared_messages.iter().enumerate() {
+        assert_eq!(shared.s.len(), w[j] as usize);
+        assert!(reveal.verify(&params, &shared.C));
+        // each share matches the unblinded commitment
+        let C = reveal.commitment();
+        for (k, s_k) in shared.s.iter().enumerate() {
+            let x = omega.pow([(share_indexes[j] + k) as u64]);
+            assert_eq!(
+                g.mul(*s_k).into_affine(),
+                ferveo::poly::public_share(&C, x)
+            );
+        }
+    }
+    assert_eq!(reveal.commitment()[0][0], g.mul(s).into_affine());
+
+    // a wrong reveal is detected
+    let mut wrong = reveal.clone();
+    wrong.r[0] = Scalar::rand(&mut rng);
+    assert!(!wrong.verify(&params, C));
+    /* a wrong reveal that is consistent with the commitment
+    is detected by the proof of knowledge */
+    let h = ferveo::poly::pedersen_generator();
+    wrong.F = ferveo::poly::unblind(C, &wrong.r, h)
+        .iter()
+        .map(|C_j| C_j[0])
+        .collect();
+    assert!(!wrong.verify(&params, C));
+    // a reveal for another dealing is rejected
+    let other = Params {
+        tau: 1,
+        ..params.clone()
+    };
+    assert!(!reveal.verify(&other, C));
+
+    // shares without blinding shares are rejected
+    let sends = scheme.dealer_share(Share { s }, &mut rng);
+    let send = Send {
+        a_hat: Vec::new(),
+        ..sends[0].clone()
+    };
+    assert!(!scheme.nodes[0].verify_share(&send));
+}
+
+#[test]
+/* test that each node outputs its shares when point-to-point messages
+are sealed, and that plaintext, redirected or forged messages are rejected */
+fn sealed_shared() {
+    use std::collections::VecDeque;
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let t = 2;
+    let w = vec![3, 1, 2, 1, 1];
+    let n = w.len();
+    let dk: Vec<Scalar> = w.iter().map(|_| Scalar::rand(&mut rng)).collect();
+    let ek: Vec<_> =
+        dk.iter().map(|dk_j| ecies::encryption_key(*dk_j)).collect();
+    // there must be a signing key for each participant
+    assert_eq!(
+        Params::new(0, 0, 0, 0, t, w.clone(), Vec::new()).err(),
+        Some(ferveo::hybridvss::Error::PublicKeys(n as u32))
+    );
+    let params = random_dealer(0, t, w.clone(), &mut rng);
+    // there must be an encryption key for each participant
+    assert_eq!(
+        params.clone().with_encryption_keys(ek[1..].to_vec()).err(),
+        Some(ferveo::hybridvss::Error::EncryptionKeys(n as u32))
+    );
+    let params = params.with_encryption_keys(ek).unwrap();
+    let mut nodes: Vec<Context> = signing_keys(n as u32)
+        .into_iter()
+        .zip(dk.iter())
+        .enumerate()
+        .map(|(i, (sk, dk_i))| {
+            Context::init_sealed(params.clone(), i as u32, sk, *dk_i)
+        })
+        .collect();
+    let s = Scalar::rand(&mut rng);
+
+    // queue of (sender, event) pairs
+    let d = params.d;
+    let sends = nodes[d as usize].deal_sealed(&mut rng, Share { s });
+    let mut queue: VecDeque<(u32, Outgoing)> =
+        sends.iter().cloned().map(|out| (d, out)).collect();
+    let mut outputs: Vec<Option<Shared>> = vec![None; n];
+    while let Some((sender, out)) = queue.pop_front() {
+        match out {
+            Outgoing::To(j, msg) => {
+                assert!(matches!(msg, Message::Sealed(_)));
+                let responses =
+                    nodes[j as usize].handle_sealed(sender, msg, &mut rng);
+                queue.extend(responses.into_iter().map(|out| (j, out)))
+            }
+            Outgoing::Output(shared) => {
+                assert!(outputs[sender as usize].is_none());
+                outputs[sender as usize] = Some(shared)
+            }
+            Outgoing::Broadcast(_) => panic!("unexpected broadcast"),
+        }
+    }
+    for (j, output) in outputs.iter().enumerate() {
+        assert_eq!(output.as_ref().unwrap().s.len(), w[j] as usize);
+    }
+
+    // a sealed message cannot be opened by another node
+    let (j, sealed) = match &sends[0] {
+        Outgoing::To(j, Message::Sealed(sealed)) => (*j, sealed.clone()),
+        _ => panic!("expected a sealed message"),
+    };
+    assert!(nodes[j as usize].open(d, &sealed).is_some());
+    let other = (j + 1) % n as u32;
+    assert!(nodes[other as usize].open(d, &sealed).is_none());
+    // nor attributed to another sender
+    assert!(nodes[j as usize].open(other, &sealed).is_none());
+    // nor forged by a node without the sender's signing key
+    let mut forged = sealed.clone();
+    let help = Message::Help(Help {
+        session: params.session_id(),
+    });
+    forged.sig = nodes[other as usize]
+        .seal(vec![Outgoing::To(j, help)], &mut rng)
+        .into_iter()
+        .find_map(|out| match out {
+            Outgoing::To(_, Message::Sealed(sealed)) => Some(sealed.sig),
+            _ => None,
+        })
+        .unwrap();
+    assert!(nodes[j as usize].open(d, &forged).is_none());
+
+    // plaintext send, echo and ready messages are rejected
+    let mut scheme = Scheme::new(params);
+    let send = scheme.dealer_share(Share { s }, &mut rng).remove(0);
+    let echo = scheme.nodes[0].send(send.clone()).unwrap().remove(0);
+    let msg = Message::Send(send);
+    assert!(nodes[0].handle_sealed(d, msg.clone(), &mut rng).is_empty());
+    assert!(nodes[0].handle(d, msg).is_empty());
+    assert!(nodes[0].handle(0, Message::Echo(echo)).is_empty());
+}
@@ -0,0 +1,222 @@
+#![allow(clippy::many_single_char_names)]
+#![allow(non_snake_case)]
+
+use ark_bls12_381::{Fr, G2Affine, G2Projective};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{Field, UniformRand};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use bls12_381::Scalar as BlsScalar;
+use ferveo::fft;
+use ferveo::hybriddkg::SessionId;
+use ferveo::pvss::*;
+use num::{One, Zero};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+type Scalar = Fr;
+
+const SESSION: SessionId = SessionId { epoch: 1, tau: 0 };
+
+// Participant parameters and keys
+struct Setup {
+    params: Params,
+    dk: Vec<Scalar>,    // the decryption key of each participant
+    sk: Vec<BlsScalar>, // the signing key of each participant
+}
+
+// generate parameters and keys for participants with weights `w`
+fn setup(t: u32, w: Vec<u32>, rng: &mut StdRng) -> Setup {
+    let dk: Vec<Scalar> = w.iter().map(|_| Scalar::rand(rng)).collect();
+    let ek = dk.iter().map(|dk_j| encryption_key(*dk_j)).collect();
+    let sk: Vec<BlsScalar> = w
+        .iter()
+        .map(|_| <BlsScalar as ff::Field>::random(&mut *rng))
+        .collect();
+    let pubkeys = sk.iter().map(ferveo::bls::pubkey).collect();
+    let params = Params::new(SESSION, t, w, ek, pubkeys).unwrap();
+    Setup { params, dk, sk }
+}
+
+// deal a random secret from each dealer, returning the secrets
+fn deal_each(
+    Setup { params, sk, .. }: &Setup,
+    dealers: &[u32],
+    rng: &mut StdRng,
+) -> (Vec<Scalar>, Vec<Transcript>) {
+    dealers
+        .iter()
+        .map(|d| {
+            let s = Scalar::rand(rng);
+            (s, deal(params, *d, s, sk[*d as usize], rng).unwrap())
+        })
+        .unzip()
+}
+
+// the lagrange basis polynomial for `xs[i]`, evaluated at zero
+fn lagrange_at_zero(xs: &[Scalar], i: usize) -> Scalar {
+    xs.iter()
+        .enumerate()
+        .filter(|(j, _)| *j != i)
+        .fold(Scalar::one(), |acc, (_, x_j)| acc * *x_j / (*x_j - xs[i]))
+}
+
+/* aggregated dealings verify,
+and the decrypted shares interpolate to the sum of the secrets */
+#[test]
+fn aggregate_decrypt() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let keys = setup(3, vec![1, 2, 1, 3], rng);
+    let (params, dk) = (&keys.params, &keys.dk);
+    let (secrets, transcripts) = deal_each(&keys, &[0, 2, 3], rng);
+    assert!(transcripts
+        .iter()
+        .all(|transcript| transcript.verify(params)));
+    let transcript = aggregate(&transcripts).unwrap();
+    assert!(transcript.verify(params));
+    assert_eq!(
+        transcript.dealers.keys().cloned().collect::<Vec<_>>(),
+        [0, 2, 3]
+    );
+
+    let s: Scalar = secrets.iter().sum();
+    let g = ark_bls12_381::G1Affine::prime_subgroup_generator();
+    assert_eq!(transcript.public_key(), g.mul(s).into_affine());
+
+    // interpolate in the exponent from the shares of participants 1 and 3
+    let omega = fft::domain(params.total_weight() as usize).group_gen;
+    let share_indexes = params.share_indexes();
+    let points: Vec<(Scalar, G2Affine)> = [1u32, 3]
+        .iter()
+        .flat_map(|j| {
+            let start = share_indexes[*j as usize];
+            transcript
+                .decrypt(params, *j, dk[*j as usize])
+                .unwrap()
+                .into_iter()
+                .enumerate()
+                .map(move |(k, Z_k)| (omega.pow([(start + k) as u64]), Z_k))
+        })
+        .take(params.t as usize + 1)
+        .collect();
+    let xs: Vec<Scalar> = points.iter().map(|(x, _)| *x).collect();
+    let h_s = points
+        .iter()
+        .enumerate()
+        .fold(G2Projective::zero(), |acc, (i, (_, Z_i))| {
+            acc + Z_i.mul(lagrange_at_zero(&xs, i))
+        });
+    let h = G2Affine::prime_subgroup_generator();
+    assert_eq!(h_s.into_affine(), h.mul(s).into_affine());
+}
+
+// tampered transcripts and transcripts for other sessions fail to verify
+#[test]
+fn verify_invalid() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let keys = setup(2, vec![2, 1, 1, 1], rng);
+    let params = &keys.params;
+    let (_, transcripts) = deal_each(&keys, &[0, 1], rng);
+    let transcript = aggregate(&transcripts).unwrap();
+
+    let mut tampered = transcript.clone();
+    tampered.Y[1] = tampered.Y[1] + tampered.Y[2];
+    assert!(!tampered.verify(params));
+
+    let mut tampered = transcript.clone();
+    tampered.dealers.remove(&1);
+    assert!(!tampered.verify(params));
+
+    let other = SessionId { epoch: 1, tau: 1 };
+    let other_params = Params {
+        tau: other.tau,
+        ..params.clone()
+    };
+    assert!(!transcript.verify(&other_params));
+
+    // a dealing attributed to dealer 1 must be signed by dealer 1
+    let s = Scalar::rand(rng);
+    let forged = deal(params, 1, s, keys.sk[0], rng).unwrap();
+    assert!(!forged.verify(params));
+}
+
+// aggregation rejects overlapping dealers and mismatched sessions
+#[test]
+fn aggregate_invalid() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let keys = setup(2, vec![1, 1, 1, 1], rng);
+    let params = &keys.params;
+    let (_, transcripts) = deal_each(&keys, &[0, 1, 1], rng);
+    assert_eq!(aggregate(&transcripts), Err(Error::DuplicateDealer(1)));
+    assert_eq!(aggregate(&[]), Err(Error::NoTranscripts));
+
+    let other_params = Params {
+        epoch: 2,
+        ..params.clone()
+    };
+    let s = Scalar::rand(rng);
+    let other_transcript = deal(&other_params, 2, s, keys.sk[2], rng).unwrap();
+    assert_eq!(
+        aggregate(&[transcripts[0].clone(), other_transcript]),
+        Err(Error::Session)
+    );
+}
+
+// transcripts survive a serialization round trip
+#[test]
+fn serialize_transcript() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let keys = setup(1, vec![1, 2], rng);
+    let params = &keys.params;
+    let (_, transcripts) = deal_each(&keys, &[0, 1], rng);
+    let transcript = aggregate(&transcripts).unwrap();
+    let mut bytes = Vec::new();
+    transcript.serialize(&mut bytes).unwrap();
+    let decoded = Transcript::deserialize(&bytes[..]).unwrap();
+    assert_eq!(decoded, transcript);
+    assert!(decoded.verify(params));
+}
+
+// dealing and decryption fail without a key for each participant
+#[test]
+fn missing_keys() {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let keys = setup(1, vec![1, 2, 1], rng);
+    let params = &keys.params;
+    let (n, s, sk) = (params.n(), Scalar::rand(rng), keys.sk[0]);
+
+    let Params { ek, pubkeys, .. } = params.clone();
+    assert_eq!(
+        Params::new(SESSION, 1, params.w.clone(), ek[1..].to_vec(), pubkeys)
+            .err(),
+        Some(Error::EncryptionKeys(n))
+    );
+    let short_ek = Params {
+        ek: ek[1..].to_vec(),
+        ..params.clone()
+    };
+    assert_eq!(
+        deal(&short_ek, 0, s, sk, rng),
+        Err(Error::EncryptionKeys(n))
+    );
+    let short_pubkeys = Params {
+        pubkeys: Vec::new(),
+        ..params.clone()
+    };
+    assert_eq!(
+        deal(&short_pubkeys, 0, s, sk, rng),
+        Err(Error::PublicKeys(n))
+    );
+    assert_eq!(deal(params, n, s, sk, rng), Err(Error::Dealer(n)));
+    // parameters with missing keys do not decode
+    let mut bytes = Vec::new();
+    short_ek.serialize(&mut bytes).unwrap();
+    assert!(Params::deserialize(&bytes[..]).is_err());
+
+    let transcript = deal(params, 0, s, sk, rng).unwrap();
+    assert!(transcript.decrypt(params, 0, keys.dk[0]).is_some());
+    assert!(transcript.decrypt(params, n, keys.dk[0]).is_none());
+    assert!(transcript.decrypt(params, 0, Scalar::zero()).is_none());
+    let mut truncated = transcript;
+    truncated.Y.pop();
+    assert!(truncated.decrypt(params, n - 1, keys.dk[0]).is_none());
+}
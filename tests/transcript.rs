@@ -0,0 +1,36 @@
+use ferveo::transcript::Transcript;
+use std::collections::BTreeSet;
+
+// hash a transcript with the given items
+fn hash(protocol: &[u8], items: &[(&[u8], &[u8])]) -> [u8; 32] {
+    let mut transcript = Transcript::new(protocol);
+    items
+        .iter()
+        .for_each(|(label, bytes)| transcript.append(label, bytes));
+    transcript.finalize()
+}
+
+// items are framed, so moving bytes between items changes the hash
+#[test]
+fn framing() {
+    let lhs = hash(b"protocol", &[(b"a", b"bc"), (b"d", b"")]);
+    assert_eq!(lhs, hash(b"protocol", &[(b"a", b"bc"), (b"d", b"")]));
+    assert_ne!(lhs, hash(b"protocol", &[(b"a", b"b"), (b"d", b"c")]));
+    assert_ne!(lhs, hash(b"protocol", &[(b"ab", b"c"), (b"d", b"")]));
+    assert_ne!(lhs, hash(b"protocol", &[(b"a", b"bc")]));
+    assert_ne!(lhs, hash(b"other", &[(b"a", b"bc"), (b"d", b"")]));
+}
+
+// encoded sets are length-prefixed, so adjacent sets cannot be merged
+#[test]
+fn encoded_sets() {
+    let hash_sets = |lhs: &[u32], rhs: &[u32]| {
+        let mut transcript = Transcript::new(b"protocol");
+        transcript
+            .append_encode(b"Q", &lhs.iter().cloned().collect::<BTreeSet<_>>());
+        transcript
+            .append_encode(b"Q", &rhs.iter().cloned().collect::<BTreeSet<_>>());
+        transcript.finalize()
+    };
+    assert_ne!(hash_sets(&[0, 1], &[2]), hash_sets(&[0], &[1, 2]));
+}